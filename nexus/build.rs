@@ -1,8 +1,6 @@
-use resvg::{tiny_skia, usvg};
 use std::{
     fs,
     path::{Path, PathBuf},
-    process::Command,
 };
 
 use embed_manifest::{
@@ -10,18 +8,22 @@ use embed_manifest::{
     manifest::{DpiAwareness::*, MaxVersionTested::*, SupportedOS::*},
 };
 
+#[path = "src/icon.rs"]
+mod icon;
+
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
 
     let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
 
-    gen_manifest();
-    check_i18n();
-    let ico = generate_ico(&out_dir);
-    embed_icon(ico.to_str().unwrap());
-    strip_commandline();
+    if std::env::var("CARGO_CFG_WINDOWS").is_ok() {
+        gen_manifest();
+        generate_icons(&out_dir);
+        embed_icon(&out_dir, "nexus.logo");
+        strip_commandline();
 
-    println!("cargo:rerun-if-changed={}", ico.to_string_lossy());
+        println!("cargo:rerun-if-changed=assets");
+    }
 }
 
 fn gen_manifest() {
@@ -33,58 +35,44 @@ fn gen_manifest() {
     embed_manifest(manifest).expect("Fail to embed manifest");
 }
 
-fn check_i18n() {
-    let todo_file = "i18n/TODO.yml";
-
-    fs::remove_file(todo_file).ok();
-    Command::new("cargo").args(&["i18n"]).status().ok();
-    PathBuf::from(todo_file).exists().then(|| {
-        panic!("Please resolve all TODOs in {todo_file} before building.");
-    });
-
-    println!("cargo:rerun-if-changed=config.toml");
-    println!("cargo:rerun-if-changed={todo_file}");
-}
-
-fn svg2bitmap<S: AsRef<str>>(svg: S) -> (Vec<u8>, u32, u32) {
-    let tree = usvg::Tree::from_str(svg.as_ref(), &Default::default()).unwrap();
-
-    let (w, h) = {
-        let size = tree.size();
-        (size.width() as u32, size.height() as u32)
-    };
-
-    let mut pixmap = tiny_skia::Pixmap::new(w, h).unwrap();
-
-    resvg::render(&tree, Default::default(), &mut pixmap.as_mut());
-
-    (pixmap.data().to_vec(), w, h)
+/// Render every SVG in `assets/` into `<name>.<theme>.ico` in `dir`
+fn generate_icons<P: AsRef<Path>>(dir: P) {
+    for entry in fs::read_dir("assets").unwrap() {
+        let svg_path = entry.unwrap().path();
+        if svg_path.extension().is_none_or(|ext| ext != "svg") {
+            continue;
+        }
+        let svg = fs::read_to_string(&svg_path).unwrap();
+
+        for theme in icon::TaskbarTheme::ALL {
+            let ico = icon::render_ico(&theme.apply(&svg), &icon::ICON_SIZES)
+                .unwrap_or_else(|e| panic!("Failed to render {}: {e:?}", svg_path.display()));
+            fs::write(icon_path(&dir, svg_path.file_stem().unwrap(), theme), ico).unwrap();
+        }
+    }
 }
 
-fn generate_ico<P: AsRef<Path>>(dir: P) -> PathBuf {
-    let svg_path = PathBuf::from("assets/nexus.logo.svg");
-    let svg = fs::read_to_string(&svg_path).unwrap();
-    let (data, w, h) = svg2bitmap(svg);
-    let image_buf = image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(w, h, data).unwrap();
-
-    let icon_path = dir
-        .as_ref()
-        .join(svg_path.file_name().unwrap())
-        .with_extension("ico");
-
-    image_buf.save(&icon_path).unwrap();
-
-    icon_path
+fn icon_path<P: AsRef<Path>, S: AsRef<std::ffi::OsStr>>(
+    dir: P,
+    stem: S,
+    theme: icon::TaskbarTheme,
+) -> PathBuf {
+    let mut name = stem.as_ref().to_os_string();
+    name.push(format!(".{}.ico", theme.name()));
+    dir.as_ref().join(name)
 }
 
 include!("src/resource.rs");
-fn embed_icon(icon_path: &str) {
+fn embed_icon<P: AsRef<Path>>(dir: P, name: &str) {
+    let dark = icon_path(&dir, name, icon::TaskbarTheme::Dark);
+    let light = icon_path(&dir, name, icon::TaskbarTheme::Light);
+
     winres::WindowsResource::new()
-        .set_icon(icon_path)
-        .set_icon_with_id(icon_path, &APP_ICON.to_string())
+        .set_icon(dark.to_str().unwrap())
+        .set_icon_with_id(dark.to_str().unwrap(), &APP_ICON.to_string())
+        .set_icon_with_id(light.to_str().unwrap(), &APP_ICON_LIGHT_TASKBAR.to_string())
         .compile()
         .expect("Failed to embed icon");
-    println!("cargo:rerun-if-changed=logo.ico");
 }
 
 fn strip_commandline() {
@@ -0,0 +1,176 @@
+use crate::app::backend::DeviceInfo;
+use std::collections::BTreeMap;
+
+/// 设备显示名称的唯一来源：配置中的别名优先，其次是蓝牙名称，最后是设备ID
+///
+/// 别名的键可以是设备ID或蓝牙名称，同名设备需要按ID区分。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceNames {
+    aliases: BTreeMap<String, String>,
+}
+
+impl DeviceNames {
+    pub fn new(aliases: BTreeMap<String, String>) -> Self {
+        Self { aliases }
+    }
+
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    /// Alias set for the id, or else for the case-insensitive Bluetooth name
+    pub fn alias(&self, device_id: &str, name: &str) -> Option<&str> {
+        let key = self.alias_key(device_id, name)?;
+        Some(self.aliases[key].as_str()).filter(|alias| !alias.is_empty())
+    }
+
+    /// Key of the alias which applies to the device
+    pub fn alias_key(&self, device_id: &str, name: &str) -> Option<&str> {
+        if let Some((key, _)) = self.aliases.get_key_value(device_id) {
+            return Some(key);
+        }
+        self.aliases
+            .keys()
+            .find(|key| !name.is_empty() && key.eq_ignore_ascii_case(name))
+            .map(String::as_str)
+    }
+
+    /// Name to show for the device
+    pub fn display_name(&self, device_id: &str, name: &str) -> String {
+        match (self.alias(device_id, name), name) {
+            (Some(alias), _) => alias.to_string(),
+            (None, "") => device_id.to_string(),
+            (None, name) => name.to_string(),
+        }
+    }
+
+    pub fn device_name(&self, device: &DeviceInfo) -> String {
+        self.display_name(&device.id, &device.name)
+    }
+
+    /// Whether `query` names the device by id, alias or Bluetooth name, ignoring case for names
+    pub fn matches(&self, query: &str, device_id: &str, name: &str) -> bool {
+        query == device_id
+            || (!name.is_empty() && query.eq_ignore_ascii_case(name))
+            || self
+                .alias(device_id, name)
+                .is_some_and(|alias| query.eq_ignore_ascii_case(alias))
+    }
+}
+
+/// Key to store an alias for `query` under, the id of the device it names
+///
+/// A Bluetooth name shared by several devices is ambiguous, their ids are listed in the error.
+/// Unknown devices keep `query` as the key so they can be named before they are paired.
+pub fn resolve_alias_key(
+    query: &str,
+    devices: &[DeviceInfo],
+    names: &DeviceNames,
+) -> anyhow::Result<String> {
+    if devices.iter().any(|device| device.id == query) {
+        return Ok(query.to_string());
+    }
+
+    let mut matching = devices
+        .iter()
+        .filter(|device| names.matches(query, &device.id, &device.name))
+        .collect::<Vec<_>>();
+    matching.dedup_by(|a, b| a.id == b.id);
+    match matching.as_slice() {
+        [] => Ok(query.to_string()),
+        [device] => Ok(device.id.clone()),
+        devices => anyhow::bail!(
+            "{query:?} names {} devices, use one of their ids:\n{}",
+            devices.len(),
+            devices
+                .iter()
+                .map(|device| format!("  {}  {}", device.id, device.name))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(id: &str, name: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn names(aliases: &[(&str, &str)]) -> DeviceNames {
+        DeviceNames::new(
+            aliases
+                .iter()
+                .map(|(key, alias)| (key.to_string(), alias.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn ids_take_precedence_over_names() {
+        let names = names(&[("id-2", "Work phone"), ("pixel", "Phone")]);
+        assert_eq!(names.display_name("id-1", "Pixel"), "Phone");
+        assert_eq!(names.display_name("id-2", "Pixel"), "Work phone");
+        assert_eq!(names.alias_key("id-1", "PIXEL"), Some("pixel"));
+        assert_eq!(names.alias_key("id-2", "Pixel"), Some("id-2"));
+    }
+
+    #[test]
+    fn falls_back_to_the_name_then_the_id() {
+        let names = names(&[("muted", "")]);
+        assert_eq!(names.display_name("id-1", "Tablet"), "Tablet");
+        assert_eq!(names.display_name("id-1", ""), "id-1");
+        // 空别名不生效，但仍是该设备的别名键
+        assert_eq!(names.alias("id-2", "Muted"), None);
+        assert_eq!(names.alias_key("id-2", "Muted"), Some("muted"));
+        assert_eq!(names.device_name(&device("id-2", "Muted")), "Muted");
+        // 没有名称的设备不匹配任何名称键
+        assert_eq!(names.alias_key("id-3", ""), None);
+    }
+
+    #[test]
+    fn matches_ids_names_and_aliases() {
+        let names = names(&[("id-1", "Phone")]);
+        assert!(names.matches("id-1", "id-1", "Pixel"));
+        assert!(!names.matches("ID-1", "id-1", "Pixel"));
+        assert!(names.matches("pixel", "id-1", "Pixel"));
+        assert!(names.matches("PHONE", "id-1", "Pixel"));
+        assert!(!names.matches("", "id-2", ""));
+    }
+
+    #[test]
+    fn resolves_alias_keys() {
+        let devices = [
+            device("id-1", "Pixel"),
+            device("id-2", "Buds"),
+            device("id-3", "Buds"),
+        ];
+        let names = names(&[("id-1", "Phone")]);
+
+        assert_eq!(resolve_alias_key("id-2", &devices, &names).unwrap(), "id-2");
+        assert_eq!(
+            resolve_alias_key("pixel", &devices, &names).unwrap(),
+            "id-1"
+        );
+        assert_eq!(
+            resolve_alias_key("phone", &devices, &names).unwrap(),
+            "id-1"
+        );
+        // 还没配对的设备按输入保存
+        assert_eq!(
+            resolve_alias_key("Laptop", &devices, &names).unwrap(),
+            "Laptop"
+        );
+
+        let error = resolve_alias_key("buds", &devices, &names)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("\"buds\" names 2 devices"), "{error}");
+        assert!(error.contains("  id-2  Buds\n  id-3  Buds"), "{error}");
+    }
+}
@@ -1,17 +1,39 @@
 use crate::{
     app::{
+        autostart::AutostartCommand,
+        backend::AudioBackend,
         config::AppConfig,
-        notify_icon::{MenuStrings, NotifyIcon},
+        history::{HistoryRecorder, HistoryStore, RecordingBackend},
+        hooks::{HookRunner, HookedBackend},
+        notify_icon::NotifyIcon,
+        resume::{ResumeReconnect, SystemEvent},
+        schedule::SystemClock,
+        script::{ScriptHost, ScriptedBackend},
+        strings::UiStrings,
+        winrt_backend::WinRtBackend,
     },
     internal::*,
+    service::{ServiceBackend, default_endpoint, serve_http},
 };
 use anyhow::Context;
-use rust_i18n::t;
-use std::{cell::LazyCell, sync::Arc};
+use std::{
+    cell::LazyCell,
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
 use tracing::log;
 use windows::{
     Win32::{
-        Foundation::*, Graphics::Gdi::UpdateWindow, System::LibraryLoader::GetModuleHandleW,
+        Foundation::*,
+        Graphics::Gdi::UpdateWindow,
+        System::{
+            LibraryLoader::GetModuleHandleW,
+            RemoteDesktop::{
+                NOTIFY_FOR_THIS_SESSION, WTSRegisterSessionNotification,
+                WTSUnRegisterSessionNotification,
+            },
+        },
         UI::WindowsAndMessaging::*,
     },
     core::*,
@@ -20,7 +42,15 @@ use windows::{
 pub struct Application {
     window: HWND,
     config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    autostart: AutostartCommand,
     notify_icon: Option<NotifyIcon>,
+    /// 用户的自动化脚本，通知显示在托盘图标上
+    script: Option<Arc<ScriptHost>>,
+    /// 等待在托盘图标上显示的脚本通知，由 `WM_SCRIPT_NOTIFY` 取出
+    script_notifications: Arc<Mutex<VecDeque<String>>>,
+    /// 电源与会话通知，交给 [`ResumeReconnect`] 处理
+    system_events: Option<mpsc::UnboundedSender<SystemEvent>>,
 }
 
 impl Application {
@@ -28,32 +58,125 @@ impl Application {
     const WINDOW_NAME: LazyCell<&'static str> = LazyCell::new(|| "LitAudioSinkNexusHiddenWindow");
     const WM_NOTIFYICON: u32 = WM_USER + 1;
     const WM_SHOW_PICKER: u32 = WM_USER + 2;
+    const WM_CONNECTIONS_CHANGED: u32 = WM_USER + 3;
+    /// 脚本通知已放入 `script_notifications`
+    const WM_SCRIPT_NOTIFY: u32 = WM_USER + 4;
+    /// `WPARAM` 非零表示等待枚举超时
+    const WM_DEVICES_ENUMERATED: u32 = WM_USER + 5;
     const WM_TASKBAR_CREATED: LazyCell<u32> =
         LazyCell::new(|| unsafe { RegisterWindowMessageW(w!("TaskbarCreated")) });
 
-    pub fn run(config: AppConfig) -> anyhow::Result<Self> {
-        let window = HWND::default();
+    pub fn run(
+        config: AppConfig,
+        autostart: AutostartCommand,
+        history: Option<HistoryStore>,
+    ) -> anyhow::Result<()> {
+        // 已有实例时只激活它，不能再连接一次设备或重复运行脚本
+        if let Ok(wnd) = Self::find_exists() {
+            log::info!("Found existing application window, activating...");
+            unsafe { PostMessageW(Some(wnd), Self::WM_SHOW_PICKER, WPARAM(0), LPARAM(0)) }?;
+            return Ok(());
+        }
+
+        let config = Arc::new(config);
+        let backend = Self::create_backend(&config, history);
+        let (backend, script) = Self::attach_script(&config, backend);
         let app = Self {
-            window,
-            config: Arc::new(config),
+            window: HWND::default(),
+            backend,
+            config,
+            autostart,
             notify_icon: None,
+            script,
+            script_notifications: Default::default(),
+            system_events: None,
         };
+        app.main_loop()
+    }
+}
 
-        match app.find_exists() {
-            Ok(wnd) => {
-                log::info!("Found existing application window, activating...");
-                unsafe { PostMessageW(Some(wnd), Self::WM_SHOW_PICKER, WPARAM(0), LPARAM(0)) }?;
+impl Application {
+    /// 服务进程在运行时作为其客户端，否则在本进程中连接
+    fn create_backend(
+        config: &Arc<AppConfig>,
+        history: Option<HistoryStore>,
+    ) -> Arc<dyn AudioBackend> {
+        let backend = Self::connect_backend();
+        let backend: Arc<dyn AudioBackend> = match history {
+            Some(store) => {
+                log::info!("Recording connection history to {:?}", store.path());
+                Arc::new(RecordingBackend::new(backend, HistoryRecorder::new(store)))
             }
-            Err(_) => {
-                app.main_loop()?;
+            None => backend,
+        };
+
+        let hooks = config.hooks();
+        if hooks.is_empty() {
+            return backend;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                log::info!("Running hooks on connection changes");
+                let runner = HookRunner::new(hooks, runtime);
+                Arc::new(HookedBackend::new(backend, config.clone(), runner))
+            }
+            Err(e) => {
+                log::warn!("Hooks need a tokio runtime: {:?}", e);
+                backend
             }
         }
+    }
+
+    /// Load the script configured in `[script]` and let it check the connections of `backend`
+    fn attach_script(
+        config: &Arc<AppConfig>,
+        backend: Arc<dyn AudioBackend>,
+    ) -> (Arc<dyn AudioBackend>, Option<Arc<ScriptHost>>) {
+        let settings = config.script();
+        let Some(path) = settings.path(&config.file_path) else {
+            return (backend, None);
+        };
+        let runtime = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                log::warn!("Scripts need a tokio runtime: {:?}", e);
+                return (backend, None);
+            }
+        };
+        let host = match ScriptHost::load(
+            &path,
+            &settings,
+            config.clone(),
+            backend.clone(),
+            Arc::new(SystemClock),
+        ) {
+            Ok(host) => Arc::new(host),
+            Err(e) => {
+                log::error!("{:#}", e);
+                return (backend, None);
+            }
+        };
 
-        Ok(app)
+        log::info!("Running script {:?}", path);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let scripted = ScriptedBackend::new(backend, host.clone(), sender);
+        runtime.spawn(host.clone().run(receiver, settings.timer()));
+        (Arc::new(scripted), Some(host))
+    }
+
+    fn connect_backend() -> Arc<dyn AudioBackend> {
+        match ServiceBackend::connect(&default_endpoint()) {
+            Ok(backend) => {
+                log::info!("Using connections of the background service");
+                Arc::new(backend)
+            }
+            Err(e) => {
+                log::debug!("Background service not available: {:?}", e);
+                Arc::new(WinRtBackend::new())
+            }
+        }
     }
-}
 
-impl Application {
     fn main_loop(&self) -> anyhow::Result<()> {
         let module = unsafe { GetModuleHandleW(None) }
             .context("Fail to get HMODULE handle for the current application")?;
@@ -112,7 +235,51 @@ impl Application {
         Ok(())
     }
 
-    fn find_exists(&self) -> anyhow::Result<HWND> {
+    /// Follow suspend, resume and session lock to restore the connections afterwards
+    fn start_resume_reconnect(&mut self) -> anyhow::Result<()> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .context("Reconnecting after resume needs a tokio runtime")?;
+        unsafe { WTSRegisterSessionNotification(self.window, NOTIFY_FOR_THIS_SESSION) }
+            .context("Failed to register session notification")?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let resume = ResumeReconnect::new(
+            self.config.clone(),
+            self.backend.clone(),
+            self.notify_icon.as_ref().unwrap().control(),
+        );
+        runtime.spawn(resume.run(receiver));
+        self.system_events = Some(sender);
+        Ok(())
+    }
+
+    /// Serve the HTTP API when `[api]` enables it
+    fn start_http_api(&self) -> anyhow::Result<()> {
+        if !self.config.api().enabled {
+            return Ok(());
+        }
+        let runtime =
+            tokio::runtime::Handle::try_current().context("The HTTP API needs a tokio runtime")?;
+        runtime.spawn({
+            let config = self.config.clone();
+            let backend = self.backend.clone();
+            let control = self.notify_icon.as_ref().unwrap().control();
+            async move {
+                if let Err(e) = serve_http(config, backend, control).await {
+                    log::error!("HTTP API stopped: {:?}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn send_system_event(&self, event: SystemEvent) {
+        if let Some(sender) = &self.system_events {
+            let _ = sender.send(event);
+        }
+    }
+
+    fn find_exists() -> anyhow::Result<HWND> {
         let wnd_class = HSTRING::from(*Self::CLASS_NAME);
         let wnd_name = HSTRING::from(*Self::WINDOW_NAME);
         let wnd = unsafe {
@@ -128,6 +295,10 @@ impl Application {
     fn handle_message(&self, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
         match message {
             WM_DESTROY => {
+                if self.system_events.is_some() {
+                    unsafe { WTSUnRegisterSessionNotification(self.window) }
+                        .warn("Failed to unregister session notification");
+                }
                 self.notify_icon.as_ref().unwrap().delete().unwrap();
                 unsafe { PostQuitMessage(0) };
             }
@@ -138,6 +309,31 @@ impl Application {
                     .handle_message(lparam.0 as u32)
                     .unwrap();
             }
+            Self::WM_CONNECTIONS_CHANGED => {
+                self.notify_icon
+                    .as_ref()
+                    .unwrap()
+                    .refresh_tooltip()
+                    .warn("Fail to update tooltip");
+            }
+            Self::WM_DEVICES_ENUMERATED => {
+                self.notify_icon
+                    .as_ref()
+                    .unwrap()
+                    .devices_enumerated(wparam.0 != 0)
+                    .warn("Fail to finish device enumeration");
+            }
+            Self::WM_SCRIPT_NOTIFY => {
+                // 消息不带数据，其他进程发送的同样只会取出已有的通知
+                let texts = std::mem::take(&mut *self.script_notifications.lock().unwrap());
+                for text in texts {
+                    self.notify_icon
+                        .as_ref()
+                        .unwrap()
+                        .show_notification(&text)
+                        .warn("Fail to show script notification");
+                }
+            }
             Self::WM_SHOW_PICKER => {
                 let x = unsafe { GetSystemMetrics(SM_CXSCREEN) };
                 let y = unsafe { GetSystemMetrics(SM_CYSCREEN) };
@@ -154,6 +350,30 @@ impl Application {
                     .handle_command((wparam.0 & 0xffff) as u32)
                     .unwrap();
             }
+            WM_POWERBROADCAST => match wparam.0 as u32 {
+                PBT_APMSUSPEND => self.send_system_event(SystemEvent::Suspend),
+                // 无论是否由用户唤醒都会收到，之后可能还有 PBT_APMRESUMESUSPEND
+                PBT_APMRESUMEAUTOMATIC => self.send_system_event(SystemEvent::Resume),
+                _ => {}
+            },
+            WM_WTSSESSION_CHANGE => match wparam.0 as u32 {
+                WTS_SESSION_LOCK => self.send_system_event(SystemEvent::SessionLocked),
+                WTS_SESSION_UNLOCK => self.send_system_event(SystemEvent::SessionUnlocked),
+                _ => {}
+            },
+            WM_SETTINGCHANGE => {
+                // 切换深浅色主题时 lParam 为 "ImmersiveColorSet"
+                let area = PCWSTR::from_raw(lparam.0 as *const u16);
+                if !area.is_null()
+                    && unsafe { area.to_string() }.is_ok_and(|area| area == "ImmersiveColorSet")
+                {
+                    self.notify_icon
+                        .as_ref()
+                        .unwrap()
+                        .reload_icon()
+                        .warn("Failed to reload tray icon");
+                }
+            }
             msg if msg == *Self::WM_TASKBAR_CREATED => {
                 // when explorer.exe restarts, the taskbar is recreated, need to re-add the notify icon
                 log::debug!("Taskbar recreated, re-adding notify icon");
@@ -189,18 +409,55 @@ impl Application {
                         window,
                         (*this).config.clone(),
                         Self::WM_NOTIFYICON,
-                        MenuStrings {
-                            bluetooth_list: t!("notify_icon.bluetooth_list").to_string(),
-                            connection_list: t!("notify_icon.connection_list").to_string(),
-                            auto_connect: t!("notify_icon.auto_connect").to_string(),
-                            exit: t!("notify_icon.exit").to_string(),
-                            ..Default::default()
-                        },
+                        Self::WM_DEVICES_ENUMERATED,
+                        UiStrings::localized(&rust_i18n::locale()),
+                        (*this).backend.clone(),
+                        (*this).autostart.clone(),
                     )
                     .unwrap();
 
                     notify_icon.add().unwrap();
                     (*this).notify_icon = Some(notify_icon);
+
+                    // 连接事件来自其他线程，交给窗口线程更新提示
+                    (*this).backend.subscribe(Arc::new({
+                        let window = WndHandle::new(window);
+                        move |_| {
+                            unsafe {
+                                PostMessageW(
+                                    Some(window.hwnd()),
+                                    Self::WM_CONNECTIONS_CHANGED,
+                                    WPARAM(0),
+                                    LPARAM(0),
+                                )
+                            }
+                            .warn("Fail to post connections changed");
+                        }
+                    }));
+                    if let Some(script) = &(*this).script {
+                        script.set_control((*this).notify_icon.as_ref().unwrap().control());
+                        let window = WndHandle::new(window);
+                        let notifications = (*this).script_notifications.clone();
+                        script.set_notifier(Arc::new(move |text| {
+                            notifications.lock().unwrap().push_back(text);
+                            let posted = unsafe {
+                                PostMessageW(
+                                    Some(window.hwnd()),
+                                    Self::WM_SCRIPT_NOTIFY,
+                                    WPARAM(0),
+                                    LPARAM(0),
+                                )
+                            };
+                            // 留在队列中的通知随下一条消息显示
+                            posted.warn("Fail to post script notification");
+                        }));
+                    }
+                    (*this)
+                        .start_resume_reconnect()
+                        .warn("Failed to start reconnecting after resume");
+                    (*this)
+                        .start_http_api()
+                        .warn("Failed to start the HTTP API");
                     SetWindowLongPtrW(window, GWLP_USERDATA, this as isize);
                 }
             } else {
@@ -0,0 +1,68 @@
+use crate::app::idle::IdleEvent;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+use tokio::sync::mpsc;
+use tracing::log;
+use windows::Win32::{
+    Media::Audio::{Endpoints::IAudioMeterInformation, *},
+    System::Com::*,
+};
+
+/// 定期读取默认输出设备的峰值，有声音时报告活动
+///
+/// 源设备的音频都从默认输出设备播放，峰值无法区分来自哪个设备。
+pub struct AudioActivityMonitor {
+    stopped: Arc<AtomicBool>,
+}
+
+impl AudioActivityMonitor {
+    const INTERVAL: Duration = Duration::from_secs(5);
+    /// 低于该峰值视为静音
+    const THRESHOLD: f32 = 1e-4;
+
+    pub fn start(sender: mpsc::UnboundedSender<IdleEvent>) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        thread::spawn({
+            let stopped = stopped.clone();
+            move || {
+                // 线程可能已经初始化为其他模式，失败不影响后续调用
+                let _ = unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) };
+
+                while !stopped.load(Ordering::Relaxed) && !sender.is_closed() {
+                    match Self::peak() {
+                        Ok(peak) if peak > Self::THRESHOLD => {
+                            let _ = sender.send(IdleEvent::Activity { device_id: None });
+                        }
+                        Ok(_) => {}
+                        Err(e) => log::debug!("Fail to read audio peak: {:?}", e),
+                    }
+                    thread::sleep(Self::INTERVAL);
+                }
+            }
+        });
+        Self { stopped }
+    }
+
+    /// The default output may change, it is looked up again on every read
+    fn peak() -> windows::core::Result<f32> {
+        unsafe {
+            let enumerator: IMMDeviceEnumerator =
+                CoCreateInstance(&MMDeviceEnumerator, None, CLSCTX_ALL)?;
+            let device = enumerator.GetDefaultAudioEndpoint(eRender, eConsole)?;
+            let meter: IAudioMeterInformation = device.Activate(CLSCTX_ALL, None)?;
+            meter.GetPeakValue()
+        }
+    }
+}
+
+impl Drop for AudioActivityMonitor {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
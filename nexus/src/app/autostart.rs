@@ -0,0 +1,191 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex, time::Duration};
+
+/// 对 `HKCU\...\CurrentVersion\Run` 中字符串值的抽象
+pub trait RunRegistry: Send + Sync {
+    fn get(&self, name: &str) -> anyhow::Result<Option<String>>;
+
+    fn set(&self, name: &str, value: &str) -> anyhow::Result<()>;
+
+    /// Delete the value, succeeds if it does not exist
+    fn delete(&self, name: &str) -> anyhow::Result<()>;
+}
+
+/// 内存中的 [`RunRegistry`]
+#[derive(Debug, Default)]
+pub struct InMemoryRunRegistry {
+    values: Mutex<HashMap<String, String>>,
+}
+
+impl RunRegistry for InMemoryRunRegistry {
+    fn get(&self, name: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.values.lock().unwrap().get(name).cloned())
+    }
+
+    fn set(&self, name: &str, value: &str) -> anyhow::Result<()> {
+        self.values
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> anyhow::Result<()> {
+        self.values.lock().unwrap().remove(name);
+        Ok(())
+    }
+}
+
+/// 开机启动时使用的命令行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutostartCommand {
+    pub executable: PathBuf,
+    pub config: PathBuf,
+    pub log: PathBuf,
+    pub delay: Option<Duration>,
+}
+
+impl AutostartCommand {
+    pub fn with_delay(&self, delay: Option<Duration>) -> Self {
+        Self {
+            delay,
+            ..self.clone()
+        }
+    }
+
+    pub fn command_line(&self) -> String {
+        let mut command = format!(
+            "\"{}\" --config \"{}\" --log \"{}\"",
+            self.executable.display(),
+            self.config.display(),
+            self.log.display()
+        );
+        if let Some(delay) = self.delay.filter(|delay| !delay.is_zero()) {
+            command.push_str(&format!(" --start-delay {}", delay.as_secs()));
+        }
+        command
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AutostartStatus {
+    Disabled,
+    /// `up_to_date` is false when the entry was written by another installation or arguments
+    Enabled {
+        command_line: String,
+        up_to_date: bool,
+    },
+}
+
+pub struct Autostart<R: RunRegistry> {
+    registry: R,
+}
+
+impl<R: RunRegistry> Autostart<R> {
+    pub const VALUE_NAME: &str = "LitAudioSinkNexus";
+
+    pub fn new(registry: R) -> Self {
+        Self { registry }
+    }
+
+    pub fn enable(&self, command: &AutostartCommand) -> anyhow::Result<()> {
+        self.registry.set(Self::VALUE_NAME, &command.command_line())
+    }
+
+    pub fn disable(&self) -> anyhow::Result<()> {
+        self.registry.delete(Self::VALUE_NAME)
+    }
+
+    pub fn status(&self, command: &AutostartCommand) -> anyhow::Result<AutostartStatus> {
+        let status = match self.registry.get(Self::VALUE_NAME)? {
+            Some(command_line) => AutostartStatus::Enabled {
+                up_to_date: command_line == command.command_line(),
+                command_line,
+            },
+            None => AutostartStatus::Disabled,
+        };
+        Ok(status)
+    }
+
+    pub fn is_enabled(&self) -> anyhow::Result<bool> {
+        Ok(self.registry.get(Self::VALUE_NAME)?.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> AutostartCommand {
+        AutostartCommand {
+            executable: PathBuf::from(r"C:\Program Files\Nexus\nexus.exe"),
+            config: PathBuf::from(r"C:\Users\me\config.toml"),
+            log: PathBuf::from(r"C:\Users\me\logs"),
+            delay: None,
+        }
+    }
+
+    const COMMAND_LINE: &str = r#""C:\Program Files\Nexus\nexus.exe" --config "C:\Users\me\config.toml" --log "C:\Users\me\logs""#;
+
+    #[test]
+    fn formats_the_start_delay() {
+        assert_eq!(command().command_line(), COMMAND_LINE);
+        assert_eq!(
+            command()
+                .with_delay(Some(Duration::from_secs(30)))
+                .command_line(),
+            format!("{COMMAND_LINE} --start-delay 30")
+        );
+        // 没有延迟时不写参数
+        assert_eq!(
+            command().with_delay(Some(Duration::ZERO)).command_line(),
+            COMMAND_LINE
+        );
+    }
+
+    #[test]
+    fn enable_and_disable() {
+        let autostart = Autostart::new(InMemoryRunRegistry::default());
+        assert!(!autostart.is_enabled().unwrap());
+        assert_eq!(
+            autostart.status(&command()).unwrap(),
+            AutostartStatus::Disabled
+        );
+
+        autostart.enable(&command()).unwrap();
+        assert!(autostart.is_enabled().unwrap());
+        assert_eq!(
+            autostart.status(&command()).unwrap(),
+            AutostartStatus::Enabled {
+                command_line: COMMAND_LINE.to_string(),
+                up_to_date: true,
+            }
+        );
+
+        autostart.disable().unwrap();
+        assert!(!autostart.is_enabled().unwrap());
+        // 删除不存在的值也成功
+        autostart.disable().unwrap();
+    }
+
+    #[test]
+    fn status_detects_outdated_entries() {
+        let autostart = Autostart::new(InMemoryRunRegistry::default());
+        let delayed = command().with_delay(Some(Duration::from_secs(10)));
+        autostart.enable(&delayed).unwrap();
+
+        assert_eq!(
+            autostart.status(&command()).unwrap(),
+            AutostartStatus::Enabled {
+                command_line: format!("{COMMAND_LINE} --start-delay 10"),
+                up_to_date: false,
+            }
+        );
+        assert_eq!(
+            autostart.status(&delayed).unwrap(),
+            AutostartStatus::Enabled {
+                command_line: delayed.command_line(),
+                up_to_date: true,
+            }
+        );
+    }
+}
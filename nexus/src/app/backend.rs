@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::log;
+
+/// A2DP 源设备
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendEvent {
+    Connected { device_id: String },
+    Disconnected { device_id: String },
+}
+
+pub type EventListener = Arc<dyn Fn(BackendEvent) + Send + Sync>;
+
+/// 音频连接的实现，可以是本进程的WinRT连接、服务进程或模拟实现
+///
+/// `connect` 在打开连接失败时返回可以 downcast 为 [`ConnectError`](super::ConnectError) 的错误
+pub trait AudioBackend: Send + Sync {
+    fn devices(&self) -> anyhow::Result<Vec<DeviceInfo>>;
+
+    fn connect(&self, device_id: &str) -> anyhow::Result<()>;
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()>;
+
+    /// IDs of the currently connected devices
+    fn connections(&self) -> anyhow::Result<Vec<String>>;
+
+    /// Drop a connection which no longer works, e.g. after the system resumed from sleep
+    fn mark_stale(&self, device_id: &str) -> anyhow::Result<()> {
+        self.disconnect(device_id)
+    }
+
+    fn subscribe(&self, listener: EventListener);
+}
+
+/// 包装后端的实现在记录与命令中需要设备名称，由这里缓存
+#[derive(Debug, Default)]
+pub struct DeviceNameCache(Mutex<HashMap<String, String>>);
+
+impl DeviceNameCache {
+    pub fn update(&self, devices: &[DeviceInfo]) {
+        let mut names = self.0.lock().unwrap();
+        for device in devices {
+            names.insert(device.id.clone(), device.name.clone());
+        }
+    }
+
+    /// The name of the device, empty if unknown
+    pub fn get(&self, device_id: &str) -> String {
+        self.0
+            .lock()
+            .unwrap()
+            .get(device_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Query the names from `backend` if the device is unknown, e.g. at its first connection
+    pub fn ensure(&self, device_id: &str, backend: &dyn AudioBackend) {
+        if self.0.lock().unwrap().contains_key(device_id) {
+            return;
+        }
+        match backend.devices() {
+            Ok(devices) => self.update(&devices),
+            Err(e) => log::debug!("Failed to query device names: {:?}", e),
+        }
+    }
+}
@@ -1,16 +1,102 @@
-use crate::internal::WarnExt;
+use crate::{
+    app::{
+        alias::DeviceNames, history::HistoryRetention, hooks::HooksConfig,
+        registry::AutoConnectPolicy, resume::ResumePolicy, schedule::ScheduleEntry,
+        script::ScriptConfig,
+    },
+    internal::WarnExt,
+    service::ApiConfig,
+};
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::PathBuf, sync::RwLock};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::Duration,
+};
+use toml_edit::{DocumentMut, Item, Table};
 use tracing::log;
 
+/// 带注释的默认配置，安装包与免安装版本都使用这份内容
+pub const DEFAULT_CONFIG: &str = include_str!("../../config.default.toml");
+
 #[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
 struct Config {
     auto_connect: bool,
+    /// 允许自动连接的设备ID或名称，为空时允许所有设备
+    auto_connect_devices: Vec<String>,
+    /// 运行期间新配对的设备是否自动连接
+    auto_connect_new_devices: bool,
+    /// 独占模式，同一时间只连接一个源设备
+    exclusive: bool,
+    /// 独占模式下断开当前设备后等待多久（秒）再连接新的设备
+    handover_delay_secs: u64,
+    /// 设备进入范围时是否自动连接
+    auto_connect_on_presence: bool,
+    /// 设备在范围内稳定多久（秒）后才自动连接
+    presence_debounce_secs: u64,
+    /// 睡眠恢复后是否重新连接之前的设备
+    reconnect_after_resume: bool,
+    /// 解锁后是否重新连接锁定期间断开的设备
+    reconnect_after_unlock: bool,
+    /// 恢复后等待蓝牙就绪再重连的时间（秒）
+    resume_reconnect_delay_secs: u64,
+    /// 连接历史保留的天数，0表示不限制
+    history_max_days: u64,
+    /// 连接历史保留的条数，0表示不限制
+    history_max_records: usize,
+    /// 相同警告的去重窗口（秒），0表示不去重
+    warn_dedup_secs: u64,
+    /// 开机启动后延迟连接的时间（秒）
+    autostart_delay_secs: u64,
+    /// 界面语言，未设置时跟随系统
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    /// 设备ID或蓝牙名称对应的显示名称
+    aliases: BTreeMap<String, String>,
+    /// 连接空闲多久（分钟）后自动断开，0表示不断开
+    idle_timeout_minutes: u64,
+    /// 按设备ID、蓝牙名称或别名设置的空闲超时（分钟），覆盖 `idle_timeout_minutes`
+    idle_timeouts: BTreeMap<String, u64>,
+    /// 连接状态变化时运行的命令
+    hooks: HooksConfig,
+    /// 定时连接与断开的规则
+    schedule: Vec<ScheduleEntry>,
+    /// 自动化脚本
+    script: ScriptConfig,
+    /// 本机HTTP接口
+    api: ApiConfig,
 }
 
 impl Default for Config {
     fn default() -> Self {
-        Self { auto_connect: true }
+        Self {
+            auto_connect: true,
+            auto_connect_devices: vec![],
+            auto_connect_new_devices: false,
+            exclusive: false,
+            handover_delay_secs: 1,
+            auto_connect_on_presence: false,
+            presence_debounce_secs: 5,
+            reconnect_after_resume: true,
+            reconnect_after_unlock: true,
+            resume_reconnect_delay_secs: 3,
+            history_max_days: 90,
+            history_max_records: 10000,
+            warn_dedup_secs: 60,
+            autostart_delay_secs: 0,
+            language: None,
+            aliases: BTreeMap::new(),
+            idle_timeout_minutes: 0,
+            idle_timeouts: BTreeMap::new(),
+            hooks: HooksConfig::default(),
+            schedule: vec![],
+            script: ScriptConfig::default(),
+            api: ApiConfig::default(),
+        }
     }
 }
 
@@ -21,6 +107,7 @@ pub struct AppConfig {
 }
 
 impl AppConfig {
+    /// Read the config file, the file is never written
     pub fn parse(path: PathBuf) -> anyhow::Result<Self> {
         let content = fs::read_to_string(&path)?;
         let config = toml::from_str(&content)?;
@@ -30,29 +117,411 @@ impl AppConfig {
             config: RwLock::new(config),
         })
     }
+    /// Read the config, falls back to the defaults without creating the file
+    pub fn load(path: PathBuf) -> Self {
+        if !path.exists() {
+            return Self::default_at(path);
+        }
+        Self::parse(path.clone()).unwrap_or_else(|e| {
+            log::warn!(
+                "Failed to parse config file {:?}, using default config: {:?}",
+                path,
+                e
+            );
+            Self::default_at(path)
+        })
+    }
+    /// Create the config file or add the keys of a new version to it, then read it
     pub fn parse_or_default(path: PathBuf) -> Self {
-        match Self::parse(path.clone()) {
-            Ok(config) => config,
-            Err(e) => {
-                log::warn!(
-                    "Failed to parse config file {:?}, using default config: {:?}",
-                    path,
-                    e
-                );
-                Self {
-                    file_path: path,
-                    config: RwLock::new(Config::default()),
-                }
-            }
+        if !path.exists() {
+            log::info!("Creating default config file {:?}", path);
+            fs::write(&path, DEFAULT_CONFIG).warn("Failed to write default config file");
+        }
+        Self::upgrade_file(&path).warn("Failed to add the new default keys to the config file");
+        Self::load(path)
+    }
+    fn default_at(path: PathBuf) -> Self {
+        Self {
+            file_path: path,
+            config: RwLock::new(Config::default()),
+        }
+    }
+    /// 升级后补充新版本的默认配置项，每个版本只补充一次
+    fn upgrade_file(path: &Path) -> anyhow::Result<()> {
+        let content = fs::read_to_string(path)?;
+        if let Some(upgraded) = upgrade_config(&content, DEFAULT_CONFIG, env!("CARGO_PKG_VERSION"))?
+        {
+            log::info!("Adding new default keys to config file {:?}", path);
+            fs::write(path, upgraded)?;
         }
+        Ok(())
     }
     pub fn auto_connect(&self) -> bool {
         self.config.read().unwrap().auto_connect
     }
+    pub fn auto_connect_policy(&self) -> AutoConnectPolicy {
+        let config = self.config.read().unwrap();
+        AutoConnectPolicy {
+            at_startup: config.auto_connect,
+            new_devices: config.auto_connect_new_devices,
+            on_presence: config.auto_connect_on_presence,
+            allowed: config.auto_connect_devices.clone(),
+            exclusive: config.exclusive,
+        }
+    }
+    pub fn exclusive(&self) -> bool {
+        self.config.read().unwrap().exclusive
+    }
+    pub fn handover_delay(&self) -> Duration {
+        Duration::from_secs(self.config.read().unwrap().handover_delay_secs)
+    }
+    pub fn presence_debounce(&self) -> Duration {
+        Duration::from_secs(self.config.read().unwrap().presence_debounce_secs)
+    }
+    pub fn resume_policy(&self) -> ResumePolicy {
+        let config = self.config.read().unwrap();
+        ResumePolicy {
+            on_resume: config.reconnect_after_resume,
+            on_unlock: config.reconnect_after_unlock,
+        }
+    }
+    pub fn resume_reconnect_delay(&self) -> Duration {
+        Duration::from_secs(self.config.read().unwrap().resume_reconnect_delay_secs)
+    }
+    pub fn history_retention(&self) -> HistoryRetention {
+        let config = self.config.read().unwrap();
+        HistoryRetention {
+            max_age: match config.history_max_days {
+                0 => None,
+                days => Some(Duration::from_secs(days * 24 * 60 * 60)),
+            },
+            max_records: match config.history_max_records {
+                0 => None,
+                records => Some(records),
+            },
+        }
+    }
+    pub fn warn_dedup_window(&self) -> Option<Duration> {
+        match self.config.read().unwrap().warn_dedup_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+    pub fn autostart_delay(&self) -> Option<Duration> {
+        match self.config.read().unwrap().autostart_delay_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+    pub fn language(&self) -> Option<String> {
+        self.config.read().unwrap().language.clone()
+    }
+    pub fn device_names(&self) -> DeviceNames {
+        DeviceNames::new(self.config.read().unwrap().aliases.clone())
+    }
+    /// Idle time after which the connection to the device is released, `None` keeps it
+    pub fn idle_timeout(&self, device_id: &str, name: &str) -> Option<Duration> {
+        let names = self.device_names();
+        let config = self.config.read().unwrap();
+        let minutes = config
+            .idle_timeouts
+            .get(device_id)
+            .or_else(|| {
+                config
+                    .idle_timeouts
+                    .iter()
+                    .find(|(key, _)| names.matches(key, device_id, name))
+                    .map(|(_, minutes)| minutes)
+            })
+            .copied()
+            .unwrap_or(config.idle_timeout_minutes);
+        (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+    }
+    pub fn hooks(&self) -> HooksConfig {
+        self.config.read().unwrap().hooks.clone()
+    }
+    /// The `[[schedule]]` rules as written, see [`parse_schedule`](super::parse_schedule)
+    pub fn schedule(&self) -> Vec<ScheduleEntry> {
+        self.config.read().unwrap().schedule.clone()
+    }
+    pub fn script(&self) -> ScriptConfig {
+        self.config.read().unwrap().script.clone()
+    }
+    pub fn api(&self) -> ApiConfig {
+        self.config.read().unwrap().api.clone()
+    }
+    /// Whether any device has an idle timeout
+    pub fn idle_disconnect_enabled(&self) -> bool {
+        let config = self.config.read().unwrap();
+        config.idle_timeout_minutes > 0 || config.idle_timeouts.values().any(|minutes| *minutes > 0)
+    }
     pub fn set_auto_connect(&self, value: bool) {
-        let mut config = self.config.write().unwrap();
-        config.auto_connect = value;
-        let config_content = toml::to_string(&*config).unwrap();
-        fs::write(&self.file_path, config_content).warn("Failed to write config file");
+        self.config.write().unwrap().auto_connect = value;
+        self.write_key("auto_connect", toml_edit::value(value))
+            .warn("Failed to write config file");
+    }
+    pub fn set_exclusive(&self, value: bool) {
+        self.config.write().unwrap().exclusive = value;
+        self.write_key("exclusive", toml_edit::value(value))
+            .warn("Failed to write config file");
+    }
+    /// `None` removes the key so the language follows the system again
+    pub fn set_language(&self, language: Option<&str>) {
+        self.config.write().unwrap().language = language.map(str::to_string);
+        let item = match language {
+            Some(language) => toml_edit::value(language),
+            None => Item::None,
+        };
+        self.write_key("language", item)
+            .warn("Failed to write config file");
+    }
+
+    /// Name `device` (an id or a Bluetooth name), `None` removes the alias
+    pub fn set_alias(&self, device: &str, alias: Option<&str>) -> anyhow::Result<()> {
+        {
+            let mut config = self.config.write().unwrap();
+            match alias {
+                Some(alias) => config.aliases.insert(device.to_string(), alias.to_string()),
+                None => config.aliases.remove(device),
+            };
+        }
+        self.edit_document(|document| {
+            if !document.contains_table("aliases") {
+                document.insert("aliases", Item::Table(Table::new()));
+            }
+            let aliases = document["aliases"]
+                .as_table_mut()
+                .ok_or_else(|| anyhow::anyhow!("`aliases` in the config is not a table"))?;
+            match alias {
+                Some(alias) => aliases[device] = toml_edit::value(alias),
+                None => {
+                    aliases.remove(device);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Save the token of the HTTP API to `[api]`
+    pub fn set_api_token(&self, token: &str) -> anyhow::Result<()> {
+        self.config.write().unwrap().api.token = Some(token.to_string());
+        self.edit_document(|document| {
+            if !document.contains_table("api") {
+                document.insert("api", Item::Table(Table::new()));
+            }
+            let api = document["api"]
+                .as_table_mut()
+                .ok_or_else(|| anyhow::anyhow!("`api` in the config is not a table"))?;
+            api["token"] = toml_edit::value(token);
+            Ok(())
+        })
+    }
+
+    /// Update one key in the file, keeping the comments and the other keys as the user left them
+    fn write_key(&self, key: &str, value: Item) -> anyhow::Result<()> {
+        self.edit_document(|document| {
+            match value {
+                Item::None => {
+                    document.remove(key);
+                }
+                value => document[key] = value,
+            }
+            Ok(())
+        })
+    }
+
+    fn edit_document(
+        &self,
+        edit: impl FnOnce(&mut DocumentMut) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let content = fs::read_to_string(&self.file_path)
+            .with_context(|| format!("Unable to read config file {:?}", self.file_path))?;
+        let mut document = content.parse::<DocumentMut>()?;
+        edit(&mut document)?;
+        fs::write(&self.file_path, document.to_string())?;
+        Ok(())
+    }
+}
+
+/// Key recording the version whose default keys were added to the file
+pub const DEFAULTS_VERSION_KEY: &str = "defaults_version";
+
+/// Add the default keys of a new `version` to `existing` and record the version
+///
+/// Returns `None` if the defaults of `version` were already added, so keys the user removed
+/// afterwards are not added again.
+pub fn upgrade_config(
+    existing: &str,
+    defaults: &str,
+    version: &str,
+) -> anyhow::Result<Option<String>> {
+    let mut document = existing.parse::<DocumentMut>()?;
+    if document.get(DEFAULTS_VERSION_KEY).and_then(Item::as_str) == Some(version) {
+        return Ok(None);
+    }
+
+    merge_document(&mut document, &defaults.parse::<DocumentMut>()?);
+    let is_new = !document.contains_key(DEFAULTS_VERSION_KEY);
+    document[DEFAULTS_VERSION_KEY] = toml_edit::value(version);
+    if is_new && let Some(mut key) = document.key_mut(DEFAULTS_VERSION_KEY) {
+        key.leaf_decor_mut().set_prefix(
+            "\n# Version whose new keys were added to this file, removed keys are not added again\n",
+        );
+    }
+    Ok(Some(document.to_string()))
+}
+
+fn merge_document(document: &mut DocumentMut, defaults: &DocumentMut) {
+    let mut next_position = max_position(document.as_table()) + 1;
+    merge_table(
+        document.as_table_mut(),
+        defaults.as_table(),
+        &mut next_position,
+    );
+}
+
+fn merge_table(table: &mut Table, defaults: &Table, next_position: &mut isize) {
+    for (key, default) in defaults.iter() {
+        match (table.get_mut(key), default) {
+            (None, _) => {
+                let (key, item) = defaults.get_key_value(key).unwrap();
+                let mut item = item.clone();
+                if let Item::Table(table) = &mut item {
+                    // 新增的表放到文件末尾
+                    table.set_position(*next_position);
+                    *next_position += 1;
+                }
+                table.insert_formatted(key, item);
+            }
+            (Some(Item::Table(table)), Item::Table(default)) => {
+                merge_table(table, default, next_position);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn max_position(table: &Table) -> isize {
+    // `[[schedule]]` 这样的表数组中每个表都有自己的位置
+    table
+        .iter()
+        .flat_map(|(_, item)| match item {
+            Item::Table(table) => vec![table],
+            Item::ArrayOfTables(array) => array.iter().collect(),
+            _ => vec![],
+        })
+        .map(|table| table.position().unwrap_or(0).max(max_position(table)))
+        .max()
+        .unwrap_or(0)
+}
+
+/// A configuration read from `content` in a temporary file, for the tests of other modules
+#[cfg(test)]
+pub(crate) fn test_config(name: &str, content: &str) -> AppConfig {
+    let path =
+        std::env::temp_dir().join(format!("nexus-test-{}-{}.toml", name, std::process::id()));
+    fs::write(&path, content).unwrap();
+    AppConfig::load(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFAULTS: &str =
+        "# Connect at startup\nauto_connect = true\n\n# Only one source\nexclusive = false\n";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nexus-config-{}-{}.toml", name, std::process::id()))
+    }
+
+    #[test]
+    fn merges_defaults_once_per_version() {
+        let upgraded = upgrade_config("auto_connect = false\n", DEFAULTS, "1.0.0")
+            .unwrap()
+            .unwrap();
+        let config = toml::from_str::<Config>(&upgraded).unwrap();
+        assert!(!config.auto_connect);
+        assert!(upgraded.contains("# Only one source\nexclusive = false\n"));
+        assert!(upgraded.contains("defaults_version = \"1.0.0\""));
+
+        // 删除的配置项在同一版本中不会再被补充
+        let removed = upgraded.replace("exclusive = false\n", "");
+        assert_eq!(upgrade_config(&removed, DEFAULTS, "1.0.0").unwrap(), None);
+
+        let next = upgrade_config(&removed, DEFAULTS, "1.1.0")
+            .unwrap()
+            .unwrap();
+        assert!(next.contains("exclusive = false"));
+        assert!(next.contains("defaults_version = \"1.1.0\""));
+        assert_eq!(next.matches("defaults_version").count(), 1);
+    }
+
+    #[test]
+    fn upgrade_keeps_existing_values() {
+        let upgraded = upgrade_config("# Mine\nexclusive = true\n", DEFAULTS, "1.0.0")
+            .unwrap()
+            .unwrap();
+        assert!(upgraded.starts_with("# Mine\nexclusive = true\n"));
+        assert!(upgraded.contains("auto_connect = true"));
+        assert_eq!(upgraded.matches("exclusive").count(), 1);
+
+        // 没有缺少的配置项时只记录版本
+        let complete = "auto_connect = false\nexclusive = true\n";
+        let upgraded = upgrade_config(complete, DEFAULTS, "1.0.0")
+            .unwrap()
+            .unwrap();
+        assert!(upgraded.starts_with(complete));
+        let config = toml::from_str::<Config>(&upgraded).unwrap();
+        assert!(!config.auto_connect);
+        assert!(config.exclusive);
+    }
+
+    #[test]
+    fn load_does_not_create_the_file() {
+        let path = temp_path("load");
+        fs::remove_file(&path).ok();
+        let config = AppConfig::load(path.clone());
+        assert!(config.auto_connect());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn parse_or_default_records_the_version() {
+        let path = temp_path("create");
+        fs::remove_file(&path).ok();
+        AppConfig::parse_or_default(path.clone());
+        let content = fs::read_to_string(&path).unwrap();
+        let version = format!(
+            "\n# Version whose new keys were added to this file, removed keys are not added again\n\
+             defaults_version = \"{}\"\n",
+            env!("CARGO_PKG_VERSION")
+        );
+        assert!(content.contains(&version));
+        assert_eq!(content.replace(&version, ""), DEFAULT_CONFIG);
+
+        // 再次启动不改写文件
+        let edited = content.replace("exclusive = false\n", "");
+        fs::write(&path, &edited).unwrap();
+        AppConfig::parse_or_default(path.clone());
+        assert_eq!(fs::read_to_string(&path).unwrap(), edited);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn edits_fail_when_the_file_cannot_be_read() {
+        let path = temp_path("missing");
+        fs::remove_file(&path).ok();
+        let config = AppConfig::load(path.clone());
+        assert!(config.set_alias("device", Some("Phone")).is_err());
+        assert!(!path.exists());
+
+        fs::write(&path, "# mine\nauto_connect = false\n").unwrap();
+        config.set_alias("device", Some("Phone")).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# mine\nauto_connect = false\n\n[aliases]\ndevice = \"Phone\"\n"
+        );
+        fs::remove_file(&path).unwrap();
     }
 }
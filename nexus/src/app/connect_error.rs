@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// `AudioPlaybackConnectionOpenResultStatus` 中的失败状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OpenStatus {
+    RequestTimedOut,
+    DeniedBySystem,
+    UnknownFailure,
+    Other(i32),
+}
+
+/// `AudioPlaybackConnectionOpenResult::ExtendedError` 中已知的HRESULT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExtendedError {
+    RadioOff(i32),
+    DeviceBusy(i32),
+    DriverMissing(i32),
+    DeviceUnreachable(i32),
+    AccessDenied(i32),
+    ServiceDisabled(i32),
+    Cancelled(i32),
+    Other(i32),
+}
+
+impl ExtendedError {
+    /// Decode an HRESULT, returns `None` for success codes.
+    pub fn from_hresult(code: i32) -> Option<Self> {
+        if code >= 0 {
+            return None;
+        }
+
+        let error = match code as u32 {
+            // ERROR_NOT_READY, ERROR_DEVICE_NOT_AVAILABLE
+            0x8007_0015 | 0x8007_10DF => Self::RadioOff(code),
+            // ERROR_BUSY, ERROR_DEVICE_IN_USE
+            0x8007_00AA | 0x8007_0964 => Self::DeviceBusy(code),
+            // ERROR_NOT_SUPPORTED, REGDB_E_CLASSNOTREG
+            0x8007_0032 | 0x8004_0154 => Self::DriverMissing(code),
+            // ERROR_DEVICE_NOT_CONNECTED, ERROR_GEN_FAILURE, ERROR_SEM_TIMEOUT, ERROR_TIMEOUT
+            0x8007_048F | 0x8007_001F | 0x8007_0079 | 0x8007_05B4 => Self::DeviceUnreachable(code),
+            // E_ACCESSDENIED
+            0x8007_0005 => Self::AccessDenied(code),
+            // ERROR_SERVICE_DISABLED, ERROR_SERVICE_NOT_ACTIVE
+            0x8007_0422 | 0x8007_0426 => Self::ServiceDisabled(code),
+            // ERROR_CANCELLED
+            0x8007_04C7 => Self::Cancelled(code),
+            _ => Self::Other(code),
+        };
+
+        Some(error)
+    }
+
+    pub fn hresult(&self) -> i32 {
+        match *self {
+            Self::RadioOff(code)
+            | Self::DeviceBusy(code)
+            | Self::DriverMissing(code)
+            | Self::DeviceUnreachable(code)
+            | Self::AccessDenied(code)
+            | Self::ServiceDisabled(code)
+            | Self::Cancelled(code)
+            | Self::Other(code) => code,
+        }
+    }
+
+    /// Localized message for the error, `None` if the HRESULT is not in the decode table
+    pub fn describe<'a>(&self, strings: &'a ExtendedErrorStrings) -> Option<&'a str> {
+        let message = match self {
+            Self::RadioOff(_) => &strings.radio_off,
+            Self::DeviceBusy(_) => &strings.device_busy,
+            Self::DriverMissing(_) => &strings.driver_missing,
+            Self::DeviceUnreachable(_) => &strings.device_unreachable,
+            Self::AccessDenied(_) => &strings.access_denied,
+            Self::ServiceDisabled(_) => &strings.service_disabled,
+            Self::Cancelled(_) => &strings.cancelled,
+            Self::Other(_) => return None,
+        };
+        Some(message)
+    }
+}
+
+impl Display for ExtendedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::RadioOff(_) => "radio off",
+            Self::DeviceBusy(_) => "device busy",
+            Self::DriverMissing(_) => "driver missing",
+            Self::DeviceUnreachable(_) => "device unreachable",
+            Self::AccessDenied(_) => "access denied",
+            Self::ServiceDisabled(_) => "bluetooth service disabled",
+            Self::Cancelled(_) => "cancelled",
+            Self::Other(_) => "unknown error",
+        };
+        write!(f, "{} ({:#010X})", name, self.hresult() as u32)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedErrorStrings {
+    pub radio_off: String,
+    pub device_busy: String,
+    pub driver_missing: String,
+    pub device_unreachable: String,
+    pub access_denied: String,
+    pub service_disabled: String,
+    pub cancelled: String,
+}
+
+impl Default for ExtendedErrorStrings {
+    fn default() -> Self {
+        Self {
+            radio_off: "Bluetooth is turned off".to_string(),
+            device_busy: "Device is busy".to_string(),
+            driver_missing: "Bluetooth audio driver is missing".to_string(),
+            device_unreachable: "Device is out of range".to_string(),
+            access_denied: "Access denied".to_string(),
+            service_disabled: "Bluetooth service is disabled".to_string(),
+            cancelled: "Cancelled".to_string(),
+        }
+    }
+}
+
+/// 连接打开失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectError {
+    pub status: OpenStatus,
+    pub extended: Option<ExtendedError>,
+}
+
+impl ConnectError {
+    pub fn new(status: OpenStatus, extended_hresult: i32) -> Self {
+        Self {
+            status,
+            extended: ExtendedError::from_hresult(extended_hresult),
+        }
+    }
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.status {
+            OpenStatus::Other(status) => write!(f, "status {}", status)?,
+            status => write!(f, "{:?}", status)?,
+        }
+        if let Some(extended) = &self.extended {
+            write!(f, ", {}", extended)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Variant = fn(i32) -> ExtendedError;
+
+    #[test]
+    fn decodes_known_hresults() {
+        let table: &[(u32, Variant)] = &[
+            (0x8007_0015, ExtendedError::RadioOff),
+            (0x8007_10DF, ExtendedError::RadioOff),
+            (0x8007_00AA, ExtendedError::DeviceBusy),
+            (0x8007_0964, ExtendedError::DeviceBusy),
+            (0x8007_0032, ExtendedError::DriverMissing),
+            (0x8004_0154, ExtendedError::DriverMissing),
+            (0x8007_048F, ExtendedError::DeviceUnreachable),
+            (0x8007_001F, ExtendedError::DeviceUnreachable),
+            (0x8007_0079, ExtendedError::DeviceUnreachable),
+            (0x8007_05B4, ExtendedError::DeviceUnreachable),
+            (0x8007_0005, ExtendedError::AccessDenied),
+            (0x8007_0422, ExtendedError::ServiceDisabled),
+            (0x8007_0426, ExtendedError::ServiceDisabled),
+            (0x8007_04C7, ExtendedError::Cancelled),
+        ];
+        let strings = ExtendedErrorStrings::default();
+        for (hresult, expected) in table {
+            let code = *hresult as i32;
+            let error = ExtendedError::from_hresult(code);
+            assert_eq!(error, Some(expected(code)), "{hresult:#010X}");
+            assert_eq!(error.unwrap().hresult(), code);
+            assert!(error.unwrap().describe(&strings).is_some());
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_hresults() {
+        let code = 0x8000_4005_u32 as i32;
+        let error = ExtendedError::from_hresult(code).unwrap();
+        assert_eq!(error, ExtendedError::Other(code));
+        assert_eq!(error.describe(&ExtendedErrorStrings::default()), None);
+        assert_eq!(error.to_string(), "unknown error (0x80004005)");
+    }
+
+    #[test]
+    fn ignores_success_codes() {
+        assert_eq!(ExtendedError::from_hresult(0), None);
+        assert_eq!(ExtendedError::from_hresult(1), None);
+        assert_eq!(
+            ConnectError::new(OpenStatus::RequestTimedOut, 0).extended,
+            None
+        );
+    }
+
+    #[test]
+    fn formats_connect_errors() {
+        let error = ConnectError::new(OpenStatus::DeniedBySystem, 0x8007_0005_u32 as i32);
+        assert_eq!(
+            error.to_string(),
+            "DeniedBySystem, access denied (0x80070005)"
+        );
+        let error = ConnectError::new(OpenStatus::Other(7), 0);
+        assert_eq!(error.to_string(), "status 7");
+    }
+}
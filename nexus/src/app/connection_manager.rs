@@ -1,10 +1,28 @@
-use crate::{app::config::AppConfig, internal::*};
+use crate::{
+    app::{
+        audio_activity::AudioActivityMonitor,
+        backend::{AudioBackend, BackendEvent, DeviceInfo},
+        config::AppConfig,
+        connect_error::ConnectError,
+        control::{BackendControl, DeviceControl, DisconnectListener},
+        idle::{IdleDisconnect, IdleEvent},
+        presence::PresenceAutoConnect,
+        presence_watcher::{CONTAINER_ID, ContainerLookup, PresenceWatcher, guid_property},
+        registry::{DeviceRegistry, RegistryAction, WatcherEvent},
+        schedule::{ScheduleRunner, SystemClock, parse_schedule},
+        script::ConnectRefused,
+        strings::DeviceStatusStrings,
+    },
+    internal::*,
+};
 use anyhow::Context;
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock, Weak},
     thread,
+    time::Duration,
 };
+use tokio::sync::mpsc as async_mpsc;
 use tracing::log;
 use windows::{
     Devices::Enumeration::*,
@@ -16,66 +34,75 @@ use windows::{
     },
     core::*,
 };
+use windows_collections::IIterable;
 
-#[derive(Debug, Clone)]
-pub struct DeviceStatusStrings {
-    pub picker_title: String,
-    pub connecting: String,
-    pub connected: String,
-    pub timeout: String,
-    pub denied_by_system: String,
-    pub not_found: String,
-    pub unknown_reason: String,
-    pub disconnected: String,
-}
-
-impl Default for DeviceStatusStrings {
-    fn default() -> Self {
-        Self {
-            picker_title: "A2DP Sink: Click to select a source device ".to_string(),
-            connecting: "Connecting".to_string(),
-            connected: "Connected".to_string(),
-            timeout: "Connection Timeout".to_string(),
-            denied_by_system: "Connection Denied by System".to_string(),
-            not_found: "Device not found".to_string(),
-            unknown_reason: "Unknown Reason".to_string(),
-            disconnected: "Disconnected".to_string(),
-        }
-    }
-}
-
-#[derive(Debug)]
 struct ConnectionContext {
     window: WndHandle,
     config: Arc<AppConfig>,
     picker: DevicePicker,
-    connections: Mutex<HashMap<HSTRING, (DeviceInformation, AudioPlaybackConnection)>>,
-    strings: DeviceStatusStrings,
+    backend: Arc<dyn AudioBackend>,
+    watcher: DeviceWatcher,
+    /// 报告已配对设备是否在范围内，枚举完成后启动
+    presence: Mutex<Option<PresenceWatcher>>,
+    registry: Mutex<DeviceRegistry>,
+    /// 监视器报告的设备，用于设置选择器中的状态
+    devices: Mutex<HashMap<HSTRING, DeviceInformation>>,
+    failures: Mutex<HashMap<HSTRING, ConnectError>>,
+    strings: RwLock<DeviceStatusStrings>,
+    /// 实际执行连接与断开，独占模式下先断开其他设备
+    control: BackendControl,
+    /// 因空闲被断开的设备，重新连接前显示为空闲断开
+    idle: Mutex<HashSet<HSTRING>>,
+    /// 空闲断开未启用时为 `None`
+    idle_events: Mutex<Option<async_mpsc::UnboundedSender<IdleEvent>>>,
+    audio_activity: Mutex<Option<AudioActivityMonitor>>,
+    /// 定时规则的任务，没有规则时为 `None`
+    schedule: Mutex<Option<tokio::task::JoinHandle<()>>>,
 }
 
-#[derive(Debug)]
 pub struct ConnectionManager {
     context: Arc<ConnectionContext>,
 }
 
 #[allow(unused)]
 impl ConnectionManager {
+    /// 启动时等待监视器报告已配对设备的最长时间
+    const ENUMERATION_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// `enumerated_message` is posted to `window` when the paired devices are known, the window
+    /// calls [`finish_enumeration`](Self::finish_enumeration) for it
     pub fn new(
         window: HWND,
+        enumerated_message: u32,
         config: Arc<AppConfig>,
         strings: DeviceStatusStrings,
+        backend: Arc<dyn AudioBackend>,
     ) -> anyhow::Result<Self> {
         let manager = Self {
             context: Arc::new(ConnectionContext {
                 window: WndHandle::new(window),
+                control: BackendControl::new(config.clone(), backend.clone()),
                 config,
-                connections: Default::default(),
+                backend,
+                watcher: DeviceInformation::CreateWatcherAqsFilterAndAdditionalProperties(
+                    &AudioPlaybackConnection::GetDeviceSelector()?,
+                    &IIterable::<HSTRING>::from(vec![HSTRING::from(CONTAINER_ID)]),
+                )
+                .context("Failed to create DeviceWatcher")?,
+                presence: Default::default(),
+                registry: Default::default(),
+                devices: Default::default(),
+                failures: Default::default(),
                 picker: DevicePicker::new().context("Failed to create DevicePicker")?,
-                strings,
+                strings: RwLock::new(strings),
+                idle: Default::default(),
+                idle_events: Default::default(),
+                audio_activity: Default::default(),
+                schedule: Default::default(),
             }),
         };
         manager
-            .init_picker()
+            .init_picker(enumerated_message)
             .context("Failed to initialize DevicePicker")?;
 
         Ok(manager)
@@ -83,10 +110,19 @@ impl ConnectionManager {
 
     pub fn show(&self, rect: Rect) -> anyhow::Result<()> {
         log::info!("Showing Device Picker");
+        self.context
+            .send_idle_event(IdleEvent::Activity { device_id: None });
 
+        log::debug!("connections: {:?}", self.context.backend.connections());
         log::debug!(
-            "connections: {:?}",
-            self.context.connections.lock().unwrap()
+            "last failures: {:?}",
+            self.context
+                .failures
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(id, e)| format!("{id}: {e}"))
+                .collect::<Vec<_>>()
         );
 
         unsafe {
@@ -113,6 +149,83 @@ impl ConnectionManager {
         Ok(())
     }
 
+    /// Switch to the strings of another language, redrawing the title and the status of
+    /// every device seen by the picker
+    pub fn set_strings(&self, strings: DeviceStatusStrings) -> anyhow::Result<()> {
+        let context = &self.context;
+        context
+            .picker
+            .Appearance()?
+            .SetTitle(&HSTRING::from(&strings.picker_title))?;
+
+        let failures = context
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(device_id, error)| (device_id.to_string(), *error))
+            .collect();
+        let statuses = strings.device_statuses(context.backend.as_ref(), &failures)?;
+        let idle_disconnected = strings.idle_disconnected.clone();
+        *context.strings.write().unwrap() = strings;
+
+        let devices = context.devices.lock().unwrap().clone();
+        for (device_id, device) in &devices {
+            let (text, options) = match statuses.get(&device_id.to_string()) {
+                Some(status) if status.connected => (
+                    status.text.as_str(),
+                    DevicePickerDisplayStatusOptions::ShowDisconnectButton,
+                ),
+                Some(status) => (
+                    status.text.as_str(),
+                    DevicePickerDisplayStatusOptions::ShowRetryButton,
+                ),
+                None if context.idle.lock().unwrap().contains(device_id) => (
+                    idle_disconnected.as_str(),
+                    DevicePickerDisplayStatusOptions::ShowRetryButton,
+                ),
+                None => ("", DevicePickerDisplayStatusOptions::None),
+            };
+            context
+                .set_display_status(device, text, options)
+                .warn("Fail to update picker display status");
+        }
+        Ok(())
+    }
+
+    /// The device as last reported by the watcher
+    pub fn device(&self, device_id: &str) -> Option<DeviceInfo> {
+        self.context
+            .registry
+            .lock()
+            .unwrap()
+            .get(device_id)
+            .cloned()
+    }
+
+    /// Devices whose connection was released by the idle timeout, with their status
+    pub fn idle_statuses(&self) -> Vec<String> {
+        let context = &self.context;
+        let names = context.config.device_names();
+        let strings = context.strings.read().unwrap().clone();
+        let mut statuses = context
+            .idle
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|device_id| {
+                let device = self.device(&device_id.to_string()).unwrap_or(DeviceInfo {
+                    id: device_id.to_string(),
+                    name: String::new(),
+                });
+                let name = names.device_name(&device);
+                strings.with_alias(Some(&name), &strings.idle_disconnected)
+            })
+            .collect::<Vec<_>>();
+        statuses.sort();
+        statuses
+    }
+
     fn show_picker(&self) -> anyhow::Result<()> {
         let x = unsafe { GetSystemMetrics(SM_CXSCREEN) };
         let y = unsafe { GetSystemMetrics(SM_CYSCREEN) };
@@ -125,7 +238,9 @@ impl ConnectionManager {
         })
     }
 
-    fn init_picker(&self) -> anyhow::Result<()> {
+    /// Set up the picker and start the watcher, the startup continues in
+    /// [`finish_enumeration`](Self::finish_enumeration) so the window is not blocked meanwhile
+    fn init_picker(&self, enumerated_message: u32) -> anyhow::Result<()> {
         let context = &self.context;
         let picker = &self.context.picker;
 
@@ -138,22 +253,10 @@ impl ConnectionManager {
 
         let selector = AudioPlaybackConnection::GetDeviceSelector()?;
 
-        let all_device = DeviceInformation::FindAllAsyncAqsFilter(&selector)?
-            .join()?
-            .into_iter()
-            .collect::<Vec<_>>();
-
-        for device in &all_device {
-            log::debug!("Clearing device: {}({})", device.Name()?, device.Id()?);
-
-            picker
-                .SetDisplayStatus(
-                    &device.clone(),
-                    &HSTRING::from(""),
-                    DevicePickerDisplayStatusOptions::None,
-                )
-                .context("Fail to clear picker display status")?;
-        }
+        context.backend.subscribe(Arc::new({
+            let context = Arc::downgrade(context);
+            move |event| Self::handle_event(&context, event)
+        }));
 
         picker
             .Filter()
@@ -163,37 +266,57 @@ impl ConnectionManager {
             .Append(&selector)
             .context("Fail to append selector")?;
 
+        // 选择器持有这些回调，回调只能弱引用上下文，否则上下文永远不会释放
         picker
             .DeviceSelected(&{
-                let context = context.clone();
+                let context = Arc::downgrade(context);
                 TypedEventHandler::<_, DeviceSelectedEventArgs>::new(move |_, args| {
+                    let Some(context) = context.upgrade() else {
+                        return Ok(());
+                    };
                     let device = args.as_ref().unwrap().SelectedDevice()?;
+                    context.send_idle_event(IdleEvent::Activity {
+                        device_id: Some(device.Id()?.to_string()),
+                    });
 
-                    log::info!("Connecting to: {}({})", device.Name()?, device.Id()?);
-                    Self::connect(context.clone(), &device).to_win_result()
+                    log::info!(
+                        "Connecting to: {}({})",
+                        context.display_name(&device),
+                        device.Id()?
+                    );
+                    match Self::connect(context, &device) {
+                        Err(e) if !Self::is_shown(&e) => Err::<(), _>(e).to_win_result(),
+                        _ => Ok(()),
+                    }
                 })
             })
             .context("Fail to set DeviceSeleted callback")?;
 
         picker
             .DisconnectButtonClicked(&{
-                let context = context.clone();
+                let context = Arc::downgrade(context);
                 TypedEventHandler::<_, DeviceDisconnectButtonClickedEventArgs>::new(
                     move |_, args| {
+                        let Some(context) = context.upgrade() else {
+                            return Ok(());
+                        };
                         let device = args.as_ref().unwrap().Device()?;
                         let device_id = device.Id().unwrap();
                         log::info!(
                             "Disconnecting device: {}({})",
-                            device.Name().unwrap_or(HSTRING::from("(Unknown)")),
+                            context.display_name(&device),
                             device_id
                         );
 
-                        context.connections.lock().unwrap().remove(&device_id);
                         context
-                            .picker
-                            .SetDisplayStatus(
+                            .control
+                            .disconnect(&device_id.to_string())
+                            .warn("Fail to disconnect device");
+                        let status = context.strings.read().unwrap().disconnected.clone();
+                        context
+                            .set_display_status(
                                 &device,
-                                &HSTRING::from(&context.strings.disconnected),
+                                &status,
                                 DevicePickerDisplayStatusOptions::None,
                             )
                             .unwrap();
@@ -206,8 +329,11 @@ impl ConnectionManager {
 
         picker
             .DevicePickerDismissed(&{
-                let context = context.clone();
+                let context = Arc::downgrade(context);
                 TypedEventHandler::new(move |_, _| {
+                    let Some(context) = context.upgrade() else {
+                        return Ok(());
+                    };
                     log::debug!("Device Picker Dismissed");
 
                     unsafe {
@@ -227,154 +353,598 @@ impl ConnectionManager {
             })
             .context("Fail to set DevicePickerDismissed callback")?;
 
-        picker
-            .Appearance()?
-            .SetTitle(&HSTRING::from(&context.strings.picker_title))?;
+        picker.Appearance()?.SetTitle(&HSTRING::from(
+            &context.strings.read().unwrap().picker_title,
+        ))?;
 
-        if self.context.config.auto_connect() {
+        Self::start_watcher(context, enumerated_message)
+            .context("Failed to start DeviceWatcher")?;
+
+        // 监视器迟迟不报告枚举完成时也继续启动
+        thread::spawn({
+            let window = WndHandle::new(context.window.hwnd());
+            move || {
+                thread::sleep(Self::ENUMERATION_TIMEOUT);
+                unsafe {
+                    PostMessageW(
+                        Some(window.hwnd()),
+                        enumerated_message,
+                        WPARAM(1),
+                        LPARAM(0),
+                    )
+                }
+                .warn("Fail to post enumeration timeout");
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Connect and disconnect through the manager, showing the status in the picker
+    pub fn control(&self) -> Arc<dyn DeviceControl> {
+        Arc::new(ManagerControl(Arc::downgrade(&self.context)))
+    }
+
+    /// Show the status of the paired devices, connect them automatically and start the
+    /// features which need them, once the watcher reported every paired device
+    ///
+    /// Called on the window thread, `timed_out` when the watcher didn't report in time.
+    pub fn finish_enumeration(&self, timed_out: bool) -> anyhow::Result<()> {
+        let context = &self.context;
+        if context.registry.lock().unwrap().is_enumerated() {
+            return Ok(());
+        }
+        if timed_out {
+            log::warn!("Device enumeration did not complete in time");
+        }
+
+        // 服务进程中可能已有连接，其状态也在这里显示
+        let actions = Self::registry_actions(context, WatcherEvent::EnumerationCompleted);
+        if actions
+            .iter()
+            .any(|action| matches!(action, RegistryAction::Connect(_)))
+        {
             self.show_picker()?;
-            for device in &all_device {
-                thread::spawn({
-                    let context = context.clone();
-                    let device = device.clone();
-                    move || {
-                        log::info!(
-                            "Auto connecting to: {}({})",
-                            device.Name().unwrap_or(HSTRING::from("(Unknown)")),
-                            device.Id().unwrap()
-                        );
-                        if let Err(e) = ConnectionManager::connect(context.clone(), &device) {
-                            log::error!("Auto connect failed: {:?}", e);
-                        }
-                    }
+        }
+        Self::apply_actions(context, actions);
+
+        Self::start_presence(context).warn("Fail to start presence watcher");
+        if context.config.idle_disconnect_enabled() {
+            Self::start_idle_disconnect(context).warn("Fail to start idle disconnect");
+        }
+        Self::start_schedule(context).warn("Fail to start schedule");
+
+        Ok(())
+    }
+
+    /// Connect devices coming into range, see [`PresenceAutoConnect`]
+    fn start_presence(context: &Arc<ConnectionContext>) -> anyhow::Result<()> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .context("Presence auto connect needs a tokio runtime")?;
+
+        // 关联端点与音频接口属于同一个设备容器
+        let lookup: ContainerLookup = Arc::new({
+            let context = Arc::downgrade(context);
+            move |container| {
+                let context = context.upgrade()?;
+                let devices = context.devices.lock().unwrap();
+                devices.iter().find_map(|(device_id, device)| {
+                    let properties = device.Properties().ok()?;
+                    (guid_property(&properties, CONTAINER_ID)? == container).then(|| DeviceInfo {
+                        id: device_id.to_string(),
+                        name: device.Name().unwrap_or_default().to_string(),
+                    })
+                })
+            }
+        });
+
+        let (sender, receiver) = async_mpsc::unbounded_channel();
+        let watcher = PresenceWatcher::start(lookup, sender)?;
+        *context.presence.lock().unwrap() = Some(watcher);
+
+        let auto_connect =
+            PresenceAutoConnect::new(context.config.clone(), context.backend.clone());
+        runtime.spawn(auto_connect.run(receiver, {
+            let context = Arc::downgrade(context);
+            move |device| {
+                if let Some(context) = context.upgrade() {
+                    Self::apply_actions(&context, vec![RegistryAction::Connect(device.id)]);
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    /// Release connections idle for their timeout, see [`IdleDisconnect`]
+    fn start_idle_disconnect(context: &Arc<ConnectionContext>) -> anyhow::Result<()> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .context("Idle disconnect needs a tokio runtime")?;
+
+        let (sender, receiver) = async_mpsc::unbounded_channel();
+        *context.audio_activity.lock().unwrap() = Some(AudioActivityMonitor::start(sender.clone()));
+        *context.idle_events.lock().unwrap() = Some(sender);
+
+        let idle_disconnect = IdleDisconnect::new(context.config.clone(), context.backend.clone());
+        runtime.spawn(idle_disconnect.run(receiver, {
+            let context = Arc::downgrade(context);
+            move |device_id| {
+                let Some(context) = context.upgrade() else {
+                    return;
+                };
+                // 先标记再断开，断开事件据此显示空闲断开
+                context
+                    .idle
+                    .lock()
+                    .unwrap()
+                    .insert(HSTRING::from(&device_id));
+                // 断开会阻塞，不占用异步任务的线程
+                tokio::task::spawn_blocking(move || {
+                    context
+                        .control
+                        .disconnect(&device_id)
+                        .warn("Fail to disconnect idle device");
                 });
             }
+        }));
+        Ok(())
+    }
+
+    /// Connect and disconnect devices by the `[[schedule]]` rules, see [`ScheduleRunner`]
+    fn start_schedule(context: &Arc<ConnectionContext>) -> anyhow::Result<()> {
+        let (rules, errors) = parse_schedule(&context.config.schedule());
+        for error in &errors {
+            log::warn!("Ignoring invalid {}", error);
+        }
+        if rules.is_empty() {
+            return Ok(());
         }
 
+        let runtime =
+            tokio::runtime::Handle::try_current().context("Schedule needs a tokio runtime")?;
+        log::info!("Scheduling {} rules", rules.len());
+        let runner = ScheduleRunner::new(
+            context.config.clone(),
+            context.backend.clone(),
+            Arc::new(SystemClock),
+            rules,
+        );
+        let connect = {
+            let context = Arc::downgrade(context);
+            move |device_id| {
+                if let Some(context) = context.upgrade() {
+                    Self::apply_actions(&context, vec![RegistryAction::Connect(device_id)]);
+                }
+            }
+        };
+        let disconnect = {
+            let context = Arc::downgrade(context);
+            move |device_id: String| {
+                if let Some(context) = context.upgrade() {
+                    tokio::task::spawn_blocking(move || {
+                        context
+                            .control
+                            .disconnect(&device_id)
+                            .warn("Fail to disconnect scheduled device");
+                    });
+                }
+            }
+        };
+        let task = runtime.spawn(runner.run(connect, disconnect));
+        *context.schedule.lock().unwrap() = Some(task);
+        Ok(())
+    }
+
+    /// Keep the registry current, posts `enumerated_message` when the enumeration completed
+    fn start_watcher(
+        context: &Arc<ConnectionContext>,
+        enumerated_message: u32,
+    ) -> anyhow::Result<()> {
+        let watcher = &context.watcher;
+
+        watcher.Added(&TypedEventHandler::<DeviceWatcher, DeviceInformation>::new(
+            {
+                let context = Arc::downgrade(context);
+                move |_, device| {
+                    let Some(context) = context.upgrade() else {
+                        return Ok(());
+                    };
+                    let device = device.as_ref().unwrap();
+                    let device_id = device.Id()?;
+                    context
+                        .devices
+                        .lock()
+                        .unwrap()
+                        .insert(device_id.clone(), device.clone());
+
+                    let info = DeviceInfo {
+                        id: device_id.to_string(),
+                        name: device.Name()?.to_string(),
+                    };
+                    let actions = Self::registry_actions(&context, WatcherEvent::Added(info));
+                    Self::apply_actions(&context, actions);
+                    Ok(())
+                }
+            },
+        ))?;
+
+        watcher.Updated(
+            &TypedEventHandler::<DeviceWatcher, DeviceInformationUpdate>::new({
+                let context = Arc::downgrade(context);
+                move |_, update| {
+                    let Some(context) = context.upgrade() else {
+                        return Ok(());
+                    };
+                    let update = update.as_ref().unwrap();
+                    let device_id = update.Id()?;
+                    let device = context.devices.lock().unwrap().get(&device_id).cloned();
+                    let Some(device) = device else {
+                        return Ok(());
+                    };
+                    device.Update(update)?;
+
+                    let info = DeviceInfo {
+                        id: device_id.to_string(),
+                        name: device.Name()?.to_string(),
+                    };
+                    let actions = Self::registry_actions(&context, WatcherEvent::Updated(info));
+                    Self::apply_actions(&context, actions);
+                    Ok(())
+                }
+            }),
+        )?;
+
+        watcher.Removed(
+            &TypedEventHandler::<DeviceWatcher, DeviceInformationUpdate>::new({
+                let context = Arc::downgrade(context);
+                move |_, update| {
+                    let Some(context) = context.upgrade() else {
+                        return Ok(());
+                    };
+                    let device_id = update.as_ref().unwrap().Id()?.to_string();
+                    let actions =
+                        Self::registry_actions(&context, WatcherEvent::Removed(device_id));
+                    Self::apply_actions(&context, actions);
+                    Ok(())
+                }
+            }),
+        )?;
+
+        // 枚举完成交给窗口线程处理，以便在启动时先显示选择器再自动连接
+        watcher.EnumerationCompleted(&TypedEventHandler::new({
+            let window = WndHandle::new(context.window.hwnd());
+            move |_, _| unsafe {
+                PostMessageW(
+                    Some(window.hwnd()),
+                    enumerated_message,
+                    WPARAM(0),
+                    LPARAM(0),
+                )
+            }
+        }))?;
+
+        watcher.Start()?;
+        Ok(())
+    }
+
+    fn registry_actions(context: &ConnectionContext, event: WatcherEvent) -> Vec<RegistryAction> {
+        let connected = context.backend.connections().unwrap_or_default();
+        context.registry.lock().unwrap().handle(
+            event,
+            &context.config.auto_connect_policy(),
+            &connected,
+        )
+    }
+
+    fn apply_actions(context: &Arc<ConnectionContext>, actions: Vec<RegistryAction>) {
+        for action in actions {
+            match action {
+                RegistryAction::ShowStatus(device_id) => {
+                    Self::show_status(context, &device_id).warn("Fail to set picker display status")
+                }
+                RegistryAction::Forget(device_id) => {
+                    let device_id = HSTRING::from(device_id);
+                    context.devices.lock().unwrap().remove(&device_id);
+                    context.failures.lock().unwrap().remove(&device_id);
+                    context.idle.lock().unwrap().remove(&device_id);
+                }
+                RegistryAction::Connect(device_id) => {
+                    thread::spawn({
+                        let context = context.clone();
+                        move || Self::auto_connect(&context, &device_id)
+                    });
+                }
+                RegistryAction::ConnectFirst(device_ids) => {
+                    // 依次尝试，直到一个设备连接成功
+                    thread::spawn({
+                        let context = context.clone();
+                        move || {
+                            device_ids
+                                .iter()
+                                .any(|device_id| Self::auto_connect(&context, device_id))
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    /// Connected, the last failure or nothing
+    fn show_status(context: &ConnectionContext, device_id: &str) -> anyhow::Result<()> {
+        let device_id = HSTRING::from(device_id);
+        let Some(device) = context.devices.lock().unwrap().get(&device_id).cloned() else {
+            return Ok(());
+        };
+
+        let strings = context.strings.read().unwrap().clone();
+        let connected = context.backend.connections()?;
+        let failure = context.failures.lock().unwrap().get(&device_id).copied();
+        let (status, options) = match failure {
+            _ if connected.contains(&device_id.to_string()) => (
+                strings.connected,
+                DevicePickerDisplayStatusOptions::ShowDisconnectButton,
+            ),
+            Some(error) => (
+                strings.failure_text(&error),
+                DevicePickerDisplayStatusOptions::ShowRetryButton,
+            ),
+            None if context.idle.lock().unwrap().contains(&device_id) => (
+                strings.idle_disconnected,
+                DevicePickerDisplayStatusOptions::ShowRetryButton,
+            ),
+            None => (String::new(), DevicePickerDisplayStatusOptions::None),
+        };
+
+        context.set_display_status(&device, &status, options)?;
         Ok(())
     }
 
     fn connect(context: Arc<ConnectionContext>, device: &DeviceInformation) -> anyhow::Result<()> {
-        context.picker.SetDisplayStatus(
+        let strings = context.strings.read().unwrap().clone();
+        context.set_display_status(
             device,
-            &HSTRING::from(&context.strings.connecting),
+            &strings.connecting,
             DevicePickerDisplayStatusOptions::ShowProgress
                 | DevicePickerDisplayStatusOptions::ShowDisconnectButton,
         )?;
 
         let device_id = device.Id().unwrap();
-        let connection = AudioPlaybackConnection::TryCreateFromId(&device_id).context(format!(
-            "Failed to create AudioPlaybackConnection for device ID: {device_id}",
-        ))?;
-
-        connection.StateChanged(&{
-            let context = context.clone();
-            let device_id = device_id.clone();
-            TypedEventHandler::<AudioPlaybackConnection, _>::new(move |sender, _| {
-                let connection = sender.as_ref().unwrap();
-                let state = sender.as_ref().unwrap().State().unwrap();
-                Self::handle_state(&device_id, context.clone(), connection, state);
-                Ok(())
-            })
-        })?;
-
-        connection.Start().context(format!(
-            "Failed to start AudioPlaybackConnection for device ID: {device_id}",
-        ))?;
-        match connection
-            .Open()
-            .context("Fail to open connection")?
-            .Status()?
-        {
-            AudioPlaybackConnectionOpenResultStatus::Success => {
+        context
+            .devices
+            .lock()
+            .unwrap()
+            .insert(device_id.clone(), device.clone());
+
+        let error = match context.control.connect(&device_id.to_string()) {
+            Ok(()) => {
                 log::info!(
                     "Device connected: {}({})",
-                    device.Name().unwrap_or(HSTRING::from("(Unknown)")),
+                    context.display_name(&device),
                     device_id
                 );
-                context
-                    .connections
-                    .lock()
-                    .unwrap()
-                    .insert(device_id.clone(), (device.clone(), connection));
-                context.picker.SetDisplayStatus(
+                context.failures.lock().unwrap().remove(&device_id);
+                context.idle.lock().unwrap().remove(&device_id);
+                context.set_display_status(
                     device,
-                    &HSTRING::from(&context.strings.connected),
+                    &strings.connected,
                     DevicePickerDisplayStatusOptions::ShowDisconnectButton,
                 )?;
+                return Ok(());
             }
-            AudioPlaybackConnectionOpenResultStatus::RequestTimedOut => {
-                context.picker.SetDisplayStatus(
+            Err(e) if e.is::<ConnectRefused>() => {
+                log::info!(
+                    "Connection to {}({}) refused by the script",
+                    context.display_name(&device),
+                    device_id
+                );
+                context.set_display_status(
                     device,
-                    &HSTRING::from(&context.strings.timeout),
+                    &strings.refused,
                     DevicePickerDisplayStatusOptions::ShowRetryButton,
                 )?;
+                return Err(e);
             }
-            AudioPlaybackConnectionOpenResultStatus::UnknownFailure => {
-                context.picker.SetDisplayStatus(
-                    device,
-                    &HSTRING::from(&context.strings.not_found), // Error reported here when device cannot be scanned
-                    DevicePickerDisplayStatusOptions::ShowRetryButton,
-                )?;
+            Err(e) => match e.downcast_ref::<ConnectError>() {
+                Some(error) => *error,
+                None => return Err(e),
+            },
+        };
+
+        log::error!(
+            "Failed to open connection to {}({}): {}",
+            context.display_name(&device),
+            device_id,
+            error
+        );
+
+        context
+            .failures
+            .lock()
+            .unwrap()
+            .insert(device_id.clone(), error);
+        context.set_display_status(
+            device,
+            &strings.failure_text(&error),
+            DevicePickerDisplayStatusOptions::ShowRetryButton,
+        )?;
+
+        Err(error.into())
+    }
+
+    /// Failed connections are shown in the picker, only the other errors need reporting
+    fn is_shown(e: &anyhow::Error) -> bool {
+        e.is::<ConnectError>() || e.is::<ConnectRefused>()
+    }
+
+    /// Connect a device reported by the watcher, returns whether it is connected
+    fn auto_connect(context: &Arc<ConnectionContext>, device_id: &str) -> bool {
+        let device = context
+            .devices
+            .lock()
+            .unwrap()
+            .get(&HSTRING::from(device_id))
+            .cloned();
+        let Some(device) = device else {
+            return false;
+        };
+
+        log::info!(
+            "Auto connecting to: {}({})",
+            context.display_name(&device),
+            device_id
+        );
+        match ConnectionManager::connect(context.clone(), &device) {
+            Ok(()) => true,
+            Err(e) => {
+                if !Self::is_shown(&e) {
+                    log::error!("Auto connect failed: {:?}", e);
+                }
+                false
             }
-            AudioPlaybackConnectionOpenResultStatus::DeniedBySystem => {
-                context.picker.SetDisplayStatus(
-                    device,
-                    &HSTRING::from(&context.strings.denied_by_system),
-                    DevicePickerDisplayStatusOptions::ShowRetryButton,
-                )?;
+        }
+    }
+
+    fn handle_event(context: &Weak<ConnectionContext>, event: BackendEvent) {
+        let Some(context) = context.upgrade() else {
+            return;
+        };
+
+        let strings = context.strings.read().unwrap().clone();
+        let (device_id, status, options) = match &event {
+            BackendEvent::Connected { device_id } => {
+                context
+                    .idle
+                    .lock()
+                    .unwrap()
+                    .remove(&HSTRING::from(device_id));
+                context.send_idle_event(IdleEvent::Connected {
+                    device_id: device_id.clone(),
+                });
+                (
+                    device_id,
+                    &strings.connected,
+                    DevicePickerDisplayStatusOptions::ShowDisconnectButton,
+                )
             }
-            res => {
-                log::error!("Failed to open connection: {:?}", res);
-                context.picker.SetDisplayStatus(
-                    device,
-                    &HSTRING::from(&format!("{}{}", context.strings.unknown_reason, res.0)),
+            BackendEvent::Disconnected { device_id } => {
+                context.send_idle_event(IdleEvent::Disconnected {
+                    device_id: device_id.clone(),
+                });
+                let status = match context
+                    .idle
+                    .lock()
+                    .unwrap()
+                    .contains(&HSTRING::from(device_id))
+                {
+                    true => &strings.idle_disconnected,
+                    false => &strings.disconnected,
+                };
+                (
+                    device_id,
+                    status,
                     DevicePickerDisplayStatusOptions::ShowRetryButton,
-                )?;
+                )
             }
         };
-        Ok(())
+
+        let device = context
+            .devices
+            .lock()
+            .unwrap()
+            .get(&HSTRING::from(device_id))
+            .cloned();
+
+        if let Some(device) = device {
+            if let BackendEvent::Disconnected { .. } = event {
+                log::info!(
+                    "Device disconnected: {}({})",
+                    context.display_name(&device),
+                    device_id
+                );
+            }
+
+            context
+                .set_display_status(&device, status, options)
+                .warn("Fail to update picker display status");
+        }
     }
+}
 
-    fn handle_state(
-        device_id: &HSTRING,
-        context: Arc<ConnectionContext>,
-        connection: &AudioPlaybackConnection,
-        state: AudioPlaybackConnectionState,
-    ) {
-        match state {
-            AudioPlaybackConnectionState::Closed => {
-                // let device_id = connection.DeviceId().unwrap(); // Bug: Windows 问题会导致 double free
+/// [`DeviceControl`] for the HTTP API, the script and the reconnection after resume
+struct ManagerControl(Weak<ConnectionContext>);
+
+impl DeviceControl for ManagerControl {
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        let context = self.0.upgrade().context("The connection manager is gone")?;
+        let device = context
+            .devices
+            .lock()
+            .unwrap()
+            .get(&HSTRING::from(device_id))
+            .cloned();
+        match device {
+            Some(device) => ConnectionManager::connect(context, &device),
+            // 监视器还没报告的设备不在选择器中
+            None => context.control.connect(device_id),
+        }
+    }
 
-                log::debug!("AudioPlaybackConnection closed: {}", device_id);
-                let connection = context.connections.lock().unwrap().remove(&device_id);
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        let context = self.0.upgrade().context("The connection manager is gone")?;
+        context.control.disconnect(device_id)
+    }
 
-                if let Some((device, _)) = connection {
-                    log::info!(
-                        "Device disconnected: {}({})",
-                        device.Name().unwrap_or(HSTRING::from("(Unknown)")),
-                        device_id
-                    );
+    fn on_disconnect(&self, listener: DisconnectListener) {
+        if let Some(context) = self.0.upgrade() {
+            context.control.on_disconnect(listener);
+        }
+    }
+}
 
-                    context
-                        .picker
-                        .SetDisplayStatus(
-                            &device,
-                            &HSTRING::from(&context.strings.disconnected),
-                            DevicePickerDisplayStatusOptions::ShowRetryButton,
-                        )
-                        .unwrap();
-                }
-            }
-            _ => {
-                log::info!("AudioPlaybackConnection state changed: {:?}", state);
-            }
+impl ConnectionContext {
+    fn send_idle_event(&self, event: IdleEvent) {
+        if let Some(sender) = self.idle_events.lock().unwrap().as_ref() {
+            let _ = sender.send(event);
         }
     }
+
+    /// Alias or Bluetooth name of the device, for the logs
+    fn display_name(&self, device: &DeviceInformation) -> String {
+        let device_id = device.Id().unwrap_or_default().to_string();
+        let name = device.Name().unwrap_or_default().to_string();
+        self.config.device_names().display_name(&device_id, &name)
+    }
+
+    /// Show `status` of the device in the picker, after its alias if it has one
+    fn set_display_status(
+        &self,
+        device: &DeviceInformation,
+        status: &str,
+        options: DevicePickerDisplayStatusOptions,
+    ) -> Result<()> {
+        let device_id = device.Id()?.to_string();
+        let name = device.Name()?.to_string();
+        let names = self.config.device_names();
+        let text = self
+            .strings
+            .read()
+            .unwrap()
+            .with_alias(names.alias(&device_id, &name), status);
+        self.picker
+            .SetDisplayStatus(device, &HSTRING::from(text), options)
+    }
 }
 
 impl Drop for ConnectionContext {
     fn drop(&mut self) {
         log::debug!("ConnectionContext dropping");
+        // 停止监视器后发送端被释放，自动连接的任务随之结束
+        self.presence.lock().unwrap().take();
+        // 发送端全部释放后空闲断开的任务结束
+        self.audio_activity.lock().unwrap().take();
+        self.idle_events.lock().unwrap().take();
+        if let Some(task) = self.schedule.lock().unwrap().take() {
+            task.abort();
+        }
+        self.watcher.Stop().warn("Fail to stop DeviceWatcher");
     }
 }
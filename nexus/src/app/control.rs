@@ -0,0 +1,174 @@
+use crate::app::{backend::AudioBackend, config::AppConfig, exclusive::Handover};
+use std::sync::{Arc, Mutex};
+
+/// Told about the devices disconnected on purpose
+pub type DisconnectListener = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// 连接与断开请求的统一入口，独占模式的切换在这里处理
+///
+/// 托盘中由 `ConnectionManager` 实现，同时更新选择器中的状态；HTTP接口、脚本与恢复后的重连都经由它。
+pub trait DeviceControl: Send + Sync {
+    /// Connect the device, in exclusive mode after disconnecting the others
+    fn connect(&self, device_id: &str) -> anyhow::Result<()>;
+    /// Disconnect on purpose, the listeners are told after it succeeded
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()>;
+    fn on_disconnect(&self, listener: DisconnectListener);
+}
+
+/// 主动断开的监听者，例如恢复后不应重连这些设备
+#[derive(Default)]
+pub struct DisconnectListeners(Mutex<Vec<DisconnectListener>>);
+
+impl DisconnectListeners {
+    pub fn add(&self, listener: DisconnectListener) {
+        self.0.lock().unwrap().push(listener);
+    }
+
+    pub fn notify(&self, device_id: &str) {
+        let listeners = self.0.lock().unwrap().clone();
+        for listener in listeners {
+            listener(device_id);
+        }
+    }
+}
+
+/// [`DeviceControl`] directly on a backend, used where no picker shows the status
+pub struct BackendControl {
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    handover: Handover,
+    listeners: DisconnectListeners,
+}
+
+impl BackendControl {
+    pub fn new(config: Arc<AppConfig>, backend: Arc<dyn AudioBackend>) -> Self {
+        Self {
+            config,
+            backend,
+            handover: Handover::new(),
+            listeners: Default::default(),
+        }
+    }
+}
+
+impl DeviceControl for BackendControl {
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        match self.config.exclusive() {
+            true => self.handover.connect(
+                self.backend.as_ref(),
+                device_id,
+                self.config.handover_delay(),
+            ),
+            false => self.backend.connect(device_id),
+        }
+    }
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.backend.disconnect(device_id)?;
+        self.listeners.notify(device_id);
+        Ok(())
+    }
+
+    fn on_disconnect(&self, listener: DisconnectListener) {
+        self.listeners.add(listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{
+        BackendCall, BackendEvent, DeviceInfo, SimulatedBackend, config::test_config,
+    };
+    use std::time::{Duration, Instant};
+
+    fn backend() -> Arc<SimulatedBackend> {
+        Arc::new(SimulatedBackend::new(["a", "b"].map(|id| DeviceInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+        })))
+    }
+
+    #[test]
+    fn hands_over_in_exclusive_mode() {
+        let backend = backend();
+        let config = test_config(
+            "control-exclusive",
+            "exclusive = true\nhandover_delay_secs = 0\n",
+        );
+        let control = BackendControl::new(Arc::new(config), backend.clone());
+
+        let disconnected = Arc::new(Mutex::new(vec![]));
+        control.on_disconnect(Arc::new({
+            let disconnected = disconnected.clone();
+            move |device_id| disconnected.lock().unwrap().push(device_id.to_string())
+        }));
+
+        control.connect("a").unwrap();
+        backend.clear_calls();
+        control.connect("b").unwrap();
+        assert_eq!(
+            backend.calls(),
+            [
+                BackendCall::Disconnect("a".to_string()),
+                BackendCall::Connect("b".to_string()),
+            ]
+        );
+        assert_eq!(backend.connections().unwrap(), ["b"]);
+        // 切换时的断开不是用户的操作
+        assert!(disconnected.lock().unwrap().is_empty());
+        control.disconnect("b").unwrap();
+        assert!(backend.connections().unwrap().is_empty());
+        assert_eq!(*disconnected.lock().unwrap(), ["b"]);
+    }
+
+    #[test]
+    fn waits_the_handover_delay_before_connecting() {
+        let backend = backend();
+        let config = test_config(
+            "control-delay",
+            "exclusive = true\nhandover_delay_secs = 1\n",
+        );
+        let control = BackendControl::new(Arc::new(config), backend.clone());
+        control.connect("a").unwrap();
+
+        // 记录断开与连接之间经过的时间
+        let events = Arc::new(Mutex::new(vec![]));
+        backend.subscribe(Arc::new({
+            let events = events.clone();
+            move |event| events.lock().unwrap().push((event, Instant::now()))
+        }));
+        control.connect("b").unwrap();
+
+        let events = events.lock().unwrap();
+        let [(disconnected, disconnected_at), (connected, connected_at)] = &events[..] else {
+            panic!("Unexpected events {events:?}");
+        };
+        assert_eq!(
+            *disconnected,
+            BackendEvent::Disconnected {
+                device_id: "a".to_string()
+            }
+        );
+        assert_eq!(
+            *connected,
+            BackendEvent::Connected {
+                device_id: "b".to_string()
+            }
+        );
+        assert!(*connected_at - *disconnected_at >= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn connects_side_by_side_otherwise() {
+        let backend = backend();
+        let config = test_config("control-shared", "exclusive = false\n");
+        let control = BackendControl::new(Arc::new(config), backend.clone());
+
+        control.connect("a").unwrap();
+        control.connect("b").unwrap();
+        let mut connections = backend.connections().unwrap();
+        connections.sort();
+        assert_eq!(connections, ["a", "b"]);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::app::backend::AudioBackend;
+use std::{sync::Mutex, thread, time::Duration};
+use tracing::log;
+
+/// 独占模式下的切换：先断开当前的源设备，等待一段时间后再连接新的设备
+///
+/// 同时发起的切换依次进行，保证任何时候最多只有一个连接。
+#[derive(Debug, Default)]
+pub struct Handover {
+    lock: Mutex<()>,
+}
+
+impl Handover {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Disconnect every other device, wait `delay` if one was connected, then connect `device_id`
+    pub fn connect(
+        &self,
+        backend: &dyn AudioBackend,
+        device_id: &str,
+        delay: Duration,
+    ) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        let others = backend
+            .connections()?
+            .into_iter()
+            .filter(|connected| connected != device_id)
+            .collect::<Vec<_>>();
+        for other in &others {
+            log::info!("Handing over from {} to {}", other, device_id);
+            backend.disconnect(other)?;
+        }
+
+        // 给源设备时间停止播放，避免两个设备同时抢占音频
+        if !others.is_empty() && !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        backend.connect(device_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{BackendCall, BackendEvent, DeviceInfo, SimulatedBackend};
+    use std::{sync::Arc, time::Instant};
+
+    fn backend() -> Arc<SimulatedBackend> {
+        Arc::new(SimulatedBackend::new(["a", "b", "c"].map(|id| {
+            DeviceInfo {
+                id: id.to_string(),
+                name: id.to_string(),
+            }
+        })))
+    }
+
+    #[test]
+    fn disconnects_the_others_before_connecting() {
+        let backend = backend();
+        backend.connect("a").unwrap();
+        backend.connect("b").unwrap();
+        backend.clear_calls();
+
+        Handover::new()
+            .connect(backend.as_ref(), "c", Duration::ZERO)
+            .unwrap();
+        assert_eq!(
+            backend.calls(),
+            [
+                BackendCall::Disconnect("a".to_string()),
+                BackendCall::Disconnect("b".to_string()),
+                BackendCall::Connect("c".to_string()),
+            ]
+        );
+        assert_eq!(backend.connections().unwrap(), ["c"]);
+    }
+
+    #[test]
+    fn waits_only_after_disconnecting() {
+        let backend = backend();
+        let handover = Handover::new();
+        let delay = Duration::from_millis(300);
+
+        let start = Instant::now();
+        handover.connect(backend.as_ref(), "a", delay).unwrap();
+        assert!(start.elapsed() < delay);
+        // 已经连接的设备不需要切换
+        handover.connect(backend.as_ref(), "a", delay).unwrap();
+        assert!(start.elapsed() < delay);
+
+        let start = Instant::now();
+        handover.connect(backend.as_ref(), "b", delay).unwrap();
+        assert!(start.elapsed() >= delay);
+    }
+
+    #[test]
+    fn concurrent_handovers_keep_a_single_connection() {
+        let backend = backend();
+        let connected = Arc::new(Mutex::new((0, 0)));
+        backend.subscribe(Arc::new({
+            let connected = connected.clone();
+            move |event| {
+                let (current, max) = &mut *connected.lock().unwrap();
+                match event {
+                    BackendEvent::Connected { .. } => *current += 1,
+                    BackendEvent::Disconnected { .. } => *current -= 1,
+                }
+                *max = (*max).max(*current);
+            }
+        }));
+
+        let handover = Arc::new(Handover::new());
+        let threads = ["a", "b", "c", "a", "b", "c"].map(|id| {
+            let backend = backend.clone();
+            let handover = handover.clone();
+            thread::spawn(move || {
+                handover
+                    .connect(backend.as_ref(), id, Duration::from_millis(10))
+                    .unwrap()
+            })
+        });
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(backend.connections().unwrap().len(), 1);
+        assert_eq!(*connected.lock().unwrap(), (1, 1));
+    }
+}
@@ -0,0 +1,982 @@
+use crate::app::{
+    alias::DeviceNames,
+    backend::{AudioBackend, BackendEvent, DeviceInfo, DeviceNameCache, EventListener},
+    connect_error::ConnectError,
+};
+use anyhow::Context;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::Write as _,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::log;
+
+/// 连接历史中的一条记录，每行一条JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub time: DateTime<Utc>,
+    pub device_id: String,
+    /// Name when the event happened, empty if unknown
+    #[serde(default)]
+    pub device_name: String,
+    #[serde(flatten)]
+    pub event: HistoryEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum HistoryEvent {
+    Connected,
+    /// `duration_secs` is missing if the connection was opened before the recording started
+    Disconnected {
+        duration_secs: Option<u64>,
+    },
+    Failed {
+        error: ConnectError,
+    },
+}
+
+/// 历史记录最多保留多久、多少条，`None` 表示不限制
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HistoryRetention {
+    pub max_age: Option<Duration>,
+    pub max_records: Option<usize>,
+}
+
+impl HistoryRetention {
+    /// Keep the records allowed at `now`, the newest ones when there are too many
+    pub fn apply(&self, records: &mut Vec<HistoryRecord>, now: DateTime<Utc>) {
+        if let Some(max_age) = self.max_age.and_then(|age| TimeDelta::from_std(age).ok()) {
+            records.retain(|record| now - record.time <= max_age);
+        }
+        if let Some(max_records) = self.max_records
+            && records.len() > max_records
+        {
+            records.drain(..records.len() - max_records);
+        }
+    }
+}
+
+/// 只追加写入的历史文件，超出保留范围后整理一次
+#[derive(Debug)]
+pub struct HistoryStore {
+    path: PathBuf,
+    retention: HistoryRetention,
+    /// Records in the file, counted to know when to compact
+    count: Mutex<usize>,
+}
+
+impl HistoryStore {
+    /// Open the file at `path` for reading, it is left as it is
+    pub fn open(path: PathBuf, retention: HistoryRetention) -> Self {
+        Self {
+            path,
+            retention,
+            count: Mutex::new(0),
+        }
+    }
+
+    /// Open the file at `path` to record, dropping the records outside `retention`
+    ///
+    /// Only the process recording the history rewrites the file, the commands reading it
+    /// use [`open`](Self::open).
+    pub fn open_for_recording(path: PathBuf, retention: HistoryRetention) -> anyhow::Result<Self> {
+        let store = Self::open(path, retention);
+        store.compact(Utc::now())?;
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn append(&self, record: &HistoryRecord) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to write history file {:?}", self.path))?;
+
+        let count = {
+            let mut count = self.count.lock().unwrap();
+            *count += 1;
+            *count
+        };
+        // 超出上限一成后再整理，避免每次追加都重写文件
+        if let Some(max_records) = self.retention.max_records
+            && count > max_records + (max_records / 10).max(1)
+        {
+            self.compact(record.time)?;
+        }
+        Ok(())
+    }
+
+    /// Every readable record in the order written, broken lines are skipped with a warning
+    pub fn read(&self) -> anyhow::Result<Vec<HistoryRecord>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e).context(format!("Failed to read {:?}", self.path)),
+        };
+
+        Ok(content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(index, line)| match serde_json::from_str(line) {
+                Ok(record) => Some(record),
+                Err(e) => {
+                    log::warn!("Skipping line {} of {:?}: {}", index + 1, self.path, e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Rewrite the file with the records `retention` keeps at `now`
+    pub fn compact(&self, now: DateTime<Utc>) -> anyhow::Result<()> {
+        let mut records = self.read()?;
+        let before = records.len();
+        self.retention.apply(&mut records, now);
+        *self.count.lock().unwrap() = records.len();
+        if records.len() == before {
+            return Ok(());
+        }
+
+        log::debug!(
+            "Dropping {} old records from {:?}",
+            before - records.len(),
+            self.path
+        );
+        let mut content = String::new();
+        for record in &records {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+        // 先写临时文件再替换，中断时不会丢失全部历史
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, content)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// 将连接事件转换为历史记录，时间由调用方给出
+#[derive(Debug)]
+pub struct HistoryRecorder {
+    store: HistoryStore,
+    /// Start of the open sessions
+    sessions: Mutex<HashMap<String, DateTime<Utc>>>,
+    names: DeviceNameCache,
+}
+
+impl HistoryRecorder {
+    pub fn new(store: HistoryStore) -> Self {
+        Self {
+            store,
+            sessions: Default::default(),
+            names: Default::default(),
+        }
+    }
+
+    /// Names of the devices written in the records
+    pub fn names(&self) -> &DeviceNameCache {
+        &self.names
+    }
+
+    pub fn record_event(&self, event: &BackendEvent, now: DateTime<Utc>) {
+        let (device_id, event) = match event {
+            BackendEvent::Connected { device_id } => {
+                self.sessions.lock().unwrap().insert(device_id.clone(), now);
+                (device_id, HistoryEvent::Connected)
+            }
+            BackendEvent::Disconnected { device_id } => {
+                let start = self.sessions.lock().unwrap().remove(device_id);
+                let duration_secs = start.map(|start| (now - start).num_seconds().max(0) as u64);
+                (device_id, HistoryEvent::Disconnected { duration_secs })
+            }
+        };
+        self.append(device_id, event, now);
+    }
+
+    pub fn record_failure(&self, device_id: &str, error: ConnectError, now: DateTime<Utc>) {
+        self.append(device_id, HistoryEvent::Failed { error }, now);
+    }
+
+    fn append(&self, device_id: &str, event: HistoryEvent, now: DateTime<Utc>) {
+        let record = HistoryRecord {
+            time: now,
+            device_id: device_id.to_string(),
+            device_name: self.names.get(device_id),
+            event,
+        };
+        if let Err(e) = self.store.append(&record) {
+            log::warn!("Failed to record connection history: {:?}", e);
+        }
+    }
+}
+
+/// 记录连接历史的 [`AudioBackend`]，包装实际建立连接的实现
+pub struct RecordingBackend {
+    inner: Arc<dyn AudioBackend>,
+    recorder: Arc<HistoryRecorder>,
+}
+
+impl RecordingBackend {
+    pub fn new(inner: Arc<dyn AudioBackend>, recorder: HistoryRecorder) -> Self {
+        let recorder = Arc::new(recorder);
+        inner.subscribe(Arc::new({
+            let recorder = recorder.clone();
+            move |event| recorder.record_event(&event, Utc::now())
+        }));
+        Self { inner, recorder }
+    }
+}
+
+impl AudioBackend for RecordingBackend {
+    fn devices(&self) -> anyhow::Result<Vec<DeviceInfo>> {
+        let devices = self.inner.devices()?;
+        self.recorder.names().update(&devices);
+        Ok(devices)
+    }
+
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.recorder.names().ensure(device_id, self.inner.as_ref());
+
+        let result = self.inner.connect(device_id);
+        if let Some(error) = result
+            .as_ref()
+            .err()
+            .and_then(|e| e.downcast_ref::<ConnectError>())
+        {
+            self.recorder.record_failure(device_id, *error, Utc::now());
+        }
+        result
+    }
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.inner.disconnect(device_id)
+    }
+
+    fn mark_stale(&self, device_id: &str) -> anyhow::Result<()> {
+        self.inner.mark_stale(device_id)
+    }
+
+    fn connections(&self) -> anyhow::Result<Vec<String>> {
+        self.inner.connections()
+    }
+
+    fn subscribe(&self, listener: EventListener) {
+        self.inner.subscribe(listener)
+    }
+}
+
+/// 按设备与时间筛选记录
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryFilter {
+    /// Device id, alias or case-insensitive name
+    pub device: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, record: &HistoryRecord, names: &DeviceNames) -> bool {
+        let device = self
+            .device
+            .as_ref()
+            .is_none_or(|device| names.matches(device, &record.device_id, &record.device_name));
+        let since = self.since.is_none_or(|since| record.time >= since);
+        device && since
+    }
+}
+
+/// Parse `--since`: a duration back from `now` like `30m`, `12h`, `7d` or `2w`, a date or an
+/// RFC 3339 time
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> anyhow::Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Some(unit) = value.chars().last().filter(char::is_ascii_alphabetic)
+        && let Ok(amount) = value[..value.len() - 1].parse::<u32>()
+    {
+        let amount = i64::from(amount);
+        let delta = match unit {
+            'm' => TimeDelta::try_minutes(amount),
+            'h' => TimeDelta::try_hours(amount),
+            'd' => TimeDelta::try_days(amount),
+            'w' => TimeDelta::try_weeks(amount),
+            _ => anyhow::bail!("Unknown unit {unit:?} in {value:?}, use m, h, d or w"),
+        };
+        return delta
+            .and_then(|delta| now.checked_sub_signed(delta))
+            .with_context(|| format!("{value:?} is too far back"));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return date
+            .and_hms_opt(0, 0, 0)
+            .and_then(|time| time.and_local_timezone(Local).earliest())
+            .map(|time| time.with_timezone(&Utc))
+            .with_context(|| format!("Invalid date {value:?}"));
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .with_context(|| {
+            format!("Invalid time {value:?}, expected e.g. 7d, 12h, 2024-05-01 or RFC 3339")
+        })
+}
+
+/// 单个设备的统计
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceStats {
+    pub device_id: String,
+    pub device_name: String,
+    pub alias: Option<String>,
+    /// Connected and failed attempts
+    pub attempts: usize,
+    pub successes: usize,
+    pub failures: usize,
+    /// `successes / attempts`, `None` without attempts
+    pub success_rate: Option<f64>,
+    /// Sessions whose length is known
+    pub sessions: usize,
+    pub total_session_secs: u64,
+    pub average_session_secs: Option<u64>,
+    pub last_event: DateTime<Utc>,
+}
+
+/// Aggregate the records per device, ordered by device id
+pub fn device_stats(records: &[HistoryRecord], names: &DeviceNames) -> Vec<DeviceStats> {
+    let mut stats = BTreeMap::<&str, DeviceStats>::new();
+    for record in records {
+        let entry = stats
+            .entry(&record.device_id)
+            .or_insert_with(|| DeviceStats {
+                device_id: record.device_id.clone(),
+                device_name: String::new(),
+                alias: None,
+                attempts: 0,
+                successes: 0,
+                failures: 0,
+                success_rate: None,
+                sessions: 0,
+                total_session_secs: 0,
+                average_session_secs: None,
+                last_event: record.time,
+            });
+        if !record.device_name.is_empty() {
+            entry.device_name = record.device_name.clone();
+        }
+        entry.last_event = entry.last_event.max(record.time);
+        match &record.event {
+            HistoryEvent::Connected => {
+                entry.attempts += 1;
+                entry.successes += 1;
+            }
+            HistoryEvent::Failed { .. } => {
+                entry.attempts += 1;
+                entry.failures += 1;
+            }
+            HistoryEvent::Disconnected { duration_secs } => {
+                if let Some(secs) = duration_secs {
+                    entry.sessions += 1;
+                    entry.total_session_secs += secs;
+                }
+            }
+        }
+    }
+
+    stats
+        .into_values()
+        .map(|mut stats| {
+            stats.alias = names
+                .alias(&stats.device_id, &stats.device_name)
+                .map(str::to_string);
+            if stats.attempts > 0 {
+                stats.success_rate = Some(stats.successes as f64 / stats.attempts as f64);
+            }
+            if stats.sessions > 0 {
+                stats.average_session_secs = Some(stats.total_session_secs / stats.sessions as u64);
+            }
+            stats
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Csv,
+    Json,
+}
+
+/// `1h 05m`, `3m 20s` or `42s`
+pub fn format_duration(secs: u64) -> String {
+    match (secs / 3600, secs % 3600 / 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m {s:02}s"),
+        (h, m, _) => format!("{h}h {m:02}m"),
+    }
+}
+
+fn event_name(event: &HistoryEvent) -> &'static str {
+    match event {
+        HistoryEvent::Connected => "connected",
+        HistoryEvent::Disconnected { .. } => "disconnected",
+        HistoryEvent::Failed { .. } => "failed",
+    }
+}
+
+fn event_detail(event: &HistoryEvent) -> String {
+    match event {
+        HistoryEvent::Connected => String::new(),
+        HistoryEvent::Disconnected { duration_secs } => {
+            duration_secs.map(format_duration).unwrap_or_default()
+        }
+        HistoryEvent::Failed { error } => error.to_string(),
+    }
+}
+
+/// A record with the alias of its device, for the JSON output
+#[derive(Serialize)]
+struct RecordOutput<'a> {
+    #[serde(flatten)]
+    record: &'a HistoryRecord,
+    alias: Option<&'a str>,
+}
+
+pub fn render_records(
+    records: &[HistoryRecord],
+    names: &DeviceNames,
+    format: OutputFormat,
+) -> anyhow::Result<String> {
+    let alias = |record: &HistoryRecord| names.alias(&record.device_id, &record.device_name);
+    match format {
+        OutputFormat::Json => {
+            let records = records
+                .iter()
+                .map(|record| RecordOutput {
+                    record,
+                    alias: alias(record),
+                })
+                .collect::<Vec<_>>();
+            Ok(serde_json::to_string_pretty(&records)? + "\n")
+        }
+        OutputFormat::Csv => {
+            let mut rows = vec![csv_row([
+                "time",
+                "device_id",
+                "device_name",
+                "alias",
+                "event",
+                "duration_secs",
+                "error",
+            ])];
+            rows.extend(records.iter().map(|record| {
+                let (duration, error) = match &record.event {
+                    HistoryEvent::Disconnected { duration_secs } => (
+                        duration_secs
+                            .map(|secs| secs.to_string())
+                            .unwrap_or_default(),
+                        String::new(),
+                    ),
+                    HistoryEvent::Failed { error } => (String::new(), error.to_string()),
+                    HistoryEvent::Connected => Default::default(),
+                };
+                csv_row([
+                    &record.time.to_rfc3339(),
+                    &record.device_id,
+                    &record.device_name,
+                    alias(record).unwrap_or_default(),
+                    event_name(&record.event),
+                    &duration,
+                    &error,
+                ])
+            }));
+            Ok(rows.concat())
+        }
+        OutputFormat::Table => Ok(table(
+            ["TIME", "DEVICE", "EVENT", "DETAIL"],
+            records.iter().map(|record| {
+                [
+                    record
+                        .time
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string(),
+                    names.display_name(&record.device_id, &record.device_name),
+                    event_name(&record.event).to_string(),
+                    event_detail(&record.event),
+                ]
+            }),
+        )),
+    }
+}
+
+pub fn render_stats(
+    stats: &[DeviceStats],
+    names: &DeviceNames,
+    format: OutputFormat,
+) -> anyhow::Result<String> {
+    let rate = |stats: &DeviceStats| {
+        stats
+            .success_rate
+            .map(|rate| format!("{:.0}%", rate * 100.0))
+            .unwrap_or_default()
+    };
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(stats)? + "\n"),
+        OutputFormat::Csv => {
+            let mut rows = vec![csv_row([
+                "device_id",
+                "device_name",
+                "alias",
+                "attempts",
+                "successes",
+                "failures",
+                "success_rate",
+                "sessions",
+                "average_session_secs",
+                "last_event",
+            ])];
+            rows.extend(stats.iter().map(|stats| {
+                csv_row([
+                    &stats.device_id,
+                    &stats.device_name,
+                    stats.alias.as_deref().unwrap_or_default(),
+                    &stats.attempts.to_string(),
+                    &stats.successes.to_string(),
+                    &stats.failures.to_string(),
+                    &stats
+                        .success_rate
+                        .map(|rate| format!("{rate:.3}"))
+                        .unwrap_or_default(),
+                    &stats.sessions.to_string(),
+                    &stats
+                        .average_session_secs
+                        .map(|secs| secs.to_string())
+                        .unwrap_or_default(),
+                    &stats.last_event.to_rfc3339(),
+                ])
+            }));
+            Ok(rows.concat())
+        }
+        OutputFormat::Table => Ok(table(
+            ["DEVICE", "ATTEMPTS", "SUCCESS", "SESSIONS", "AVG SESSION"],
+            stats.iter().map(|stats| {
+                [
+                    names.display_name(&stats.device_id, &stats.device_name),
+                    stats.attempts.to_string(),
+                    rate(stats),
+                    stats.sessions.to_string(),
+                    stats
+                        .average_session_secs
+                        .map(format_duration)
+                        .unwrap_or_default(),
+                ]
+            }),
+        )),
+    }
+}
+
+fn csv_row<const N: usize>(fields: [&str; N]) -> String {
+    let mut row = fields
+        .iter()
+        .map(|field| match field.contains([',', '"', '\n', '\r']) {
+            true => format!("\"{}\"", field.replace('"', "\"\"")),
+            false => field.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push('\n');
+    row
+}
+
+/// Left aligned columns separated by two spaces
+fn table<const N: usize>(header: [&str; N], rows: impl Iterator<Item = [String; N]>) -> String {
+    let rows = std::iter::once(header.map(str::to_string))
+        .chain(rows)
+        .collect::<Vec<_>>();
+    let mut widths = [0; N];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut output = String::new();
+    for row in &rows {
+        let mut line = String::new();
+        for (index, (cell, width)) in row.iter().zip(widths).enumerate() {
+            match index + 1 == N {
+                true => line.push_str(cell),
+                false => write!(line, "{cell:width$}  ").unwrap(),
+            }
+        }
+        output.push_str(line.trim_end());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{OpenStatus, SimulatedBackend};
+    use chrono::TimeZone;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "nexus-history-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn names() -> DeviceNames {
+        DeviceNames::new([("a".to_string(), "Mine".to_string())].into())
+    }
+
+    fn at(minutes: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 5, 1, 12, 0, 0).unwrap() + TimeDelta::minutes(minutes)
+    }
+
+    fn event(
+        minutes: i64,
+        device_id: &str,
+        device_name: &str,
+        event: HistoryEvent,
+    ) -> HistoryRecord {
+        HistoryRecord {
+            time: at(minutes),
+            device_id: device_id.to_string(),
+            device_name: device_name.to_string(),
+            event,
+        }
+    }
+
+    fn timed_out() -> ConnectError {
+        ConnectError::new(OpenStatus::RequestTimedOut, 0)
+    }
+
+    fn disconnected(secs: Option<u64>) -> HistoryEvent {
+        HistoryEvent::Disconnected {
+            duration_secs: secs,
+        }
+    }
+
+    fn record(days_ago: i64, now: DateTime<Utc>) -> HistoryRecord {
+        HistoryRecord {
+            time: now - TimeDelta::days(days_ago),
+            device_id: "a".to_string(),
+            device_name: "Phone".to_string(),
+            event: HistoryEvent::Connected,
+        }
+    }
+
+    #[test]
+    fn only_recording_compacts_the_file() {
+        let path = temp_path("compact");
+        let now = Utc::now();
+        let retention = HistoryRetention {
+            max_age: Some(Duration::from_secs(7 * 24 * 3600)),
+            max_records: None,
+        };
+        let content = [record(30, now), record(1, now)]
+            .iter()
+            .map(|record| serde_json::to_string(record).unwrap() + "\n")
+            .collect::<String>();
+        fs::write(&path, &content).unwrap();
+
+        // 读取历史的命令不改写文件
+        let store = HistoryStore::open(path.clone(), retention);
+        assert_eq!(store.read().unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+        let store = HistoryStore::open_for_recording(path.clone(), retention).unwrap();
+        assert_eq!(store.read().unwrap(), vec![record(1, now)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn aggregates_attempts_and_sessions_per_device() {
+        let records = [
+            event(0, "a", "Phone", HistoryEvent::Connected),
+            event(10, "a", "Phone", disconnected(Some(600))),
+            event(
+                20,
+                "a",
+                "Phone",
+                HistoryEvent::Failed { error: timed_out() },
+            ),
+            event(30, "a", "Phone", HistoryEvent::Connected),
+            event(50, "a", "Phone", disconnected(Some(1200))),
+            // 记录开始前打开的连接不计入会话
+            event(60, "a", "Phone", disconnected(None)),
+            event(5, "b", "", disconnected(None)),
+        ];
+
+        let stats = device_stats(&records, &names());
+        assert_eq!(stats.len(), 2);
+        let a = &stats[0];
+        assert_eq!(a.device_id, "a");
+        assert_eq!(a.device_name, "Phone");
+        assert_eq!(a.alias.as_deref(), Some("Mine"));
+        assert_eq!((a.attempts, a.successes, a.failures), (3, 2, 1));
+        assert_eq!(a.success_rate, Some(2.0 / 3.0));
+        assert_eq!((a.sessions, a.total_session_secs), (2, 1800));
+        assert_eq!(a.average_session_secs, Some(900));
+        assert_eq!(a.last_event, at(60));
+
+        let b = &stats[1];
+        assert_eq!((b.attempts, b.sessions), (0, 0));
+        assert_eq!(b.success_rate, None);
+        assert_eq!(b.average_session_secs, None);
+    }
+
+    #[test]
+    fn renders_records_in_every_format() {
+        let records = [
+            event(0, "a", "Phone, Pro", HistoryEvent::Connected),
+            event(1, "b", "Tablet", disconnected(Some(65))),
+            event(
+                2,
+                "b",
+                "Tablet",
+                HistoryEvent::Failed { error: timed_out() },
+            ),
+        ];
+        let names = names();
+
+        let local = |minutes| {
+            at(minutes)
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        };
+        let row = |time: &str, device: &str, event: &str, detail: &str| {
+            format!("{time:19}  {device:6}  {event:12}  {detail}")
+                .trim_end()
+                .to_string()
+                + "\n"
+        };
+        let table = render_records(&records, &names, OutputFormat::Table).unwrap();
+        assert_eq!(
+            table,
+            [
+                row("TIME", "DEVICE", "EVENT", "DETAIL"),
+                row(&local(0), "Mine", "connected", ""),
+                row(&local(1), "Tablet", "disconnected", "1m 05s"),
+                row(&local(2), "Tablet", "failed", "RequestTimedOut"),
+            ]
+            .concat()
+        );
+
+        let csv = render_records(&records, &names, OutputFormat::Csv).unwrap();
+        assert_eq!(
+            csv,
+            "time,device_id,device_name,alias,event,duration_secs,error\n\
+             2026-05-01T12:00:00+00:00,a,\"Phone, Pro\",Mine,connected,,\n\
+             2026-05-01T12:01:00+00:00,b,Tablet,,disconnected,65,\n\
+             2026-05-01T12:02:00+00:00,b,Tablet,,failed,,RequestTimedOut\n"
+        );
+
+        let json = render_records(&records, &names, OutputFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json[0]["alias"], "Mine");
+        assert_eq!(json[0]["event"], "connected");
+        assert_eq!(json[1]["alias"], serde_json::Value::Null);
+        assert_eq!(json[1]["duration_secs"], 65);
+        assert_eq!(json[2]["error"]["status"], "RequestTimedOut");
+    }
+
+    #[test]
+    fn renders_stats_in_every_format() {
+        let records = [
+            event(0, "a", "Phone", HistoryEvent::Connected),
+            event(1, "a", "Phone", disconnected(Some(65))),
+            event(2, "a", "Phone", HistoryEvent::Failed { error: timed_out() }),
+        ];
+        let names = names();
+        let stats = device_stats(&records, &names);
+
+        let table = render_stats(&stats, &names, OutputFormat::Table).unwrap();
+        assert_eq!(
+            table,
+            "DEVICE  ATTEMPTS  SUCCESS  SESSIONS  AVG SESSION\n\
+             Mine    2         50%      1         1m 05s\n"
+        );
+
+        let csv = render_stats(&stats, &names, OutputFormat::Csv).unwrap();
+        assert_eq!(
+            csv,
+            "device_id,device_name,alias,attempts,successes,failures,success_rate,sessions,\
+             average_session_secs,last_event\n\
+             a,Phone,Mine,2,1,1,0.500,1,65,2026-05-01T12:02:00+00:00\n"
+        );
+
+        let json = render_stats(&stats, &names, OutputFormat::Json).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(json[0]["device_id"], "a");
+        assert_eq!(json[0]["success_rate"], 0.5);
+        assert_eq!(json[0]["average_session_secs"], 65);
+    }
+
+    #[test]
+    fn quotes_csv_fields_with_separators() {
+        assert_eq!(
+            csv_row(["plain", "a,b", "say \"hi\"", "two\nlines", "cr\r"]),
+            "plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\"cr\r\"\n"
+        );
+    }
+
+    #[test]
+    fn parses_since() {
+        let now = at(0);
+        assert_eq!(parse_since("30m", now).unwrap(), at(-30));
+        assert_eq!(parse_since(" 12h ", now).unwrap(), at(-12 * 60));
+        assert_eq!(parse_since("7d", now).unwrap(), at(-7 * 24 * 60));
+        assert_eq!(parse_since("2w", now).unwrap(), at(-14 * 24 * 60));
+        assert_eq!(
+            parse_since("2026-05-01T16:00:00+02:00", now).unwrap(),
+            at(2 * 60)
+        );
+        let midnight = Local
+            .with_ymd_and_hms(2026, 4, 1, 0, 0, 0)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(parse_since("2026-04-01", now).unwrap(), midnight);
+
+        for value in ["bogus", "5y", "", "2026-13-01", "99999999999w"] {
+            assert!(parse_since(value, now).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn filters_by_device_and_since() {
+        let records = [
+            event(0, "a", "Phone", HistoryEvent::Connected),
+            event(10, "b", "Tablet", HistoryEvent::Connected),
+            event(20, "a", "Phone", disconnected(Some(1200))),
+        ];
+        let names = names();
+        let matching = |filter: HistoryFilter| {
+            records
+                .iter()
+                .filter(|record| filter.matches(record, &names))
+                .map(|record| record.time)
+                .collect::<Vec<_>>()
+        };
+
+        // id、别名与不区分大小写的蓝牙名称都可以
+        for device in ["a", "mine", "PHONE"] {
+            let filter = HistoryFilter {
+                device: Some(device.to_string()),
+                since: None,
+            };
+            assert_eq!(matching(filter), [at(0), at(20)]);
+        }
+        let filter = HistoryFilter {
+            device: None,
+            since: Some(at(10)),
+        };
+        assert_eq!(matching(filter), [at(10), at(20)]);
+        let filter = HistoryFilter {
+            device: Some("tablet".to_string()),
+            since: Some(at(15)),
+        };
+        assert!(matching(filter).is_empty());
+    }
+
+    #[test]
+    fn retention_keeps_the_newest_records() {
+        let now = at(0);
+        let mut records = (0..5)
+            .rev()
+            .map(|days| record(days, now))
+            .collect::<Vec<_>>();
+        HistoryRetention {
+            max_age: Some(Duration::from_secs(3 * 24 * 3600)),
+            max_records: None,
+        }
+        .apply(&mut records, now);
+        assert_eq!(records, [3, 2, 1, 0].map(|days| record(days, now)));
+
+        HistoryRetention {
+            max_age: None,
+            max_records: Some(2),
+        }
+        .apply(&mut records, now);
+        assert_eq!(records, [1, 0].map(|days| record(days, now)));
+    }
+
+    #[test]
+    fn appending_past_the_limit_compacts_the_file() {
+        let path = temp_path("limit");
+        let store = HistoryStore::open(
+            path.clone(),
+            HistoryRetention {
+                max_age: None,
+                max_records: Some(10),
+            },
+        );
+        let records = (0..12)
+            .map(|minutes| event(minutes, "a", "Phone", HistoryEvent::Connected))
+            .collect::<Vec<_>>();
+        for record in &records[..11] {
+            store.append(record).unwrap();
+        }
+        // 超出上限一成以内不改写
+        assert_eq!(store.read().unwrap().len(), 11);
+
+        store.append(&records[11]).unwrap();
+        assert_eq!(store.read().unwrap(), records[2..]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recording_backend_stores_failures_and_sessions() {
+        let path = temp_path("recording");
+        let backend = Arc::new(SimulatedBackend::new([DeviceInfo {
+            id: "a".to_string(),
+            name: "Phone".to_string(),
+        }]));
+        let recorder = HistoryRecorder::new(HistoryStore::open(path.clone(), Default::default()));
+        let recording = RecordingBackend::new(backend.clone(), recorder);
+
+        backend.set_failure("a", Some(timed_out()));
+        assert!(recording.connect("a").is_err());
+        backend.set_failure("a", None);
+        recording.connect("a").unwrap();
+        backend.drop_connection("a");
+
+        let records = HistoryStore::open(path.clone(), Default::default())
+            .read()
+            .unwrap();
+        let events = records
+            .iter()
+            .map(|record| record.event.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                HistoryEvent::Failed { error: timed_out() },
+                HistoryEvent::Connected,
+                disconnected(Some(0)),
+            ]
+        );
+        // 名称在连接前从后端查询
+        assert!(records.iter().all(|record| record.device_name == "Phone"));
+        fs::remove_file(&path).unwrap();
+    }
+}
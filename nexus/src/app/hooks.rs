@@ -0,0 +1,454 @@
+use crate::app::{
+    backend::{AudioBackend, BackendEvent, DeviceInfo, DeviceNameCache, EventListener},
+    config::AppConfig,
+    connect_error::ConnectError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    process::Command,
+    runtime::Handle,
+    sync::{Semaphore, oneshot},
+};
+use tracing::log;
+
+/// 配置文件中的 `[hooks]`，连接状态变化时运行的命令
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_connected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_disconnected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_failed: Option<String>,
+    /// Seconds after which a command is killed, 0 lets it run until it exits
+    pub timeout_secs: u64,
+    /// Commands running at the same time, the others wait for their turn
+    pub max_concurrent: usize,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_connected: None,
+            on_disconnected: None,
+            on_failed: None,
+            timeout_secs: 30,
+            max_concurrent: 4,
+        }
+    }
+}
+
+impl HooksConfig {
+    pub fn command(&self, kind: HookKind) -> Option<&str> {
+        let command = match kind {
+            HookKind::Connected => &self.on_connected,
+            HookKind::Disconnected => &self.on_disconnected,
+            HookKind::Failed => &self.on_failed,
+        };
+        command
+            .as_deref()
+            .map(str::trim)
+            .filter(|command| !command.is_empty())
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        [
+            HookKind::Connected,
+            HookKind::Disconnected,
+            HookKind::Failed,
+        ]
+        .into_iter()
+        .all(|kind| self.command(kind).is_none())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    Connected,
+    Disconnected,
+    Failed,
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connected => write!(f, "connected"),
+            Self::Disconnected => write!(f, "disconnected"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// 传给钩子命令的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookEvent {
+    pub kind: HookKind,
+    pub device_id: String,
+    pub device_name: String,
+    pub alias: Option<String>,
+    pub error: Option<String>,
+}
+
+impl HookEvent {
+    /// Environment of the command, `NEXUS_STATE` is the state of the device after the event
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let state = match self.kind {
+            HookKind::Connected => "connected",
+            HookKind::Disconnected | HookKind::Failed => "disconnected",
+        };
+        vec![
+            ("NEXUS_EVENT", self.kind.to_string()),
+            ("NEXUS_DEVICE_ID", self.device_id.clone()),
+            ("NEXUS_DEVICE_NAME", self.device_name.clone()),
+            ("NEXUS_DEVICE_ALIAS", self.alias.clone().unwrap_or_default()),
+            ("NEXUS_STATE", state.to_string()),
+            ("NEXUS_ERROR", self.error.clone().unwrap_or_default()),
+        ]
+    }
+}
+
+/// 在tokio运行时中运行钩子命令，限制同时运行的数量与时间
+///
+/// 同一设备的命令按事件顺序依次运行，不同设备的命令可以同时运行。
+#[derive(Clone)]
+pub struct HookRunner {
+    config: HooksConfig,
+    runtime: Handle,
+    permits: Arc<Semaphore>,
+    /// 每个设备最后一个命令结束的通知
+    last: Arc<Mutex<HashMap<String, oneshot::Receiver<()>>>>,
+}
+
+impl HookRunner {
+    pub fn new(config: HooksConfig, runtime: Handle) -> Self {
+        let permits = Arc::new(Semaphore::new(config.max_concurrent.max(1)));
+        Self {
+            config,
+            runtime,
+            permits,
+            last: Default::default(),
+        }
+    }
+
+    /// Run the command configured for the event in the background
+    pub fn fire(&self, event: HookEvent) -> Option<tokio::task::JoinHandle<Option<i32>>> {
+        let command = self.config.command(event.kind)?.to_string();
+        let timeout = self.config.timeout();
+        let permits = self.permits.clone();
+        let (done, finished) = oneshot::channel();
+        let previous = self
+            .last
+            .lock()
+            .unwrap()
+            .insert(event.device_id.clone(), finished);
+        Some(self.runtime.spawn(async move {
+            // 发送端在命令结束或任务被取消时释放
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            let _permit = permits.acquire_owned().await.ok()?;
+            let code = run_hook(&command, &event, timeout).await;
+            drop(done);
+            code
+        }))
+    }
+}
+
+/// Run `command` through the shell, returns its exit code
+async fn run_hook(command: &str, event: &HookEvent, timeout: Option<Duration>) -> Option<i32> {
+    let mut process = shell(command);
+    process
+        .envs(event.env())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    log::debug!(
+        "Running {} hook for {}: {}",
+        event.kind,
+        event.device_id,
+        command
+    );
+    let output = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, process.output()).await,
+        None => Ok(process.output().await),
+    };
+    let output = match output {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            log::warn!("Failed to run {} hook {:?}: {:?}", event.kind, command, e);
+            return None;
+        }
+        // 超时后丢弃的子进程随之被终止
+        Err(_) => {
+            log::warn!(
+                "{} hook {:?} killed after {}s",
+                event.kind,
+                command,
+                timeout.unwrap_or_default().as_secs()
+            );
+            return None;
+        }
+    };
+
+    let code = output.status.code();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    match code {
+        Some(0) => log::info!("{} hook for {} exited with 0", event.kind, event.device_id),
+        code => log::warn!(
+            "{} hook for {} exited with {}: {}",
+            event.kind,
+            event.device_id,
+            code.map_or("a signal".to_string(), |code| code.to_string()),
+            stderr.trim()
+        ),
+    }
+    code
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut process = Command::new("cmd");
+    // 保留引号原样交给cmd解析
+    process.raw_arg("/C").raw_arg(command);
+    process.creation_flags(windows::Win32::System::Threading::CREATE_NO_WINDOW.0);
+    process
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command);
+    process
+}
+
+/// 连接状态变化时运行钩子的 [`AudioBackend`]，包装实际建立连接的实现
+pub struct HookedBackend {
+    inner: Arc<dyn AudioBackend>,
+    hooks: Arc<Hooks>,
+}
+
+struct Hooks {
+    runner: HookRunner,
+    config: Arc<AppConfig>,
+    /// 设备的蓝牙名称，事件中只有ID
+    names: DeviceNameCache,
+}
+
+impl Hooks {
+    fn fire(&self, kind: HookKind, device_id: &str, error: Option<String>) {
+        let device_name = self.names.get(device_id);
+        let alias = self
+            .config
+            .device_names()
+            .alias(device_id, &device_name)
+            .map(str::to_string);
+        self.runner.fire(HookEvent {
+            kind,
+            device_id: device_id.to_string(),
+            device_name,
+            alias,
+            error,
+        });
+    }
+}
+
+impl HookedBackend {
+    pub fn new(inner: Arc<dyn AudioBackend>, config: Arc<AppConfig>, runner: HookRunner) -> Self {
+        let hooks = Arc::new(Hooks {
+            runner,
+            config,
+            names: Default::default(),
+        });
+        inner.subscribe(Arc::new({
+            let hooks = hooks.clone();
+            move |event| match event {
+                BackendEvent::Connected { device_id } => {
+                    hooks.fire(HookKind::Connected, &device_id, None)
+                }
+                BackendEvent::Disconnected { device_id } => {
+                    hooks.fire(HookKind::Disconnected, &device_id, None)
+                }
+            }
+        }));
+        Self { inner, hooks }
+    }
+}
+
+impl AudioBackend for HookedBackend {
+    fn devices(&self) -> anyhow::Result<Vec<DeviceInfo>> {
+        let devices = self.inner.devices()?;
+        self.hooks.names.update(&devices);
+        Ok(devices)
+    }
+
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.hooks.names.ensure(device_id, self.inner.as_ref());
+
+        let result = self.inner.connect(device_id);
+        if let Some(error) = result
+            .as_ref()
+            .err()
+            .and_then(|e| e.downcast_ref::<ConnectError>())
+        {
+            self.hooks
+                .fire(HookKind::Failed, device_id, Some(error.to_string()));
+        }
+        result
+    }
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.inner.disconnect(device_id)
+    }
+
+    fn mark_stale(&self, device_id: &str) -> anyhow::Result<()> {
+        self.inner.mark_stale(device_id)
+    }
+
+    fn connections(&self) -> anyhow::Result<Vec<String>> {
+        self.inner.connections()
+    }
+
+    fn subscribe(&self, listener: EventListener) {
+        self.inner.subscribe(listener)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::{path::PathBuf, time::Instant};
+
+    fn output(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "nexus-test-hook-{}-{}.txt",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn event(kind: HookKind, device_id: &str, error: Option<&str>) -> HookEvent {
+        HookEvent {
+            kind,
+            device_id: device_id.to_string(),
+            device_name: "Phone".to_string(),
+            alias: Some("phone".to_string()),
+            error: error.map(str::to_string),
+        }
+    }
+
+    fn runner(config: HooksConfig) -> HookRunner {
+        HookRunner::new(config, Handle::current())
+    }
+
+    #[tokio::test]
+    async fn passes_the_event_in_the_environment() {
+        let path = output("env");
+        let runner = runner(HooksConfig {
+            on_failed: Some(format!(
+                "printf '%s|%s|%s|%s|%s|%s' \"$NEXUS_EVENT\" \"$NEXUS_DEVICE_ID\" \
+                 \"$NEXUS_DEVICE_NAME\" \"$NEXUS_DEVICE_ALIAS\" \"$NEXUS_STATE\" \
+                 \"$NEXUS_ERROR\" > '{}'; exit 3",
+                path.display()
+            )),
+            ..Default::default()
+        });
+
+        let code = runner
+            .fire(event(HookKind::Failed, "a", Some("refused")))
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(code, Some(3));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "failed|a|Phone|phone|disconnected|refused"
+        );
+        // 没有配置的事件不运行命令
+        assert!(runner.fire(event(HookKind::Connected, "a", None)).is_none());
+    }
+
+    #[tokio::test]
+    async fn kills_commands_running_too_long() {
+        let runner = runner(HooksConfig {
+            on_connected: Some("sleep 10".to_string()),
+            timeout_secs: 1,
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        let code = runner
+            .fire(event(HookKind::Connected, "a", None))
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(code, None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn zero_timeout_waits_for_the_command() {
+        let runner = runner(HooksConfig {
+            on_connected: Some("sleep 1; exit 7".to_string()),
+            timeout_secs: 0,
+            ..Default::default()
+        });
+
+        let code = runner
+            .fire(event(HookKind::Connected, "a", None))
+            .unwrap()
+            .await
+            .unwrap();
+        assert_eq!(code, Some(7));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn runs_the_commands_of_a_device_in_order() {
+        let path = output("order");
+        // 先触发的命令等待得更久
+        let runner = runner(HooksConfig {
+            on_failed: Some(format!(
+                "sleep \"0.$((4 - NEXUS_ERROR))\"; echo \"$NEXUS_DEVICE_ID$NEXUS_ERROR\" >> '{}'",
+                path.display()
+            )),
+            max_concurrent: 4,
+            ..Default::default()
+        });
+
+        let mut handles = vec![];
+        for error in ["1", "2", "3"] {
+            handles.push(
+                runner
+                    .fire(event(HookKind::Failed, "a", Some(error)))
+                    .unwrap(),
+            );
+        }
+        // 其他设备的命令不必等待
+        handles.push(
+            runner
+                .fire(event(HookKind::Failed, "b", Some("3")))
+                .unwrap(),
+        );
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), Some(0));
+        }
+
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(lines.lines().collect::<Vec<_>>(), ["b3", "a1", "a2", "a3"]);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::app::{backend::AudioBackend, config::AppConfig};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::log;
+
+/// 影响空闲计时的事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdleEvent {
+    Connected {
+        device_id: String,
+    },
+    Disconnected {
+        device_id: String,
+    },
+    /// Audio played or the user interacted, `None` applies to every connected device
+    ///
+    /// The output meter can't tell the sources apart, so playback keeps all of them alive.
+    Activity {
+        device_id: Option<String>,
+    },
+}
+
+/// 记录每个已连接设备最后一次活动的时间
+#[derive(Debug, Default)]
+pub struct IdleTracker {
+    /// 设备的空闲超时与最后活动时间，没有超时的设备不记录
+    devices: HashMap<String, (Duration, Instant)>,
+}
+
+impl IdleTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start timing the device, `None` keeps it connected however long it is idle
+    pub fn connected(&mut self, device_id: &str, timeout: Option<Duration>, now: Instant) {
+        match timeout {
+            Some(timeout) => {
+                self.devices.insert(device_id.to_string(), (timeout, now));
+            }
+            None => {
+                self.devices.remove(device_id);
+            }
+        }
+    }
+
+    pub fn disconnected(&mut self, device_id: &str) {
+        self.devices.remove(device_id);
+    }
+
+    pub fn activity(&mut self, device_id: Option<&str>, now: Instant) {
+        for (id, (_, last)) in self.devices.iter_mut() {
+            if device_id.is_none_or(|device_id| device_id == id) {
+                *last = now;
+            }
+        }
+    }
+
+    /// Devices idle for their whole timeout, they are no longer timed
+    pub fn due(&mut self, now: Instant) -> Vec<String> {
+        let mut due = self
+            .devices
+            .iter()
+            .filter(|(_, (timeout, last))| now >= *last + *timeout)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        due.sort();
+        for id in &due {
+            self.devices.remove(id);
+        }
+        due
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.devices
+            .values()
+            .map(|(timeout, last)| *last + *timeout)
+            .min()
+    }
+}
+
+/// 按配置断开空闲的连接
+pub struct IdleDisconnect {
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    tracker: IdleTracker,
+}
+
+impl IdleDisconnect {
+    pub fn new(config: Arc<AppConfig>, backend: Arc<dyn AudioBackend>) -> Self {
+        Self {
+            config,
+            backend,
+            tracker: IdleTracker::new(),
+        }
+    }
+
+    /// Follow `events` until they close, calling `disconnect` for the devices which went idle
+    pub async fn run(
+        mut self,
+        mut events: mpsc::UnboundedReceiver<IdleEvent>,
+        disconnect: impl Fn(String),
+    ) {
+        // 启动前已有的连接从现在开始计时
+        let backend = self.backend.clone();
+        match blocking(move || backend.connections()).await {
+            Ok(connections) => {
+                for device_id in connections {
+                    self.connected(&device_id).await;
+                }
+            }
+            Err(e) => log::warn!("Failed to query connections: {:?}", e),
+        }
+
+        loop {
+            let deadline = self.tracker.next_deadline();
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => self.handle(event).await,
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                    if deadline.is_some() => {}
+            }
+
+            for device_id in self.tracker.due(Instant::now()) {
+                log::info!("Disconnecting idle device {}", device_id);
+                disconnect(device_id);
+            }
+        }
+    }
+
+    async fn handle(&mut self, event: IdleEvent) {
+        match event {
+            IdleEvent::Connected { device_id } => self.connected(&device_id).await,
+            IdleEvent::Disconnected { device_id } => self.tracker.disconnected(&device_id),
+            IdleEvent::Activity { device_id } => {
+                self.tracker.activity(device_id.as_deref(), Instant::now())
+            }
+        }
+    }
+
+    async fn connected(&mut self, device_id: &str) {
+        // 超时可以按蓝牙名称配置，需要先查到名称
+        let backend = self.backend.clone();
+        let name = blocking(move || backend.devices())
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .find(|device| device.id == device_id)
+            .map(|device| device.name)
+            .unwrap_or_default();
+        let timeout = self.config.idle_timeout(device_id, &name);
+        self.tracker.connected(device_id, timeout, Instant::now());
+    }
+}
+
+/// Run a blocking backend call outside of the async workers
+async fn blocking<T: Send + 'static>(
+    call: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(call).await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{DeviceInfo, SimulatedBackend, config::test_config};
+    use std::sync::Mutex;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn backend() -> Arc<SimulatedBackend> {
+        Arc::new(SimulatedBackend::new([
+            DeviceInfo {
+                id: "a".to_string(),
+                name: "Phone".to_string(),
+            },
+            DeviceInfo {
+                id: "b".to_string(),
+                name: "Tablet".to_string(),
+            },
+        ]))
+    }
+
+    /// Run the idle disconnect in the background, returns the events and the disconnected ids
+    fn start(
+        name: &str,
+        content: &str,
+        backend: Arc<SimulatedBackend>,
+    ) -> (mpsc::UnboundedSender<IdleEvent>, Arc<Mutex<Vec<String>>>) {
+        let config = Arc::new(test_config(name, content));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let disconnected = Arc::new(Mutex::new(vec![]));
+        tokio::spawn(IdleDisconnect::new(config, backend).run(receiver, {
+            let disconnected = disconnected.clone();
+            move |device_id| disconnected.lock().unwrap().push(device_id)
+        }));
+        (sender, disconnected)
+    }
+
+    fn connected(device_id: &str) -> IdleEvent {
+        IdleEvent::Connected {
+            device_id: device_id.to_string(),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn times_connections_from_the_start() {
+        let backend = backend();
+        backend.connect("a").unwrap();
+        let (_events, disconnected) = start("idle-start", "idle_timeout_minutes = 1\n", backend);
+
+        tokio::time::sleep(MINUTE - Duration::from_secs(1)).await;
+        assert!(disconnected.lock().unwrap().is_empty());
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(*disconnected.lock().unwrap(), ["a"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn activity_keeps_devices_connected() {
+        let (events, disconnected) =
+            start("idle-activity", "idle_timeout_minutes = 1\n", backend());
+        events.send(connected("a")).unwrap();
+        events.send(connected("b")).unwrap();
+
+        tokio::time::sleep(MINUTE / 2).await;
+        events
+            .send(IdleEvent::Activity {
+                device_id: Some("a".to_string()),
+            })
+            .unwrap();
+        tokio::time::sleep(MINUTE / 2 + Duration::from_secs(1)).await;
+        assert_eq!(*disconnected.lock().unwrap(), ["b"]);
+
+        // 播放音频时所有设备都保持连接
+        events
+            .send(IdleEvent::Activity { device_id: None })
+            .unwrap();
+        tokio::time::sleep(MINUTE - Duration::from_secs(1)).await;
+        assert_eq!(*disconnected.lock().unwrap(), ["b"]);
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(*disconnected.lock().unwrap(), ["b", "a"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn follows_the_timeouts_per_device() {
+        let (events, disconnected) = start(
+            "idle-per-device",
+            "idle_timeout_minutes = 1\n[idle_timeouts]\ntablet = 0\na = 3\n",
+            backend(),
+        );
+        events.send(connected("a")).unwrap();
+        events.send(connected("b")).unwrap();
+
+        tokio::time::sleep(MINUTE * 2).await;
+        assert!(disconnected.lock().unwrap().is_empty());
+        tokio::time::sleep(MINUTE * 2).await;
+        assert_eq!(*disconnected.lock().unwrap(), ["a"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stops_timing_disconnected_devices() {
+        let (events, disconnected) =
+            start("idle-disconnected", "idle_timeout_minutes = 1\n", backend());
+        events.send(connected("a")).unwrap();
+        tokio::time::sleep(MINUTE / 2).await;
+        events
+            .send(IdleEvent::Disconnected {
+                device_id: "a".to_string(),
+            })
+            .unwrap();
+
+        tokio::time::sleep(MINUTE * 2).await;
+        assert!(disconnected.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    app::{config::AppConfig, strings::UiStrings},
+    locales,
+};
+use rust_i18n::t;
+use tracing::log;
+
+/// 内置翻译的语言，`locales/` 中的翻译可以增加其他语言
+pub const LOCALES: [&str; 3] = ["en", "zh-CN", "zh-TW"];
+
+/// Locale used when none of the preferred languages is supported
+pub const FALLBACK_LOCALE: &str = "en";
+
+/// Built-in locales followed by the ones added by translation overlays
+pub fn available_locales() -> Vec<String> {
+    let mut available = LOCALES.map(str::to_string).to_vec();
+    if let Some(overlay) = locales::overlay() {
+        for locale in locales::overlay_locales(overlay) {
+            if !available.iter().any(|known| known == locale) {
+                available.push(locale.to_string());
+            }
+        }
+    }
+    available
+}
+
+/// Available locales with their name written in that language, for the tray menu
+pub fn available_languages() -> Vec<(String, String)> {
+    available_locales()
+        .into_iter()
+        .map(|locale| {
+            // 没有翻译语言名称的覆盖语言显示其代码，而不是回退的英文名称
+            let named = LOCALES.contains(&locale.as_str())
+                || locales::overlay()
+                    .and_then(|overlay| overlay.translations.get("language.name")?.get(&locale))
+                    .is_some();
+            let name = match named {
+                true => t!("language.name", locale = &locale).to_string(),
+                false => locale.clone(),
+            };
+            (locale, name)
+        })
+        .collect()
+}
+
+pub fn is_supported(language: &str) -> bool {
+    available_locales().iter().any(|locale| locale == language)
+}
+
+/// Language, script and region subtags of a BCP-47 tag, other subtags are ignored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageTag {
+    pub language: String,
+    pub script: Option<String>,
+    pub region: Option<String>,
+}
+
+impl LanguageTag {
+    /// Parse `zh-Hant-HK`, `en_GB` or `es-419`, returns `None` without a valid language subtag
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut subtags = tag.split(['-', '_']).filter(|subtag| !subtag.is_empty());
+
+        let language = subtags.next()?;
+        if !(2..=8).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic())
+        {
+            return None;
+        }
+
+        let mut parsed = Self {
+            language: language.to_ascii_lowercase(),
+            script: None,
+            region: None,
+        };
+        for subtag in subtags {
+            let alphabetic = subtag.chars().all(|c| c.is_ascii_alphabetic());
+            let numeric = subtag.chars().all(|c| c.is_ascii_digit());
+            match subtag.len() {
+                4 if alphabetic && parsed.script.is_none() && parsed.region.is_none() => {
+                    let (first, rest) = subtag.split_at(1);
+                    parsed.script =
+                        Some(first.to_ascii_uppercase() + rest.to_ascii_lowercase().as_str());
+                }
+                2 if alphabetic && parsed.region.is_none() => {
+                    parsed.region = Some(subtag.to_ascii_uppercase());
+                }
+                3 if numeric && parsed.region.is_none() => {
+                    parsed.region = Some(subtag.to_string());
+                }
+                // 扩展、变体等子标签不影响匹配
+                _ => break,
+            }
+        }
+        Some(parsed)
+    }
+
+    /// Fill in the script implied by the language and region, `zh-HK` is written in `Hant`
+    pub fn script(&self) -> Option<&str> {
+        if let Some(script) = &self.script {
+            return Some(script);
+        }
+        match (self.language.as_str(), self.region.as_deref()) {
+            ("zh", Some("TW" | "HK" | "MO")) => Some("Hant"),
+            ("zh", _) => Some("Hans"),
+            _ => None,
+        }
+    }
+
+    /// How well `self` fits the supported `locale`: same language, no conflicting script,
+    /// and a bonus for the same region. `None` if the language is not understood at all
+    fn score(&self, locale: &Self) -> Option<u8> {
+        if self.language != locale.language {
+            return None;
+        }
+        match (self.script(), locale.script()) {
+            (Some(a), Some(b)) if a != b => return None,
+            _ => {}
+        }
+        let same_region = self.region.is_some() && self.region == locale.region;
+        Some(if same_region { 2 } else { 1 })
+    }
+}
+
+/// Pick one of the `available` locales for the user's preferred languages, in order of
+/// preference
+///
+/// `zh-Hant-*`, `zh-HK` and `zh-MO` use `zh-TW`, other Chinese `zh-CN`, every `en-*` uses `en`.
+/// Unsupported languages are skipped, if none of them is supported [`FALLBACK_LOCALE`] is used.
+pub fn negotiate_locale<S: AsRef<str>>(preferred: &[S], available: &[String]) -> String {
+    let supported = available
+        .iter()
+        .filter_map(|locale| Some((locale, LanguageTag::parse(locale)?)))
+        .collect::<Vec<_>>();
+
+    for tag in preferred {
+        let Some(tag) = LanguageTag::parse(tag.as_ref()) else {
+            log::debug!("Ignoring invalid language tag {:?}", tag.as_ref());
+            continue;
+        };
+        // 同分时取列表中靠前的语言
+        let mut best: Option<(u8, &String)> = None;
+        for (locale, supported) in &supported {
+            if let Some(score) = tag.score(supported)
+                && best.is_none_or(|(best, _)| score > best)
+            {
+                best = Some((score, locale));
+            }
+        }
+        if let Some((_, locale)) = best {
+            return locale.clone();
+        }
+    }
+    FALLBACK_LOCALE.to_string()
+}
+
+/// Locale of the language pinned in the config, or the best match of the system languages
+pub fn resolve_locale(pinned: Option<&str>, system: &[String]) -> String {
+    if let Some(language) = pinned {
+        if is_supported(language) {
+            return language.to_string();
+        }
+        log::warn!("Unsupported language {language:?} in config, following the system");
+    }
+    negotiate_locale(system, &available_locales())
+}
+
+/// Pin `language` in the config, `None` to follow the system, returns the locale to use
+pub fn pin_language(config: &AppConfig, language: Option<&str>, system: &[String]) -> String {
+    config.set_language(language);
+    resolve_locale(language, system)
+}
+
+/// [`pin_language`], then switch the current locale and rebuild the strings of the user interface
+pub fn switch_language(config: &AppConfig, language: Option<&str>, system: &[String]) -> UiStrings {
+    let locale = pin_language(config, language, system);
+    rust_i18n::set_locale(&locale);
+    log::info!("Language set to {:?}, using locale {}", language, locale);
+
+    UiStrings::localized(&locale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{
+        AudioBackend, ConnectError, DeviceInfo, OpenStatus, SimulatedBackend, config::test_config,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn switching_rebuilds_the_device_statuses() {
+        let config = test_config("language", "auto_connect = true\n");
+        let backend = SimulatedBackend::new(["a", "b"].map(|id| DeviceInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+        }));
+        backend.connect("a").unwrap();
+        let failures = HashMap::from([(
+            "b".to_string(),
+            ConnectError::new(OpenStatus::RequestTimedOut, 0),
+        )]);
+        let system = ["en-GB".to_string()];
+        let content = || std::fs::read_to_string(&config.file_path).unwrap();
+
+        // 不切换全局的语言，并行的测试不受影响
+        let locale = pin_language(&config, Some("zh-CN"), &system);
+        assert_eq!(locale, "zh-CN");
+        assert_eq!(config.language().as_deref(), Some("zh-CN"));
+        assert!(content().contains("language = \"zh-CN\""));
+        let strings = UiStrings::localized(&locale);
+        assert_eq!(strings.tooltip, "LitSinkNexus: 蓝牙音频接收");
+        assert_eq!(strings.device_status.picker_title, "A2DP Sink: 选择源设备");
+        let statuses = strings
+            .device_status
+            .device_statuses(&backend, &failures)
+            .unwrap();
+        assert_eq!(statuses["a"].text, "已连接");
+        assert!(statuses["a"].connected);
+        assert_eq!(statuses["b"].text, "连接超时");
+        assert!(!statuses["b"].connected);
+
+        // 跟随系统时删除配置项
+        let locale = pin_language(&config, None, &system);
+        assert_eq!(locale, "en");
+        assert_eq!(config.language(), None);
+        assert!(!content().contains("language"));
+        let statuses = UiStrings::localized(&locale)
+            .device_status
+            .device_statuses(&backend, &failures)
+            .unwrap();
+        assert_eq!(statuses["a"].text, "Connected");
+        assert_eq!(statuses["b"].text, "Connection Timeout");
+        std::fs::remove_file(&config.file_path).unwrap();
+    }
+
+    #[test]
+    fn negotiates_locales() {
+        let available = LOCALES.map(str::to_string);
+        let cases: [(&[&str], &str); 14] = [
+            (&["zh-Hant-HK"], "zh-TW"),
+            (&["zh-HK"], "zh-TW"),
+            (&["zh-MO"], "zh-TW"),
+            (&["zh-Hant"], "zh-TW"),
+            (&["zh-SG"], "zh-CN"),
+            (&["zh-Hans-HK"], "zh-CN"),
+            (&["zh"], "zh-CN"),
+            (&["en-GB"], "en"),
+            (&["en_US"], "en"),
+            // 不支持的语言跳过，取下一个偏好
+            (&["es-419", "zh-TW"], "zh-TW"),
+            (&["es-419"], "en"),
+            (&["", "1x", "toolonglanguage", "zh-CN"], "zh-CN"),
+            (&["!!"], "en"),
+            (&[], "en"),
+        ];
+        for (preferred, expected) in cases {
+            assert_eq!(
+                negotiate_locale(preferred, &available),
+                expected,
+                "{preferred:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_language_tags() {
+        let tag = |language: &str, script: Option<&str>, region: Option<&str>| LanguageTag {
+            language: language.to_string(),
+            script: script.map(str::to_string),
+            region: region.map(str::to_string),
+        };
+        assert_eq!(
+            LanguageTag::parse("ZH-hant-hk"),
+            Some(tag("zh", Some("Hant"), Some("HK")))
+        );
+        assert_eq!(
+            LanguageTag::parse("es-419"),
+            Some(tag("es", None, Some("419")))
+        );
+        assert_eq!(
+            LanguageTag::parse("en-GB-oxendict"),
+            Some(tag("en", None, Some("GB")))
+        );
+        assert_eq!(LanguageTag::parse("zh-HK").unwrap().script(), Some("Hant"));
+        assert_eq!(LanguageTag::parse("zh-SG").unwrap().script(), Some("Hans"));
+        assert_eq!(LanguageTag::parse("x"), None);
+        assert_eq!(LanguageTag::parse("12-US"), None);
+    }
+
+    #[test]
+    fn prefers_the_same_region() {
+        let available = ["en", "en-GB", "zh-CN"].map(str::to_string);
+        assert_eq!(negotiate_locale(&["en-GB"], &available), "en-GB");
+        assert_eq!(negotiate_locale(&["en-AU"], &available), "en");
+    }
+}
@@ -1,7 +1,59 @@
+mod alias;
+#[cfg(windows)]
 mod app;
+#[cfg(windows)]
+mod audio_activity;
+mod autostart;
+mod backend;
 mod config;
+mod connect_error;
+#[cfg(windows)]
 mod connection_manager;
+mod control;
+mod exclusive;
+mod history;
+mod hooks;
+mod idle;
+mod language;
+#[cfg(windows)]
 mod notify_icon;
+mod paths;
+mod presence;
+#[cfg(windows)]
+mod presence_watcher;
+mod registry;
+mod resume;
+#[cfg(windows)]
+mod run_key;
+mod schedule;
+mod script;
+mod simulated;
+mod strings;
+#[cfg(windows)]
+mod winrt_backend;
 
+pub use alias::*;
+#[cfg(windows)]
 pub use app::*;
+pub use autostart::*;
+pub use backend::*;
 pub use config::*;
+pub use connect_error::*;
+pub use control::*;
+pub use exclusive::*;
+pub use history::*;
+pub use hooks::*;
+pub use idle::*;
+pub use language::*;
+pub use paths::*;
+pub use presence::*;
+pub use registry::*;
+pub use resume::*;
+#[cfg(windows)]
+pub use run_key::*;
+pub use schedule::*;
+pub use script::*;
+pub use simulated::*;
+pub use strings::*;
+#[cfg(windows)]
+pub use winrt_backend::*;
@@ -1,53 +1,54 @@
-use std::sync::Arc;
+use std::{
+    cell::{Cell, RefCell},
+    sync::Arc,
+};
 
 use crate::{
     app::{
+        autostart::{Autostart, AutostartCommand},
+        backend::AudioBackend,
+        backend::DeviceInfo,
         config::AppConfig,
-        connection_manager::{ConnectionManager, DeviceStatusStrings},
+        connection_manager::ConnectionManager,
+        control::DeviceControl,
+        language::{available_languages, switch_language},
+        run_key::RunKey,
+        strings::{MenuStrings, UiStrings, tooltip_text},
     },
     internal::*,
-    resource::APP_ICON,
+    resource::{APP_ICON, APP_ICON_LIGHT_TASKBAR},
 };
 use anyhow::Context;
-use rust_i18n::t;
 use tracing::log;
 use windows::{
     Foundation::{Rect, Uri},
     System::Launcher,
     Win32::{
         Foundation::*,
-        System::LibraryLoader::GetModuleHandleW,
-        UI::{HiDpi::GetDpiForWindow, Shell::*, WindowsAndMessaging::*},
+        System::{LibraryLoader::GetModuleHandleW, Registry::*},
+        UI::{
+            HiDpi::{GetDpiForWindow, GetSystemMetricsForDpi},
+            Shell::*,
+            WindowsAndMessaging::*,
+        },
     },
     core::*,
 };
 
-#[derive(Debug, Clone)]
-pub struct MenuStrings {
-    pub connection_list: String,
-    pub bluetooth_list: String,
-    pub auto_connect: String,
-    pub exit: String,
-}
-
-impl Default for MenuStrings {
-    fn default() -> Self {
-        Self {
-            connection_list: "Open Connection List(&C)".to_string(),
-            bluetooth_list: "Add Bluetooth Device(&B)".to_string(),
-            auto_connect: "Automatically connect at startup(&A)".to_string(),
-            exit: "Exit(&X)".to_string(),
-        }
-    }
-}
-
 pub struct NotifyIcon {
     window: HWND,
     config: Arc<AppConfig>,
-    data: NOTIFYICONDATAW,
+    data: RefCell<NOTIFYICONDATAW>,
+    /// The icon in `data` was loaded by us, the fallback `IDI_APPLICATION` is shared
+    owns_icon: Cell<bool>,
+    /// Title of the tooltip, the connected devices are listed below it
+    tooltip: RefCell<String>,
     notify_icon_id: NOTIFYICONIDENTIFIER,
     manager: ConnectionManager,
-    menu_str: MenuStrings,
+    backend: Arc<dyn AudioBackend>,
+    menu_str: RefCell<MenuStrings>,
+    autostart: Autostart<RunKey>,
+    autostart_command: AutostartCommand,
 }
 
 #[allow(unused)]
@@ -56,55 +57,111 @@ impl NotifyIcon {
     const IDM_CONNECTION: u32 = 1002;
     const IDM_DEVICES: u32 = 1003;
     const IDM_AUTO_CONNECT: u32 = 1004;
+    const IDM_AUTOSTART: u32 = 1005;
+    const IDM_EXCLUSIVE: u32 = 1006;
+    /// 跟随系统，之后依次为 [`available_languages`] 中的语言
+    const IDM_LANGUAGE_BASE: u32 = 1100;
 
     pub fn new(
         window: HWND,
         config: Arc<AppConfig>,
         callback_message: u32,
-        strings: MenuStrings,
+        enumerated_message: u32,
+        strings: UiStrings,
+        backend: Arc<dyn AudioBackend>,
+        autostart_command: AutostartCommand,
     ) -> anyhow::Result<Self> {
         let module = unsafe { GetModuleHandleW(None) }
             .context("Fail to get HMODULE handle for the current application")?;
         let instance = HINSTANCE::from(module);
 
-        let icon = unsafe { LoadIconW(Some(instance), PCWSTR::from_raw(APP_ICON as _)) }
-            .unwrap_or(unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap());
+        let loaded = Self::load_tray_icon(window, instance);
+        let owns_icon = loaded.is_ok();
+        let icon = loaded.unwrap_or(unsafe { LoadIconW(None, IDI_APPLICATION) }.unwrap());
+
+        let mut data = NOTIFYICONDATAW {
+            hWnd: window,
+            uFlags: NIF_ICON | NIF_MESSAGE | NIF_TIP | NIF_SHOWTIP,
+            uCallbackMessage: callback_message,
+            Anonymous: NOTIFYICONDATAW_0 { uVersion: 4 },
+            hIcon: icon,
+            ..Default::default()
+        };
+        set_tip(&mut data, &strings.tooltip);
 
         Ok(Self {
             window,
-            data: NOTIFYICONDATAW {
-                hWnd: window,
-                uFlags: NIF_ICON | NIF_MESSAGE | NIF_TIP | NIF_SHOWTIP,
-                uCallbackMessage: callback_message,
-                Anonymous: NOTIFYICONDATAW_0 { uVersion: 4 },
-                hIcon: icon,
-                ..Default::default()
-            }
-            .into(),
+            data: RefCell::new(data),
+            owns_icon: Cell::new(owns_icon),
+            tooltip: RefCell::new(strings.tooltip),
             notify_icon_id: NOTIFYICONIDENTIFIER {
                 cbSize: size_of::<NOTIFYICONIDENTIFIER>() as u32,
                 hWnd: window,
                 ..Default::default()
             },
-            menu_str: strings,
+            menu_str: RefCell::new(strings.menu),
+            autostart: Autostart::new(RunKey),
+            autostart_command,
             manager: ConnectionManager::new(
                 window,
+                enumerated_message,
                 config.clone(),
-                DeviceStatusStrings {
-                    picker_title: t!("connection_manager.picker_title").to_string(),
-                    timeout: t!("connection_manager.timeout").to_string(),
-                    connecting: t!("connection_manager.connecting").to_string(),
-                    connected: t!("connection_manager.connected").to_string(),
-                    denied_by_system: t!("connection_manager.denied_by_system").to_string(),
-                    not_found: t!("connection_manager.not_found").to_string(),
-                    unknown_reason: t!("connection_manager.unknown_reason").to_string(),
-                    disconnected: t!("connection_manager.disconnected").to_string(),
-                },
+                strings.device_status,
+                backend.clone(),
             )?,
+            backend,
             config,
         })
     }
 
+    /// Load the frame of the multi-size icon matching the tray size and the taskbar theme
+    fn load_tray_icon(window: HWND, instance: HINSTANCE) -> anyhow::Result<HICON> {
+        let id = match taskbar_uses_light_theme() {
+            true => APP_ICON_LIGHT_TASKBAR,
+            false => APP_ICON,
+        };
+        let dpi = unsafe { GetDpiForWindow(window) };
+        let (cx, cy) = unsafe {
+            (
+                GetSystemMetricsForDpi(SM_CXSMICON, dpi),
+                GetSystemMetricsForDpi(SM_CYSMICON, dpi),
+            )
+        };
+
+        let icon = unsafe {
+            LoadImageW(
+                Some(instance),
+                PCWSTR::from_raw(id as _),
+                IMAGE_ICON,
+                cx,
+                cy,
+                LR_DEFAULTCOLOR,
+            )
+        }
+        .context("Failed to load tray icon")?;
+        Ok(HICON(icon.0))
+    }
+
+    /// Load the icon for the current taskbar theme, called when the user switched the theme
+    pub fn reload_icon(&self) -> anyhow::Result<()> {
+        let module = unsafe { GetModuleHandleW(None) }
+            .context("Fail to get HMODULE handle for the current application")?;
+        let icon = Self::load_tray_icon(self.window, HINSTANCE::from(module))?;
+
+        let previous = {
+            let mut data = self.data.borrow_mut();
+            let previous = std::mem::replace(&mut data.hIcon, icon);
+            unsafe { Shell_NotifyIconW(NIM_MODIFY, &*data) }
+                .context("Failed to update tray icon")?;
+            previous
+        };
+        if self.owns_icon.replace(true) {
+            unsafe { DestroyIcon(previous) }.warn("Failed to destroy the previous tray icon");
+        }
+        log::debug!("Tray icon reloaded for the taskbar theme");
+        Ok(())
+    }
+
     pub fn show_menu(&self) -> anyhow::Result<()> {
         let mut point = POINT::default();
         unsafe { GetCursorPos(&mut point) }.context("Failed to get cursor position")?;
@@ -113,7 +170,34 @@ impl NotifyIcon {
         unsafe { SetForegroundWindow(self.window) }.warn("Fail to set foreground window");
 
         let hmenu = unsafe { CreatePopupMenu() }.context("Failed to create popup menu")?;
-        let strings = self.menu_str.clone();
+        let strings = self.menu_str.borrow().clone();
+
+        // 已连接的设备只用于显示
+        let connected = self.connected_names();
+        for name in &connected {
+            unsafe {
+                AppendMenuW(
+                    hmenu,
+                    MF_STRING | MF_CHECKED | MF_GRAYED,
+                    0,
+                    PCWSTR::from_raw(HSTRING::from(name).as_ptr()),
+                )
+            }?;
+        }
+        let idle = self.manager.idle_statuses();
+        for status in &idle {
+            unsafe {
+                AppendMenuW(
+                    hmenu,
+                    MF_STRING | MF_GRAYED,
+                    0,
+                    PCWSTR::from_raw(HSTRING::from(status).as_ptr()),
+                )
+            }?;
+        }
+        if !connected.is_empty() || !idle.is_empty() {
+            unsafe { AppendMenuW(hmenu, MF_SEPARATOR, 0, PCWSTR::null()) }?;
+        }
 
         unsafe {
             AppendMenuW(
@@ -149,6 +233,46 @@ impl NotifyIcon {
             )
         }?;
 
+        let checked = match self.config.exclusive() {
+            true => MF_CHECKED,
+            false => MF_UNCHECKED,
+        };
+        unsafe {
+            AppendMenuW(
+                hmenu,
+                MF_STRING | checked,
+                Self::IDM_EXCLUSIVE as usize,
+                PCWSTR::from_raw(HSTRING::from(strings.exclusive).as_ptr()),
+            )
+        }?;
+
+        let checked = match self.autostart.is_enabled() {
+            Ok(true) => MF_CHECKED,
+            Ok(false) => MF_UNCHECKED,
+            Err(e) => {
+                log::warn!("Fail to query autostart entry: {:?}", e);
+                MF_UNCHECKED
+            }
+        };
+        unsafe {
+            AppendMenuW(
+                hmenu,
+                MF_STRING | checked,
+                Self::IDM_AUTOSTART as usize,
+                PCWSTR::from_raw(HSTRING::from(strings.autostart).as_ptr()),
+            )
+        }?;
+
+        let language_menu = self.language_menu(&strings.follow_system)?;
+        unsafe {
+            AppendMenuW(
+                hmenu,
+                MF_STRING | MF_POPUP,
+                language_menu.0 as usize,
+                PCWSTR::from_raw(HSTRING::from(strings.language).as_ptr()),
+            )
+        }?;
+
         unsafe { AppendMenuW(hmenu, MF_SEPARATOR, 0, PCWSTR::null()) }.unwrap();
 
         unsafe {
@@ -176,6 +300,35 @@ impl NotifyIcon {
         Ok(())
     }
 
+    /// "Follow System" and the supported languages, the current choice is checked
+    fn language_menu(&self, follow_system: &str) -> anyhow::Result<HMENU> {
+        let hmenu = unsafe { CreatePopupMenu() }.context("Failed to create language menu")?;
+        let pinned = self.config.language();
+
+        let checked = |selected: bool| if selected { MF_CHECKED } else { MF_UNCHECKED };
+        unsafe {
+            AppendMenuW(
+                hmenu,
+                MF_STRING | checked(pinned.is_none()),
+                Self::IDM_LANGUAGE_BASE as usize,
+                PCWSTR::from_raw(HSTRING::from(follow_system).as_ptr()),
+            )
+        }?;
+        unsafe { AppendMenuW(hmenu, MF_SEPARATOR, 0, PCWSTR::null()) }?;
+
+        for (index, (locale, name)) in available_languages().iter().enumerate() {
+            unsafe {
+                AppendMenuW(
+                    hmenu,
+                    MF_STRING | checked(pinned.as_ref() == Some(locale)),
+                    (Self::IDM_LANGUAGE_BASE + 1 + index as u32) as usize,
+                    PCWSTR::from_raw(HSTRING::from(name).as_ptr()),
+                )
+            }?;
+        }
+        Ok(hmenu)
+    }
+
     pub fn show_picker(&self, x: i32, y: i32) -> anyhow::Result<()> {
         self.manager
             .show(Rect {
@@ -188,15 +341,16 @@ impl NotifyIcon {
     }
 
     pub fn add(&self) -> anyhow::Result<()> {
-        unsafe { Shell_NotifyIconW(NIM_ADD, &self.data) }.context("Failed to add tray icon")?;
-        unsafe { Shell_NotifyIconW(NIM_SETVERSION, &self.data) }
+        let data = self.data.borrow();
+        unsafe { Shell_NotifyIconW(NIM_ADD, &*data) }.context("Failed to add tray icon")?;
+        unsafe { Shell_NotifyIconW(NIM_SETVERSION, &*data) }
             .context("Fail to set NotifyIcon's Version")?;
 
         Ok(())
     }
 
     pub fn delete(&self) -> anyhow::Result<()> {
-        unsafe { Shell_NotifyIconW(NIM_DELETE, &self.data) }
+        unsafe { Shell_NotifyIconW(NIM_DELETE, &*self.data.borrow()) }
             .context("Failed to remove tray icon")?;
         Ok(())
     }
@@ -234,14 +388,116 @@ impl NotifyIcon {
                 self.config.set_auto_connect(!auto_connect);
                 log::info!("Auto Connect set to {}", !auto_connect);
             }
+            Self::IDM_EXCLUSIVE => {
+                let exclusive = !self.config.exclusive();
+                self.config.set_exclusive(exclusive);
+                log::info!("Exclusive mode set to {}", exclusive);
+            }
+            Self::IDM_AUTOSTART => {
+                self.toggle_autostart().warn("Fail to toggle autostart");
+            }
             Self::IDM_EXIT => {
                 unsafe { PostQuitMessage(0) };
             }
+            id if id >= Self::IDM_LANGUAGE_BASE => {
+                let languages = available_languages();
+                let language = match (id - Self::IDM_LANGUAGE_BASE) as usize {
+                    0 => None,
+                    index => match languages.get(index - 1) {
+                        Some((locale, _)) => Some(locale.as_str()),
+                        None => return Ok(()),
+                    },
+                };
+                let strings = switch_language(&self.config, language, &user_preferred_languages());
+                self.apply_strings(strings)
+                    .warn("Fail to apply strings of the new language");
+            }
             _ => {}
         }
         Ok(())
     }
 
+    /// Rebuild the tooltip, menu and picker with the strings of another language
+    fn apply_strings(&self, strings: UiStrings) -> anyhow::Result<()> {
+        *self.menu_str.borrow_mut() = strings.menu;
+        *self.tooltip.borrow_mut() = strings.tooltip;
+        self.refresh_tooltip()?;
+
+        self.manager.set_strings(strings.device_status)
+    }
+
+    /// Connect and disconnect through the connection manager
+    pub fn control(&self) -> Arc<dyn DeviceControl> {
+        self.manager.control()
+    }
+
+    /// Continue the startup of the connection manager once the paired devices are known
+    pub fn devices_enumerated(&self, timed_out: bool) -> anyhow::Result<()> {
+        self.manager.finish_enumeration(timed_out)
+    }
+
+    /// Show the connected and idle disconnected devices in the tooltip, called when the
+    /// connections changed
+    pub fn refresh_tooltip(&self) -> anyhow::Result<()> {
+        let mut devices = self.connected_names();
+        devices.extend(self.manager.idle_statuses());
+        let tip = tooltip_text(&self.tooltip.borrow(), &devices);
+        let mut data = self.data.borrow_mut();
+        set_tip(&mut data, &tip);
+        unsafe { Shell_NotifyIconW(NIM_MODIFY, &*data) }.context("Failed to update tooltip")?;
+        Ok(())
+    }
+
+    /// Show a balloon notification with the tooltip title
+    pub fn show_notification(&self, text: &str) -> anyhow::Result<()> {
+        // 只修改通知，不改变保存的图标与提示
+        let mut data = *self.data.borrow();
+        data.uFlags = NIF_INFO;
+        data.dwInfoFlags = NIIF_INFO;
+        copy_wide(&mut data.szInfoTitle, &self.tooltip.borrow());
+        copy_wide(&mut data.szInfo, text);
+        unsafe { Shell_NotifyIconW(NIM_MODIFY, &data) }.context("Failed to show notification")?;
+        Ok(())
+    }
+
+    /// Display names of the connected devices
+    fn connected_names(&self) -> Vec<String> {
+        let connections = match self.backend.connections() {
+            Ok(connections) => connections,
+            Err(e) => {
+                log::warn!("Failed to query connections: {:?}", e);
+                return vec![];
+            }
+        };
+        let names = self.config.device_names();
+        let mut connected = connections
+            .into_iter()
+            .map(|device_id| {
+                let device = self.manager.device(&device_id).unwrap_or(DeviceInfo {
+                    id: device_id,
+                    name: String::new(),
+                });
+                names.device_name(&device)
+            })
+            .collect::<Vec<_>>();
+        connected.sort();
+        connected
+    }
+
+    fn toggle_autostart(&self) -> anyhow::Result<()> {
+        if self.autostart.is_enabled()? {
+            self.autostart.disable()?;
+            log::info!("Autostart disabled");
+        } else {
+            let command = self
+                .autostart_command
+                .with_delay(self.config.autostart_delay());
+            self.autostart.enable(&command)?;
+            log::info!("Autostart enabled: {}", command.command_line());
+        }
+        Ok(())
+    }
+
     fn show_connection_list(&self) -> anyhow::Result<()> {
         let rect = unsafe { Shell_NotifyIconGetRect(&self.notify_icon_id) }
             .context("Fail to get notify icon rect")?;
@@ -257,3 +513,36 @@ impl NotifyIcon {
             .context("Fail to show popup of connections")
     }
 }
+
+/// `szTip` 最多127个字符，超出部分截断
+fn set_tip(data: &mut NOTIFYICONDATAW, tip: &str) {
+    copy_wide(&mut data.szTip, tip);
+}
+
+/// Copy `text` into the fixed size buffer, leaving room for the terminating zero
+fn copy_wide(buffer: &mut [u16], text: &str) {
+    let text = text
+        .encode_utf16()
+        .take(buffer.len() - 1)
+        .collect::<Vec<_>>();
+    buffer.fill(0);
+    buffer[..text.len()].copy_from_slice(&text);
+}
+
+/// `SystemUsesLightTheme` 为1时任务栏是浅色的
+fn taskbar_uses_light_theme() -> bool {
+    let mut value = 0u32;
+    let mut size = size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!(r"Software\Microsoft\Windows\CurrentVersion\Themes\Personalize"),
+            w!("SystemUsesLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut value as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    };
+    status == ERROR_SUCCESS && value == 1
+}
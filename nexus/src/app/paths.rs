@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+/// 与可执行文件放在一起时，配置与日志保存在可执行文件所在目录
+pub const PORTABLE_MARKER: &str = "portable.marker";
+
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+pub const DEFAULT_LOG_DIR: &str = "logs";
+/// 翻译覆盖文件所在的目录，与配置文件放在一起
+pub const LOCALES_DIR: &str = "locales";
+/// 连接历史，与配置文件放在一起
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+/// 配置文件与日志目录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppPaths {
+    pub config: PathBuf,
+    pub log: PathBuf,
+    pub portable: bool,
+}
+
+impl AppPaths {
+    /// Paths given on the command line win, the defaults are next to the executable in portable
+    /// mode and relative to the working directory otherwise
+    pub fn resolve(exe_dir: Option<&Path>, config: Option<PathBuf>, log: Option<PathBuf>) -> Self {
+        let portable_dir = exe_dir.filter(|dir| dir.join(PORTABLE_MARKER).is_file());
+        let default_path = |name: &str| match portable_dir {
+            Some(dir) => dir.join(name),
+            None => PathBuf::from(name),
+        };
+
+        Self {
+            config: config.unwrap_or_else(|| default_path(DEFAULT_CONFIG_FILE)),
+            log: log.unwrap_or_else(|| default_path(DEFAULT_LOG_DIR)),
+            portable: portable_dir.is_some(),
+        }
+    }
+
+    /// `locales/` next to the config file
+    pub fn locales(&self) -> PathBuf {
+        self.next_to_config(LOCALES_DIR)
+    }
+
+    /// `history.jsonl` next to the config file
+    pub fn history(&self) -> PathBuf {
+        self.next_to_config(HISTORY_FILE)
+    }
+
+    fn next_to_config(&self, name: &str) -> PathBuf {
+        self.config.parent().unwrap_or(Path::new("")).join(name)
+    }
+
+    /// Resolve against the directory of the running executable
+    pub fn for_current_exe(config: Option<PathBuf>, log: Option<PathBuf>) -> Self {
+        let exe = std::env::current_exe().ok();
+        Self::resolve(exe.as_deref().and_then(Path::parent), config, log)
+    }
+}
@@ -0,0 +1,265 @@
+use crate::app::{
+    backend::{AudioBackend, DeviceInfo},
+    config::AppConfig,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::log;
+
+/// 已配对的源设备进入或离开蓝牙范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresenceEvent {
+    pub device: DeviceInfo,
+    pub present: bool,
+}
+
+/// 设备需要在范围内稳定一段时间才算出现，避免信号边缘反复连接
+#[derive(Debug)]
+pub struct PresenceDebouncer {
+    debounce: Duration,
+    /// 出现但还没稳定的设备及其出现的时间
+    pending: HashMap<String, (DeviceInfo, Instant)>,
+    present: HashSet<String>,
+}
+
+impl PresenceDebouncer {
+    pub fn new(debounce: Duration) -> Self {
+        Self {
+            debounce,
+            pending: Default::default(),
+            present: Default::default(),
+        }
+    }
+
+    pub fn update(&mut self, event: PresenceEvent, now: Instant) {
+        let id = event.device.id.clone();
+        if event.present {
+            if !self.present.contains(&id) {
+                self.pending.entry(id).or_insert((event.device, now));
+            }
+        } else {
+            self.pending.remove(&id);
+            self.present.remove(&id);
+        }
+    }
+
+    /// Devices present for the whole debounce window since their last appearance
+    pub fn due(&mut self, now: Instant) -> Vec<DeviceInfo> {
+        let due = self
+            .pending
+            .iter()
+            .filter(|(_, (_, since))| now >= *since + self.debounce)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+
+        let mut devices = due
+            .into_iter()
+            .filter_map(|id| {
+                let (device, _) = self.pending.remove(&id)?;
+                self.present.insert(id);
+                Some(device)
+            })
+            .collect::<Vec<_>>();
+        devices.sort_by(|a, b| a.id.cmp(&b.id));
+        devices
+    }
+
+    /// When the next pending device becomes due
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending
+            .values()
+            .map(|(_, since)| *since + self.debounce)
+            .min()
+    }
+}
+
+/// 设备进入范围时按配置自动连接
+pub struct PresenceAutoConnect {
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    debouncer: PresenceDebouncer,
+}
+
+impl PresenceAutoConnect {
+    pub fn new(config: Arc<AppConfig>, backend: Arc<dyn AudioBackend>) -> Self {
+        let debouncer = PresenceDebouncer::new(config.presence_debounce());
+        Self {
+            config,
+            backend,
+            debouncer,
+        }
+    }
+
+    /// Follow `feed` until it closes, calling `connect` for the devices to connect
+    pub async fn run(
+        mut self,
+        mut feed: mpsc::UnboundedReceiver<PresenceEvent>,
+        connect: impl Fn(DeviceInfo),
+    ) {
+        loop {
+            let deadline = self.debouncer.next_deadline();
+            tokio::select! {
+                event = feed.recv() => match event {
+                    Some(event) => {
+                        log::debug!("Presence: {:?}", event);
+                        self.debouncer.update(event, Instant::now());
+                    }
+                    None => break,
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                    if deadline.is_some() => {}
+            }
+
+            for device in self.debouncer.due(Instant::now()) {
+                if self.should_connect(&device) {
+                    log::info!(
+                        "Device came into range: {}({})",
+                        self.config.device_names().device_name(&device),
+                        device.id
+                    );
+                    connect(device);
+                }
+            }
+        }
+    }
+
+    fn should_connect(&self, device: &DeviceInfo) -> bool {
+        let policy = self.config.auto_connect_policy();
+        if !policy.on_presence || !policy.allows(device) {
+            return false;
+        }
+        match self.backend.connections() {
+            Ok(connections) => !connections.contains(&device.id),
+            Err(e) => {
+                log::warn!("Failed to query connections: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{AudioBackend, SimulatedBackend, config::test_config};
+    use std::sync::Mutex;
+
+    fn device(id: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+        }
+    }
+
+    fn event(id: &str, present: bool) -> PresenceEvent {
+        PresenceEvent {
+            device: device(id),
+            present,
+        }
+    }
+
+    /// Run the auto connect in the background, returns the feed and the connected ids
+    fn start(
+        name: &str,
+        content: &str,
+        backend: Arc<SimulatedBackend>,
+    ) -> (
+        mpsc::UnboundedSender<PresenceEvent>,
+        Arc<Mutex<Vec<String>>>,
+    ) {
+        let config = Arc::new(test_config(name, content));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let connected = Arc::new(Mutex::new(vec![]));
+        tokio::spawn(PresenceAutoConnect::new(config, backend).run(receiver, {
+            let connected = connected.clone();
+            move |device| connected.lock().unwrap().push(device.id)
+        }));
+        (sender, connected)
+    }
+
+    async fn advance(duration: Duration) {
+        tokio::time::sleep(duration).await;
+    }
+
+    #[test]
+    fn debounces_devices_at_the_edge_of_range() {
+        let start = Instant::now();
+        let mut debouncer = PresenceDebouncer::new(Duration::from_secs(5));
+        debouncer.update(event("a", true), start);
+        assert_eq!(
+            debouncer.next_deadline(),
+            Some(start + Duration::from_secs(5))
+        );
+        debouncer.update(event("a", false), start + Duration::from_secs(2));
+        debouncer.update(event("a", true), start + Duration::from_secs(3));
+        assert_eq!(debouncer.due(start + Duration::from_secs(5)), vec![]);
+        assert_eq!(
+            debouncer.due(start + Duration::from_secs(8)),
+            vec![device("a")]
+        );
+        // 仍在范围内时不会再次到期
+        debouncer.update(event("a", true), start + Duration::from_secs(9));
+        assert_eq!(debouncer.next_deadline(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connects_devices_which_stay_in_range() {
+        let backend = Arc::new(SimulatedBackend::new([device("a"), device("b")]));
+        let (feed, connected) = start(
+            "presence-stay",
+            "auto_connect_on_presence = true\npresence_debounce_secs = 5\n",
+            backend,
+        );
+
+        feed.send(event("a", true)).unwrap();
+        feed.send(event("b", true)).unwrap();
+        advance(Duration::from_secs(2)).await;
+        feed.send(event("b", false)).unwrap();
+        advance(Duration::from_secs(2)).await;
+        assert!(connected.lock().unwrap().is_empty());
+
+        advance(Duration::from_secs(2)).await;
+        assert_eq!(*connected.lock().unwrap(), ["a"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn follows_the_policy() {
+        let backend = Arc::new(SimulatedBackend::new([
+            device("a"),
+            device("b"),
+            device("c"),
+        ]));
+        backend.connect("c").unwrap();
+        let (feed, connected) = start(
+            "presence-policy",
+            "auto_connect_on_presence = true\npresence_debounce_secs = 1\n\
+             auto_connect_devices = [\"a\", \"C\"]\n",
+            backend,
+        );
+
+        // b 不在允许列表中，c 已经连接
+        for id in ["a", "b", "c"] {
+            feed.send(event(id, true)).unwrap();
+        }
+        advance(Duration::from_secs(2)).await;
+        assert_eq!(*connected.lock().unwrap(), ["a"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn does_nothing_when_disabled() {
+        let backend = Arc::new(SimulatedBackend::new([device("a")]));
+        let (feed, connected) = start(
+            "presence-disabled",
+            "auto_connect_on_presence = false\npresence_debounce_secs = 1\n",
+            backend,
+        );
+
+        feed.send(event("a", true)).unwrap();
+        advance(Duration::from_secs(2)).await;
+        assert!(connected.lock().unwrap().is_empty());
+    }
+}
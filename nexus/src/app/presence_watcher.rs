@@ -0,0 +1,135 @@
+use crate::app::{backend::DeviceInfo, presence::PresenceEvent};
+use anyhow::Context;
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+use tokio::sync::mpsc;
+use tracing::log;
+use windows::{
+    Devices::{Bluetooth::BluetoothDevice, Enumeration::*},
+    Foundation::{IReference, TypedEventHandler},
+    core::*,
+};
+use windows_collections::IIterable;
+
+pub const CONTAINER_ID: &str = "System.Devices.ContainerId";
+const AEP_CONTAINER_ID: &str = "System.Devices.Aep.ContainerId";
+const AEP_IS_PRESENT: &str = "System.Devices.Aep.IsPresent";
+
+/// Find the A2DP source of a Bluetooth device by the container both belong to
+pub type ContainerLookup = Arc<dyn Fn(&str) -> Option<DeviceInfo> + Send + Sync>;
+
+/// 通过已配对蓝牙设备关联端点的 `IsPresent` 属性报告设备是否在范围内
+pub struct PresenceWatcher {
+    watcher: DeviceWatcher,
+}
+
+impl PresenceWatcher {
+    pub fn start(
+        lookup: ContainerLookup,
+        sender: mpsc::UnboundedSender<PresenceEvent>,
+    ) -> anyhow::Result<Self> {
+        let properties = IIterable::<HSTRING>::from(vec![
+            HSTRING::from(AEP_CONTAINER_ID),
+            HSTRING::from(AEP_IS_PRESENT),
+        ]);
+        let watcher = DeviceInformation::CreateWatcherWithKindAqsFilterAndAdditionalProperties(
+            &BluetoothDevice::GetDeviceSelectorFromPairingState(true)?,
+            &properties,
+            DeviceInformationKind::AssociationEndpoint,
+        )
+        .context("Failed to create presence watcher")?;
+
+        // 更新事件中只有变化的属性，需要记住每个端点所属的容器
+        let containers = Arc::new(Mutex::new(HashMap::<HSTRING, String>::new()));
+
+        watcher.Added(&TypedEventHandler::<DeviceWatcher, DeviceInformation>::new(
+            {
+                let containers = containers.clone();
+                let lookup = lookup.clone();
+                let sender = sender.clone();
+                move |_, endpoint| {
+                    let endpoint = endpoint.as_ref().unwrap();
+                    let properties = endpoint.Properties()?;
+                    let Some(container) = guid_property(&properties, AEP_CONTAINER_ID) else {
+                        return Ok(());
+                    };
+                    containers
+                        .lock()
+                        .unwrap()
+                        .insert(endpoint.Id()?, container.clone());
+
+                    if let Some(present) = bool_property(&properties, AEP_IS_PRESENT) {
+                        send(&lookup, &sender, &container, present);
+                    }
+                    Ok(())
+                }
+            },
+        ))?;
+
+        watcher.Updated(
+            &TypedEventHandler::<DeviceWatcher, DeviceInformationUpdate>::new({
+                let containers = containers.clone();
+                move |_, update| {
+                    let update = update.as_ref().unwrap();
+                    let Some(present) = bool_property(&update.Properties()?, AEP_IS_PRESENT) else {
+                        return Ok(());
+                    };
+                    let container = containers.lock().unwrap().get(&update.Id()?).cloned();
+                    if let Some(container) = container {
+                        send(&lookup, &sender, &container, present);
+                    }
+                    Ok(())
+                }
+            }),
+        )?;
+
+        watcher.Removed(
+            &TypedEventHandler::<DeviceWatcher, DeviceInformationUpdate>::new(move |_, update| {
+                containers
+                    .lock()
+                    .unwrap()
+                    .remove(&update.as_ref().unwrap().Id()?);
+                Ok(())
+            }),
+        )?;
+
+        watcher.Start()?;
+        Ok(Self { watcher })
+    }
+}
+
+impl Drop for PresenceWatcher {
+    fn drop(&mut self) {
+        if let Err(e) = self.watcher.Stop() {
+            log::debug!("Fail to stop presence watcher: {:?}", e);
+        }
+    }
+}
+
+fn send(
+    lookup: &ContainerLookup,
+    sender: &mpsc::UnboundedSender<PresenceEvent>,
+    container: &str,
+    present: bool,
+) {
+    if let Some(device) = lookup(container) {
+        let _ = sender.send(PresenceEvent { device, present });
+    }
+}
+
+fn bool_property(
+    properties: &windows_collections::IMapView<HSTRING, IInspectable>,
+    name: &str,
+) -> Option<bool> {
+    let value = properties.Lookup(&HSTRING::from(name)).ok()?;
+    value.cast::<IReference<bool>>().ok()?.Value().ok()
+}
+
+/// Container ids are compared as `{XXXXXXXX-...}` strings
+pub fn guid_property(
+    properties: &windows_collections::IMapView<HSTRING, IInspectable>,
+    name: &str,
+) -> Option<String> {
+    let value = properties.Lookup(&HSTRING::from(name)).ok()?;
+    let guid = value.cast::<IReference<GUID>>().ok()?.Value().ok()?;
+    Some(format!("{guid:?}"))
+}
@@ -0,0 +1,348 @@
+use crate::app::backend::DeviceInfo;
+use std::collections::BTreeMap;
+use tracing::log;
+
+/// 设备监视器报告的变化，Windows上来自 `DeviceWatcher`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatcherEvent {
+    Added(DeviceInfo),
+    /// Properties such as the name changed
+    Updated(DeviceInfo),
+    Removed(String),
+    /// Every device paired before the watcher started has been reported
+    EnumerationCompleted,
+}
+
+/// What the picker and the connections need to follow a [`WatcherEvent`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryAction {
+    /// Show the current status of the device in the picker
+    ShowStatus(String),
+    /// The device is gone, drop what is kept about it
+    Forget(String),
+    /// Connect the device automatically
+    Connect(String),
+    /// Connect the first of the devices which succeeds, one after the other, in exclusive mode
+    ConnectFirst(Vec<String>),
+}
+
+/// 哪些设备可以自动连接
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AutoConnectPolicy {
+    /// Connect when the first enumeration completed
+    pub at_startup: bool,
+    /// Connect devices paired while the application runs
+    pub new_devices: bool,
+    /// Connect devices when they come into range
+    pub on_presence: bool,
+    /// Ids or names of the devices which may be connected automatically, empty allows every one
+    pub allowed: Vec<String>,
+    /// Only one source at a time, see [`Handover`](crate::app::Handover)
+    pub exclusive: bool,
+}
+
+impl AutoConnectPolicy {
+    pub fn allows(&self, device: &DeviceInfo) -> bool {
+        self.position(device).is_some()
+    }
+
+    /// The allowed devices in the order of `allowed`
+    pub fn select<'a>(
+        &self,
+        devices: impl IntoIterator<Item = &'a DeviceInfo>,
+    ) -> Vec<&'a DeviceInfo> {
+        let mut selected = devices
+            .into_iter()
+            .filter_map(|device| Some((self.position(device)?, device)))
+            .collect::<Vec<_>>();
+        selected.sort_by_key(|(position, _)| *position);
+        selected.into_iter().map(|(_, device)| device).collect()
+    }
+
+    /// Index of the first entry of `allowed` matching the device, 0 for every device when empty
+    fn position(&self, device: &DeviceInfo) -> Option<usize> {
+        if self.allowed.is_empty() {
+            return Some(0);
+        }
+        self.allowed
+            .iter()
+            .position(|entry| *entry == device.id || entry.eq_ignore_ascii_case(&device.name))
+    }
+}
+
+/// 当前已配对的A2DP源设备，由监视器事件维护
+#[derive(Debug, Default, Clone)]
+pub struct DeviceRegistry {
+    devices: BTreeMap<String, DeviceInfo>,
+    enumerated: bool,
+}
+
+impl DeviceRegistry {
+    pub fn devices(&self) -> impl Iterator<Item = &DeviceInfo> {
+        self.devices.values()
+    }
+
+    pub fn get(&self, device_id: &str) -> Option<&DeviceInfo> {
+        self.devices.get(device_id)
+    }
+
+    /// Whether the devices paired before the start are all known
+    pub fn is_enumerated(&self) -> bool {
+        self.enumerated
+    }
+
+    /// Apply the event, `connected` are the ids of the current connections
+    ///
+    /// Nothing is shown or connected before the enumeration completed, devices added after it
+    /// were paired while the application runs.
+    pub fn handle(
+        &mut self,
+        event: WatcherEvent,
+        policy: &AutoConnectPolicy,
+        connected: &[String],
+    ) -> Vec<RegistryAction> {
+        let can_connect =
+            |device: &DeviceInfo| !connected.contains(&device.id) && policy.allows(device);
+
+        match event {
+            WatcherEvent::Added(device) | WatcherEvent::Updated(device) => {
+                let id = device.id.clone();
+                let previous = self.devices.insert(id.clone(), device.clone());
+                match &previous {
+                    Some(previous) if previous.name != device.name => {
+                        log::info!(
+                            "Device renamed: {} -> {}({})",
+                            previous.name,
+                            device.name,
+                            id
+                        );
+                    }
+                    Some(_) => {}
+                    None => log::debug!("Device added: {}({})", device.name, id),
+                }
+
+                if !self.enumerated {
+                    return vec![];
+                }
+                let mut actions = vec![RegistryAction::ShowStatus(id.clone())];
+                if previous.is_none() && policy.new_devices && can_connect(&device) {
+                    log::info!("New device paired: {}({})", device.name, id);
+                    actions.push(RegistryAction::Connect(id));
+                }
+                actions
+            }
+            WatcherEvent::Removed(device_id) => match self.devices.remove(&device_id) {
+                Some(device) => {
+                    log::info!("Device removed: {}({})", device.name, device_id);
+                    vec![RegistryAction::Forget(device_id)]
+                }
+                None => vec![],
+            },
+            WatcherEvent::EnumerationCompleted => {
+                if self.enumerated {
+                    return vec![];
+                }
+                self.enumerated = true;
+                log::debug!("Device enumeration completed: {:?}", self.devices);
+
+                let mut actions = self
+                    .devices
+                    .keys()
+                    .map(|id| RegistryAction::ShowStatus(id.clone()))
+                    .collect::<Vec<_>>();
+                if !policy.at_startup {
+                    return actions;
+                }
+                let selected = policy
+                    .select(self.devices.values().filter(|device| can_connect(device)))
+                    .into_iter()
+                    .map(|device| device.id.clone());
+                match policy.exclusive {
+                    // 已有连接的源设备时保持不变
+                    true if connected.is_empty() => {
+                        let selected = selected.collect::<Vec<_>>();
+                        if !selected.is_empty() {
+                            actions.push(RegistryAction::ConnectFirst(selected));
+                        }
+                    }
+                    true => {}
+                    false => actions.extend(selected.map(RegistryAction::Connect)),
+                }
+                actions
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replays watcher events against a registry like the connection manager does
+    struct FakeWatcher {
+        registry: DeviceRegistry,
+        policy: AutoConnectPolicy,
+        connected: Vec<String>,
+    }
+
+    impl FakeWatcher {
+        fn new(policy: AutoConnectPolicy) -> Self {
+            Self {
+                registry: DeviceRegistry::default(),
+                policy,
+                connected: vec![],
+            }
+        }
+
+        fn send(&mut self, event: WatcherEvent) -> Vec<RegistryAction> {
+            self.registry.handle(event, &self.policy, &self.connected)
+        }
+
+        fn add(&mut self, id: &str, name: &str) -> Vec<RegistryAction> {
+            self.send(WatcherEvent::Added(device(id, name)))
+        }
+    }
+
+    fn device(id: &str, name: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn policy(allowed: &[&str]) -> AutoConnectPolicy {
+        AutoConnectPolicy {
+            at_startup: true,
+            new_devices: true,
+            on_presence: false,
+            allowed: allowed.iter().map(|entry| entry.to_string()).collect(),
+            exclusive: false,
+        }
+    }
+
+    #[test]
+    fn waits_for_the_enumeration() {
+        let mut watcher = FakeWatcher::new(policy(&[]));
+        assert_eq!(watcher.add("a", "Phone"), vec![]);
+        assert_eq!(watcher.add("b", "Tablet"), vec![]);
+        assert!(!watcher.registry.is_enumerated());
+
+        watcher.connected = vec!["b".to_string()];
+        assert_eq!(
+            watcher.send(WatcherEvent::EnumerationCompleted),
+            vec![
+                RegistryAction::ShowStatus("a".to_string()),
+                RegistryAction::ShowStatus("b".to_string()),
+                RegistryAction::Connect("a".to_string()),
+            ]
+        );
+        assert!(watcher.registry.is_enumerated());
+        // 超时后继续启动，之后到达的完成事件不再重复连接
+        assert_eq!(watcher.send(WatcherEvent::EnumerationCompleted), vec![]);
+    }
+
+    #[test]
+    fn connects_only_allowed_devices() {
+        let mut watcher = FakeWatcher::new(policy(&["phone", "c"]));
+        watcher.add("a", "Phone");
+        watcher.add("b", "Tablet");
+        let actions = watcher.send(WatcherEvent::EnumerationCompleted);
+        assert!(actions.contains(&RegistryAction::Connect("a".to_string())));
+        assert!(!actions.contains(&RegistryAction::Connect("b".to_string())));
+
+        assert_eq!(
+            watcher.add("c", "Laptop"),
+            vec![
+                RegistryAction::ShowStatus("c".to_string()),
+                RegistryAction::Connect("c".to_string()),
+            ]
+        );
+        assert_eq!(
+            watcher.add("d", "Watch"),
+            vec![RegistryAction::ShowStatus("d".to_string())]
+        );
+    }
+
+    #[test]
+    fn startup_connect_can_be_disabled() {
+        let mut watcher = FakeWatcher::new(AutoConnectPolicy {
+            at_startup: false,
+            new_devices: false,
+            ..policy(&[])
+        });
+        watcher.add("a", "Phone");
+        assert_eq!(
+            watcher.send(WatcherEvent::EnumerationCompleted),
+            vec![RegistryAction::ShowStatus("a".to_string())]
+        );
+        assert_eq!(
+            watcher.add("b", "Tablet"),
+            vec![RegistryAction::ShowStatus("b".to_string())]
+        );
+    }
+
+    #[test]
+    fn updates_and_removals() {
+        let mut watcher = FakeWatcher::new(policy(&[]));
+        watcher.add("a", "Phone");
+        watcher.send(WatcherEvent::EnumerationCompleted);
+
+        // 改名不是新配对的设备
+        assert_eq!(
+            watcher.send(WatcherEvent::Updated(device("a", "My Phone"))),
+            vec![RegistryAction::ShowStatus("a".to_string())]
+        );
+        assert_eq!(watcher.registry.get("a").unwrap().name, "My Phone");
+
+        assert_eq!(
+            watcher.send(WatcherEvent::Removed("a".to_string())),
+            vec![RegistryAction::Forget("a".to_string())]
+        );
+        assert_eq!(watcher.registry.devices().count(), 0);
+        assert_eq!(watcher.send(WatcherEvent::Removed("a".to_string())), vec![]);
+    }
+
+    #[test]
+    fn exclusive_startup_connects_the_first_allowed_device() {
+        let mut watcher = FakeWatcher::new(AutoConnectPolicy {
+            exclusive: true,
+            ..policy(&["Tablet", "a", "c"])
+        });
+        watcher.add("a", "Phone");
+        watcher.add("b", "Tablet");
+        watcher.add("c", "Laptop");
+        watcher.add("d", "Watch");
+        assert_eq!(
+            watcher.send(WatcherEvent::EnumerationCompleted),
+            vec![
+                RegistryAction::ShowStatus("a".to_string()),
+                RegistryAction::ShowStatus("b".to_string()),
+                RegistryAction::ShowStatus("c".to_string()),
+                RegistryAction::ShowStatus("d".to_string()),
+                RegistryAction::ConnectFirst(vec![
+                    "b".to_string(),
+                    "a".to_string(),
+                    "c".to_string()
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn exclusive_startup_keeps_a_connected_source() {
+        let mut watcher = FakeWatcher::new(AutoConnectPolicy {
+            exclusive: true,
+            ..policy(&[])
+        });
+        watcher.add("a", "Phone");
+        watcher.add("b", "Tablet");
+        watcher.connected = vec!["b".to_string()];
+        assert_eq!(
+            watcher.send(WatcherEvent::EnumerationCompleted),
+            vec![
+                RegistryAction::ShowStatus("a".to_string()),
+                RegistryAction::ShowStatus("b".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use crate::{
+    app::{backend::AudioBackend, config::AppConfig, control::DeviceControl},
+    internal::WarnExt,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::log;
+
+/// 系统电源与会话通知，Windows上来自隐藏窗口收到的 `WM_POWERBROADCAST` 与 `WM_WTSSESSION_CHANGE`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemEvent {
+    Suspend,
+    Resume,
+    SessionLocked,
+    SessionUnlocked,
+}
+
+/// 恢复哪些场景下断开的连接
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResumePolicy {
+    /// Reconnect the devices connected before the system went to sleep
+    pub on_resume: bool,
+    /// Reconnect the devices connected before the session was locked and lost since
+    pub on_unlock: bool,
+}
+
+/// 记录挂起或锁定前的连接，在恢复后重新连接
+///
+/// 睡眠恢复后原有的 `AudioPlaybackConnection` 都已失效，但要等到 `Closed` 事件才会从连接中移除，
+/// 因此恢复时先将其全部标记为失效，稍等蓝牙就绪后再重新连接。
+#[derive(Debug)]
+pub struct ResumeTracker {
+    delay: Duration,
+    suspended: bool,
+    locked: bool,
+    /// Connections before the suspend or the lock
+    saved: Vec<String>,
+    /// Devices to reconnect at the deadline
+    pending: Vec<String>,
+    deadline: Option<Instant>,
+}
+
+impl ResumeTracker {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            suspended: false,
+            locked: false,
+            saved: vec![],
+            pending: vec![],
+            deadline: None,
+        }
+    }
+
+    /// Apply the event, `connected` are the ids of the current connections
+    ///
+    /// Returns the connections which are stale and have to be dropped before reconnecting.
+    pub fn handle(
+        &mut self,
+        event: SystemEvent,
+        now: Instant,
+        connected: &[String],
+        policy: &ResumePolicy,
+    ) -> Vec<String> {
+        match event {
+            SystemEvent::Suspend => {
+                // 恢复后还没来得及重连的设备同样需要恢复
+                let pending = std::mem::take(&mut self.pending);
+                self.deadline = None;
+                self.save(connected.iter().chain(&pending));
+                self.suspended = true;
+                vec![]
+            }
+            SystemEvent::Resume => {
+                // 系统会发送多次恢复通知，只处理挂起后的第一次
+                if !self.suspended {
+                    return vec![];
+                }
+                self.suspended = false;
+                if policy.on_resume {
+                    let saved = std::mem::take(&mut self.saved);
+                    self.schedule(saved, now);
+                } else if !self.locked {
+                    self.saved.clear();
+                }
+                connected.to_vec()
+            }
+            SystemEvent::SessionLocked => {
+                self.locked = true;
+                self.save(connected);
+                vec![]
+            }
+            SystemEvent::SessionUnlocked => {
+                if !self.locked {
+                    return vec![];
+                }
+                self.locked = false;
+                let saved = std::mem::take(&mut self.saved);
+                if policy.on_unlock {
+                    let lost = saved
+                        .into_iter()
+                        .filter(|id| !connected.contains(id))
+                        .collect();
+                    self.schedule(lost, now);
+                }
+                vec![]
+            }
+        }
+    }
+
+    /// The device was disconnected on purpose, it is not restored
+    pub fn forget(&mut self, device_id: &str) {
+        self.saved.retain(|id| id != device_id);
+        self.pending.retain(|id| id != device_id);
+        if self.pending.is_empty() {
+            self.deadline = None;
+        }
+    }
+
+    /// Devices to reconnect now, leaving out the ones connected in the meantime
+    pub fn due(&mut self, now: Instant, connected: &[String]) -> Vec<String> {
+        if self.deadline.is_none_or(|deadline| now < deadline) {
+            return vec![];
+        }
+        self.deadline = None;
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .filter(|id| !connected.contains(id))
+            .collect()
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn save<'a>(&mut self, connected: impl IntoIterator<Item = &'a String>) {
+        for id in connected {
+            if !self.saved.contains(id) {
+                self.saved.push(id.clone());
+            }
+        }
+    }
+
+    fn schedule(&mut self, devices: Vec<String>, now: Instant) {
+        for id in devices {
+            if !self.pending.contains(&id) {
+                self.pending.push(id);
+            }
+        }
+        if !self.pending.is_empty() {
+            self.deadline = Some(now + self.delay);
+        }
+    }
+}
+
+/// 睡眠恢复与解锁后按配置恢复之前的连接
+pub struct ResumeReconnect {
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    control: Arc<dyn DeviceControl>,
+    tracker: ResumeTracker,
+    /// 通过 `control` 主动断开的设备，例如锁定期间因空闲或定时规则断开
+    disconnected: mpsc::UnboundedReceiver<String>,
+}
+
+impl ResumeReconnect {
+    pub fn new(
+        config: Arc<AppConfig>,
+        backend: Arc<dyn AudioBackend>,
+        control: Arc<dyn DeviceControl>,
+    ) -> Self {
+        let tracker = ResumeTracker::new(config.resume_reconnect_delay());
+        let (sender, disconnected) = mpsc::unbounded_channel();
+        control.on_disconnect(Arc::new(move |device_id| {
+            let _ = sender.send(device_id.to_string());
+        }));
+        Self {
+            config,
+            backend,
+            control,
+            tracker,
+            disconnected,
+        }
+    }
+
+    /// Follow `events` until they close, reconnecting through the control
+    pub async fn run(mut self, mut events: mpsc::UnboundedReceiver<SystemEvent>) {
+        loop {
+            let deadline = self.tracker.next_deadline();
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => self.handle(event),
+                    None => break,
+                },
+                Some(device_id) = self.disconnected.recv() => {
+                    log::debug!("Not restoring {}, disconnected on purpose", device_id);
+                    self.tracker.forget(&device_id);
+                }
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                    if deadline.is_some() => {}
+            }
+
+            for device_id in self.tracker.due(Instant::now(), &self.connections()) {
+                log::info!("Restoring connection to {}", device_id);
+                let control = self.control.clone();
+                // 连接可能需要数秒，不阻塞其他任务
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = control.connect(&device_id) {
+                        log::error!("Failed to restore connection to {}: {:?}", device_id, e);
+                    }
+                });
+            }
+        }
+    }
+
+    fn handle(&mut self, event: SystemEvent) {
+        log::info!("System event: {:?}", event);
+        let stale = self.tracker.handle(
+            event,
+            Instant::now(),
+            &self.connections(),
+            &self.config.resume_policy(),
+        );
+        for device_id in stale {
+            log::debug!("Dropping stale connection to {}", device_id);
+            self.backend
+                .mark_stale(&device_id)
+                .warn("Failed to drop stale connection");
+        }
+    }
+
+    fn connections(&self) -> Vec<String> {
+        match self.backend.connections() {
+            Ok(connections) => connections,
+            Err(e) => {
+                log::warn!("Failed to query connections: {:?}", e);
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{BackendControl, DeviceInfo, SimulatedBackend, config::test_config};
+
+    const DELAY: Duration = Duration::from_secs(3);
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn forgets_devices_disconnected_while_locked() {
+        let policy = ResumePolicy {
+            on_resume: true,
+            on_unlock: true,
+        };
+        let now = Instant::now();
+        let mut tracker = ResumeTracker::new(DELAY);
+        tracker.handle(SystemEvent::SessionLocked, now, &ids(&["a", "b"]), &policy);
+        tracker.forget("a");
+        tracker.handle(SystemEvent::SessionUnlocked, now, &[], &policy);
+
+        assert_eq!(tracker.next_deadline(), Some(now + DELAY));
+        assert_eq!(tracker.due(now + DELAY, &[]), ids(&["b"]));
+    }
+
+    #[test]
+    fn forgetting_the_last_pending_device_cancels_the_reconnect() {
+        let policy = ResumePolicy {
+            on_resume: true,
+            on_unlock: false,
+        };
+        let now = Instant::now();
+        let mut tracker = ResumeTracker::new(DELAY);
+        tracker.handle(SystemEvent::Suspend, now, &ids(&["a"]), &policy);
+        tracker.handle(SystemEvent::Resume, now, &ids(&["a"]), &policy);
+        tracker.forget("a");
+
+        assert_eq!(tracker.next_deadline(), None);
+        assert_eq!(tracker.due(now + DELAY, &[]), Vec::<String>::new());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unlock_skips_deliberate_disconnects() {
+        let backend = Arc::new(SimulatedBackend::new(["a", "b"].map(|id| DeviceInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+        })));
+        let config = Arc::new(test_config(
+            "resume-unlock",
+            "reconnect_after_unlock = true\nresume_reconnect_delay_secs = 3\n",
+        ));
+        let control = Arc::new(BackendControl::new(config.clone(), backend.clone()));
+        control.connect("a").unwrap();
+        control.connect("b").unwrap();
+
+        let (events, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(ResumeReconnect::new(config, backend.clone(), control.clone()).run(receiver));
+        events.send(SystemEvent::SessionLocked).unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        // 空闲断开 a，b 在锁定期间丢失
+        control.disconnect("a").unwrap();
+        backend.drop_connection("b");
+        events.send(SystemEvent::SessionUnlocked).unwrap();
+        tokio::time::sleep(DELAY * 2).await;
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while backend.connections().unwrap().is_empty() {
+            assert!(std::time::Instant::now() < deadline);
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(backend.connections().unwrap(), ["b"]);
+    }
+}
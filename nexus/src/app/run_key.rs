@@ -0,0 +1,91 @@
+use crate::app::autostart::RunRegistry;
+use windows::{
+    Win32::{Foundation::*, System::Registry::*},
+    core::*,
+};
+
+/// 当前用户的 `Software\Microsoft\Windows\CurrentVersion\Run`
+pub struct RunKey;
+
+impl RunKey {
+    const PATH: PCWSTR = w!(r"Software\Microsoft\Windows\CurrentVersion\Run");
+}
+
+impl RunRegistry for RunKey {
+    fn get(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let name = HSTRING::from(name);
+        let mut size = 0u32;
+
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                Self::PATH,
+                &name,
+                RRF_RT_REG_SZ,
+                None,
+                None,
+                Some(&mut size),
+            )
+        };
+        if status == ERROR_FILE_NOT_FOUND {
+            return Ok(None);
+        }
+        status.ok()?;
+
+        let mut buffer = vec![0u16; size as usize / 2];
+        unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                Self::PATH,
+                &name,
+                RRF_RT_REG_SZ,
+                None,
+                Some(buffer.as_mut_ptr() as *mut _),
+                Some(&mut size),
+            )
+        }
+        .ok()?;
+
+        let value = String::from_utf16_lossy(&buffer[..size as usize / 2]);
+        Ok(Some(value.trim_end_matches('\0').to_string()))
+    }
+
+    fn set(&self, name: &str, value: &str) -> anyhow::Result<()> {
+        let mut key = HKEY::default();
+        unsafe {
+            RegCreateKeyExW(
+                HKEY_CURRENT_USER,
+                Self::PATH,
+                None,
+                PCWSTR::null(),
+                REG_OPTION_NON_VOLATILE,
+                KEY_SET_VALUE,
+                None,
+                &mut key,
+                None,
+            )
+        }
+        .ok()?;
+
+        let data = value
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let result =
+            unsafe { RegSetValueExW(key, &HSTRING::from(name), None, REG_SZ, Some(&data)) };
+        let _ = unsafe { RegCloseKey(key) };
+
+        result.ok()?;
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> anyhow::Result<()> {
+        let status =
+            unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, Self::PATH, &HSTRING::from(name)) };
+        if status != ERROR_FILE_NOT_FOUND {
+            status.ok()?;
+        }
+        Ok(())
+    }
+}
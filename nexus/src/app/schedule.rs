@@ -0,0 +1,725 @@
+use crate::app::{
+    alias::DeviceNames,
+    backend::{AudioBackend, DeviceInfo},
+    config::AppConfig,
+};
+use anyhow::Context;
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, TimeDelta, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{fmt, sync::Arc, time::Duration};
+use tracing::log;
+
+/// 配置文件中 `[[schedule]]` 的一条规则，未经校验
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ScheduleEntry {
+    /// `connect` or `disconnect`
+    pub action: String,
+    /// Device id, Bluetooth name or alias, every device when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+    /// `mon-fri`, `sat,sun` or `daily`, every day when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub days: Option<String>,
+    /// `22:00`, or `09:00-18:00` to apply the action at the start and reverse it at the end,
+    /// starting inside the range applies the action too
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// `minute hour day-of-month month day-of-week` instead of `days` and `time`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cron: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleAction {
+    Connect,
+    Disconnect,
+}
+
+impl ScheduleAction {
+    pub fn reverse(self) -> Self {
+        match self {
+            Self::Connect => Self::Disconnect,
+            Self::Disconnect => Self::Connect,
+        }
+    }
+}
+
+impl fmt::Display for ScheduleAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect => write!(f, "connect"),
+            Self::Disconnect => write!(f, "disconnect"),
+        }
+    }
+}
+
+/// 星期的集合，第0位为周一
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Weekdays(u8);
+
+impl Weekdays {
+    pub const ALL: Self = Self(0x7F);
+
+    pub fn contains(&self, weekday: Weekday) -> bool {
+        self.0 & (1 << weekday.num_days_from_monday()) != 0
+    }
+
+    /// Parse `daily`, `mon-fri`, `sat,sun` or a mix like `mon,wed-fri`
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        if value == "daily" || value == "*" {
+            return Ok(Self::ALL);
+        }
+
+        let mut days = 0u8;
+        for part in value.split(',').map(str::trim) {
+            let (first, last) = match part.split_once('-') {
+                Some((first, last)) => (parse_weekday(first)?, parse_weekday(last)?),
+                None => (parse_weekday(part)?, parse_weekday(part)?),
+            };
+            // `fri-mon` 跨过周末
+            let mut day = first;
+            loop {
+                days |= 1 << day.num_days_from_monday();
+                if day == last {
+                    break;
+                }
+                day = day.succ();
+            }
+        }
+        Ok(Self(days))
+    }
+}
+
+fn parse_weekday(value: &str) -> anyhow::Result<Weekday> {
+    value.trim().parse::<Weekday>().map_err(|_| {
+        anyhow::anyhow!("unknown day {value:?}, use mon, tue, wed, thu, fri, sat or sun")
+    })
+}
+
+fn parse_time(value: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map_err(|_| anyhow::anyhow!("invalid time {value:?}, use HH:MM"))
+}
+
+/// 五个字段的cron表达式：分 时 日 月 星期，精确到分钟
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    /// 第0位为周日
+    weekdays: u8,
+    /// Day of month and day of week both restricted, either of them matches like in cron
+    either_day: bool,
+}
+
+impl CronExpr {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let fields = value.split_whitespace().collect::<Vec<_>>();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            anyhow::bail!(
+                "cron {value:?} needs 5 fields: minute hour day-of-month month day-of-week"
+            );
+        };
+
+        let weekdays = parse_cron_field(weekday, 0, 7).context("day of week")?;
+        // 0和7都表示周日
+        let weekdays = ((weekdays | (weekdays >> 7)) & 0x7F) as u8;
+        Ok(Self {
+            minutes: parse_cron_field(minute, 0, 59).context("minute")?,
+            hours: parse_cron_field(hour, 0, 23).context("hour")? as u32,
+            days_of_month: parse_cron_field(day, 1, 31).context("day of month")? as u32,
+            months: parse_cron_field(month, 1, 12).context("month")? as u16,
+            weekdays,
+            either_day: *day != "*" && *weekday != "*",
+        })
+    }
+
+    pub fn matches(&self, time: NaiveDateTime) -> bool {
+        let day = self.days_of_month & (1 << time.day()) != 0;
+        let weekday = self.weekdays & (1 << time.weekday().num_days_from_sunday()) != 0;
+        let day_matches = match self.either_day {
+            true => day || weekday,
+            false => day && weekday,
+        };
+        self.minutes & (1 << time.minute()) != 0
+            && self.hours & (1 << time.hour()) != 0
+            && self.months & (1 << time.month()) != 0
+            && day_matches
+    }
+}
+
+/// Bits of the values allowed by a field like `*`, `*/15`, `1-5`, `8-18/2` or `0,30`
+fn parse_cron_field(field: &str, min: u32, max: u32) -> anyhow::Result<u64> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step = step
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| anyhow::anyhow!("invalid step {step:?}"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            range => {
+                let parse = |value: &str| {
+                    value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|value| (min..=max).contains(value))
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("{value:?} is not a number from {min} to {max}")
+                        })
+                };
+                match range.split_once('-') {
+                    Some((first, last)) => (parse(first)?, parse(last)?),
+                    None => (parse(range)?, parse(range)?),
+                }
+            }
+        };
+        anyhow::ensure!(first <= last, "range {range:?} ends before it starts");
+        for value in (first..=last).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleTrigger {
+    At {
+        days: Weekdays,
+        time: NaiveTime,
+    },
+    /// The action at the start, the reverse at the end which may be on the next day
+    Range {
+        days: Weekdays,
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+    Cron(CronExpr),
+}
+
+/// 校验后的定时规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleRule {
+    pub action: ScheduleAction,
+    pub device: Option<String>,
+    pub trigger: ScheduleTrigger,
+}
+
+impl ScheduleRule {
+    pub fn parse(entry: &ScheduleEntry) -> anyhow::Result<Self> {
+        let action = match entry.action.trim().to_ascii_lowercase().as_str() {
+            "connect" => ScheduleAction::Connect,
+            "disconnect" => ScheduleAction::Disconnect,
+            "" => anyhow::bail!("`action` is missing, use \"connect\" or \"disconnect\""),
+            action => anyhow::bail!("unknown action {action:?}, use \"connect\" or \"disconnect\""),
+        };
+
+        let trigger = match (&entry.cron, &entry.days, &entry.time) {
+            (Some(_), Some(_), _) | (Some(_), _, Some(_)) => {
+                anyhow::bail!("use either `cron` or `days` and `time`")
+            }
+            (Some(cron), None, None) => ScheduleTrigger::Cron(CronExpr::parse(cron)?),
+            (None, _, None) => anyhow::bail!("`time` or `cron` is missing"),
+            (None, days, Some(time)) => {
+                let days = match days {
+                    Some(days) => Weekdays::parse(days)?,
+                    None => Weekdays::ALL,
+                };
+                match time.split_once('-') {
+                    Some((start, end)) => {
+                        let (start, end) = (parse_time(start)?, parse_time(end)?);
+                        anyhow::ensure!(start != end, "time range {time:?} is empty");
+                        ScheduleTrigger::Range { days, start, end }
+                    }
+                    None => ScheduleTrigger::At {
+                        days,
+                        time: parse_time(time)?,
+                    },
+                }
+            }
+        };
+
+        let device = entry
+            .device
+            .as_deref()
+            .map(str::trim)
+            .filter(|device| !device.is_empty())
+            .map(str::to_string);
+        Ok(Self {
+            action,
+            device,
+            trigger,
+        })
+    }
+
+    /// Action of the rule in the minute starting at `time`
+    pub fn fires(&self, time: NaiveDateTime) -> Option<ScheduleAction> {
+        let minute = time.time().with_second(0)?.with_nanosecond(0)?;
+        match &self.trigger {
+            ScheduleTrigger::At { days, time: at } => {
+                (days.contains(time.weekday()) && minute == *at).then_some(self.action)
+            }
+            ScheduleTrigger::Range { days, start, end } => {
+                if minute == *start && days.contains(time.weekday()) {
+                    return Some(self.action);
+                }
+                // 跨午夜的范围在开始的第二天结束
+                let started = match end < start {
+                    true => time.weekday().pred(),
+                    false => time.weekday(),
+                };
+                (minute == *end && days.contains(started)).then_some(self.action.reverse())
+            }
+            ScheduleTrigger::Cron(cron) => cron.matches(time).then_some(self.action),
+        }
+    }
+
+    /// Action of a range rule while `time` is inside its range, the end minute is outside
+    ///
+    /// Applied when the schedule starts or the clock jumped, the start of the range was missed.
+    pub fn in_effect(&self, time: NaiveDateTime) -> Option<ScheduleAction> {
+        let ScheduleTrigger::Range { days, start, end } = &self.trigger else {
+            return None;
+        };
+        let minute = time.time().with_second(0)?.with_nanosecond(0)?;
+        let today = days.contains(time.weekday());
+        let inside = match start < end {
+            true => today && *start <= minute && minute < *end,
+            // 跨午夜的范围在开始的第二天结束
+            false => {
+                (today && minute >= *start)
+                    || (minute < *end && days.contains(time.weekday().pred()))
+            }
+        };
+        inside.then_some(self.action)
+    }
+
+    /// The first minute after `time` in which the rule fires, within a year
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<(NaiveDateTime, ScheduleAction)> {
+        let start = truncate_to_minute(time)?;
+        (1..=366 * 24 * 60).find_map(|minutes| {
+            let time = start + TimeDelta::minutes(minutes);
+            self.fires(time).map(|action| (time, action))
+        })
+    }
+}
+
+impl fmt::Display for ScheduleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let device = self.device.as_deref().unwrap_or("every device");
+        match &self.trigger {
+            ScheduleTrigger::At { time, .. } => {
+                write!(f, "{} {} at {}", self.action, device, time.format("%H:%M"))
+            }
+            ScheduleTrigger::Range { start, end, .. } => write!(
+                f,
+                "{} {} from {} to {}",
+                self.action,
+                device,
+                start.format("%H:%M"),
+                end.format("%H:%M")
+            ),
+            ScheduleTrigger::Cron(_) => write!(f, "{} {} by cron", self.action, device),
+        }
+    }
+}
+
+/// Validate the entries, the errors name the rule by its position starting from 1
+pub fn parse_schedule(entries: &[ScheduleEntry]) -> (Vec<ScheduleRule>, Vec<String>) {
+    let mut rules = vec![];
+    let mut errors = vec![];
+    for (index, entry) in entries.iter().enumerate() {
+        match ScheduleRule::parse(entry) {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(format!("schedule rule {}: {:#}", index + 1, e)),
+        }
+    }
+    (rules, errors)
+}
+
+fn truncate_to_minute(time: NaiveDateTime) -> Option<NaiveDateTime> {
+    time.with_second(0)?.with_nanosecond(0)
+}
+
+/// 当前的本地时间，定时规则按墙上时间执行
+pub trait Clock: Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+/// 规则触发的动作，`device` 为 `None` 时作用于所有设备
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledAction {
+    pub action: ScheduleAction,
+    pub device: Option<String>,
+}
+
+/// 按分钟检查规则，返回上次检查之后到期的动作
+#[derive(Debug)]
+pub struct Scheduler {
+    rules: Vec<ScheduleRule>,
+    /// The last minute checked
+    last: Option<NaiveDateTime>,
+}
+
+impl Scheduler {
+    /// 超过该时间没有检查（例如睡眠）时跳过错过的规则，而不是醒来后一起执行
+    const MAX_CATCH_UP: TimeDelta = TimeDelta::minutes(5);
+
+    pub fn new(rules: Vec<ScheduleRule>) -> Self {
+        Self { rules, last: None }
+    }
+
+    /// Actions of the minutes since the last call up to the one containing `now`
+    ///
+    /// The first call and a call after the clock jumped check the current minute and the
+    /// range rules `now` is inside of.
+    pub fn due(&mut self, now: NaiveDateTime) -> Vec<ScheduledAction> {
+        let Some(now) = truncate_to_minute(now) else {
+            return vec![];
+        };
+        let first = match self.last {
+            Some(last) if now == last => return vec![],
+            // 时钟被调回时不重复执行，只恢复范围规则的状态
+            Some(last) if now < last => {
+                log::info!("Clock set back from {} to {}", last, now);
+                self.last = Some(now);
+                return self.actions(now, now, ScheduleRule::in_effect);
+            }
+            Some(last) if now - last > Self::MAX_CATCH_UP => {
+                log::info!("Skipping the schedule from {} to {}", last, now);
+                None
+            }
+            Some(last) => Some(last + TimeDelta::minutes(1)),
+            None => None,
+        };
+        self.last = Some(now);
+
+        match first {
+            Some(first) => self.actions(first, now, ScheduleRule::fires),
+            None => self.actions(now, now, |rule, time| {
+                rule.fires(time).or_else(|| rule.in_effect(time))
+            }),
+        }
+    }
+
+    /// Actions `check` finds for the rules in the minutes from `first` to `last`
+    fn actions(
+        &self,
+        first: NaiveDateTime,
+        last: NaiveDateTime,
+        check: impl Fn(&ScheduleRule, NaiveDateTime) -> Option<ScheduleAction>,
+    ) -> Vec<ScheduledAction> {
+        let mut actions = vec![];
+        let mut minute = first;
+        while minute <= last {
+            for rule in &self.rules {
+                if let Some(action) = check(rule, minute) {
+                    actions.push(ScheduledAction {
+                        action,
+                        device: rule.device.clone(),
+                    });
+                }
+            }
+            minute += TimeDelta::minutes(1);
+        }
+        actions
+    }
+}
+
+/// Ids of the devices the action applies to, devices already in the target state are left out
+pub fn schedule_targets(
+    action: &ScheduledAction,
+    devices: &[DeviceInfo],
+    connected: &[String],
+    names: &DeviceNames,
+) -> Vec<String> {
+    let selected = |device_id: &str, name: &str| {
+        action
+            .device
+            .as_deref()
+            .is_none_or(|query| names.matches(query, device_id, name))
+    };
+    match action.action {
+        ScheduleAction::Connect => devices
+            .iter()
+            .filter(|device| !connected.contains(&device.id) && selected(&device.id, &device.name))
+            .map(|device| device.id.clone())
+            .collect(),
+        ScheduleAction::Disconnect => connected
+            .iter()
+            .filter(|device_id| {
+                let name = devices
+                    .iter()
+                    .find(|device| device.id == **device_id)
+                    .map(|device| device.name.as_str())
+                    .unwrap_or_default();
+                selected(device_id, name)
+            })
+            .cloned()
+            .collect(),
+    }
+}
+
+/// 按定时规则连接或断开设备
+pub struct ScheduleRunner {
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    clock: Arc<dyn Clock>,
+    scheduler: Scheduler,
+}
+
+impl ScheduleRunner {
+    pub fn new(
+        config: Arc<AppConfig>,
+        backend: Arc<dyn AudioBackend>,
+        clock: Arc<dyn Clock>,
+        rules: Vec<ScheduleRule>,
+    ) -> Self {
+        Self {
+            config,
+            backend,
+            clock,
+            scheduler: Scheduler::new(rules),
+        }
+    }
+
+    /// Check the rules at the start of every minute, calling `connect` and `disconnect` for
+    /// the devices they apply to
+    pub async fn run(mut self, connect: impl Fn(String), disconnect: impl Fn(String)) {
+        loop {
+            for action in self.scheduler.due(self.clock.now()) {
+                self.apply(&action, &connect, &disconnect);
+            }
+
+            // 每分钟重新读取时钟，系统时间调整或睡眠后也能按时执行
+            let second = self.clock.now().second() as u64;
+            tokio::time::sleep(Duration::from_secs(60 - second.min(59))).await;
+        }
+    }
+
+    fn apply(
+        &self,
+        action: &ScheduledAction,
+        connect: &impl Fn(String),
+        disconnect: &impl Fn(String),
+    ) {
+        let (devices, connected) = match (self.backend.devices(), self.backend.connections()) {
+            (Ok(devices), Ok(connected)) => (devices, connected),
+            (Err(e), _) | (_, Err(e)) => {
+                log::warn!("Failed to query devices for the schedule: {:?}", e);
+                return;
+            }
+        };
+        let names = self.config.device_names();
+        for device_id in schedule_targets(action, &devices, &connected, &names) {
+            log::info!("Scheduled {} of {}", action.action, device_id);
+            match action.action {
+                ScheduleAction::Connect => connect(device_id),
+                ScheduleAction::Disconnect => disconnect(device_id),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{BackendCall, SimulatedBackend, config::test_config};
+    use chrono::NaiveDate;
+    use std::sync::Mutex;
+
+    /// 由测试设置的时钟
+    struct ManualClock(Mutex<NaiveDateTime>);
+
+    impl ManualClock {
+        fn set(&self, time: NaiveDateTime) {
+            *self.0.lock().unwrap() = time;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    /// 2026-10-19 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 10, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn rule(action: &str, days: Option<&str>, time: &str) -> ScheduleRule {
+        ScheduleRule::parse(&ScheduleEntry {
+            action: action.to_string(),
+            days: days.map(str::to_string),
+            time: Some(time.to_string()),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn actions(actions: &[ScheduleAction]) -> Vec<ScheduledAction> {
+        actions
+            .iter()
+            .map(|action| ScheduledAction {
+                action: *action,
+                device: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ranges_fire_at_the_start_and_the_end() {
+        let mut scheduler = Scheduler::new(vec![rule("connect", None, "09:00-18:00")]);
+        assert_eq!(scheduler.due(at(19, 8, 58)), vec![]);
+        assert_eq!(
+            scheduler.due(at(19, 9, 0)),
+            actions(&[ScheduleAction::Connect])
+        );
+        assert_eq!(scheduler.due(at(19, 9, 3)), vec![]);
+        assert_eq!(scheduler.due(at(19, 9, 5)), vec![]);
+        // 错过的分钟在下一次检查时补上
+        scheduler.due(at(19, 17, 58));
+        assert_eq!(
+            scheduler.due(at(19, 18, 1)),
+            actions(&[ScheduleAction::Disconnect])
+        );
+    }
+
+    #[test]
+    fn first_check_applies_the_range_in_effect() {
+        let rules = vec![rule("connect", Some("mon-fri"), "09:00-18:00")];
+        let mut scheduler = Scheduler::new(rules.clone());
+        assert_eq!(
+            scheduler.due(at(19, 12, 30)),
+            actions(&[ScheduleAction::Connect])
+        );
+        assert_eq!(scheduler.due(at(19, 12, 31)), vec![]);
+
+        // 范围外与不在指定的日期时什么也不做
+        assert_eq!(Scheduler::new(rules.clone()).due(at(19, 18, 30)), vec![]);
+        assert_eq!(Scheduler::new(rules).due(at(18, 12, 0)), vec![]);
+    }
+
+    #[test]
+    fn ranges_across_midnight_end_the_next_day() {
+        let rules = vec![rule("disconnect", Some("fri"), "22:00-07:00")];
+        // 周五晚上与周六早上在范围内，周日早上不在
+        assert_eq!(
+            Scheduler::new(rules.clone()).due(at(23, 23, 0)),
+            actions(&[ScheduleAction::Disconnect])
+        );
+        assert_eq!(
+            Scheduler::new(rules.clone()).due(at(24, 6, 59)),
+            actions(&[ScheduleAction::Disconnect])
+        );
+        assert_eq!(Scheduler::new(rules.clone()).due(at(25, 6, 0)), vec![]);
+
+        let mut scheduler = Scheduler::new(rules);
+        scheduler.due(at(24, 6, 59));
+        assert_eq!(
+            scheduler.due(at(24, 7, 0)),
+            actions(&[ScheduleAction::Connect])
+        );
+    }
+
+    #[test]
+    fn clock_jumps_restore_the_range_state() {
+        let mut scheduler = Scheduler::new(vec![
+            rule("connect", None, "09:00-18:00"),
+            rule("disconnect", None, "08:00"),
+        ]);
+        assert_eq!(scheduler.due(at(19, 7, 0)), vec![]);
+        // 睡眠中错过了开始的时间
+        assert_eq!(
+            scheduler.due(at(19, 10, 0)),
+            actions(&[ScheduleAction::Connect])
+        );
+        // 调回时钟不重复执行08:00的规则
+        assert_eq!(scheduler.due(at(19, 7, 59)), vec![]);
+        assert_eq!(
+            scheduler.due(at(19, 8, 0)),
+            actions(&[ScheduleAction::Disconnect])
+        );
+        assert_eq!(scheduler.due(at(19, 7, 30)), vec![]);
+        assert_eq!(
+            scheduler.due(at(19, 9, 30)),
+            actions(&[ScheduleAction::Connect])
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn runner_follows_the_clock() {
+        let backend = Arc::new(SimulatedBackend::new([DeviceInfo {
+            id: "a".to_string(),
+            name: "Phone".to_string(),
+        }]));
+        let clock = Arc::new(ManualClock(Mutex::new(at(19, 17, 58))));
+        let config = Arc::new(test_config("schedule-runner", ""));
+        let runner = ScheduleRunner::new(
+            config,
+            backend.clone(),
+            clock.clone(),
+            vec![rule("connect", None, "09:00-18:00")],
+        );
+        let calls = Arc::new(Mutex::new(vec![]));
+        tokio::spawn(runner.run(
+            {
+                let calls = calls.clone();
+                move |device_id| calls.lock().unwrap().push(BackendCall::Connect(device_id))
+            },
+            {
+                let backend = backend.clone();
+                let calls = calls.clone();
+                move |device_id: String| {
+                    backend.disconnect(&device_id).unwrap();
+                    calls
+                        .lock()
+                        .unwrap()
+                        .push(BackendCall::Disconnect(device_id))
+                }
+            },
+        ));
+
+        // 启动时正在范围内
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [BackendCall::Connect("a".to_string())]
+        );
+
+        backend.connect("a").unwrap();
+        clock.set(at(19, 18, 0));
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                BackendCall::Connect("a".to_string()),
+                BackendCall::Disconnect("a".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,681 @@
+use crate::app::{
+    backend::{AudioBackend, BackendEvent, DeviceInfo, EventListener},
+    config::AppConfig,
+    control::{BackendControl, DeviceControl},
+    schedule::{Clock, ScheduleAction, ScheduledAction, schedule_targets},
+};
+use anyhow::Context;
+use chrono::{Datelike, Timelike};
+use rhai::{
+    AST, Array, CallFnOptions, Dynamic, Engine, Map, Scope, module_resolvers::DummyModuleResolver,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use tracing::log;
+
+/// 配置文件中的 `[script]`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ScriptConfig {
+    /// Rhai script, relative paths start at the directory of the config file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    /// Seconds between the calls of `on_timer`, 0 never calls it
+    pub timer_secs: u64,
+    /// Operations a single call may run before it is stopped
+    pub max_operations: u64,
+    /// Milliseconds a single call may run before it is stopped
+    pub timeout_ms: u64,
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            timer_secs: 60,
+            max_operations: 100_000,
+            timeout_ms: 1000,
+        }
+    }
+}
+
+impl ScriptConfig {
+    pub fn timer(&self) -> Option<Duration> {
+        (self.timer_secs > 0).then(|| Duration::from_secs(self.timer_secs))
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    /// The script file, relative paths are resolved against the directory of `config_path`
+    pub fn path(&self, config_path: &Path) -> Option<PathBuf> {
+        let file = self.file.as_ref()?;
+        Some(match config_path.parent() {
+            Some(dir) => dir.join(file),
+            None => file.clone(),
+        })
+    }
+}
+
+/// 脚本拒绝连接时 [`ScriptedBackend::connect`] 返回的错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectRefused {
+    pub device_id: String,
+}
+
+impl fmt::Display for ConnectRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Connection to {} refused by the script", self.device_id)
+    }
+}
+
+impl std::error::Error for ConnectRefused {}
+
+/// 脚本调用的动作，在脚本返回后执行
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ScriptAction {
+    Connect(String),
+    Disconnect(String),
+    Notify(String),
+}
+
+pub type Notifier = Arc<dyn Fn(String) + Send + Sync>;
+
+/// 脚本可以使用的设备状态与动作
+struct ScriptApi {
+    backend: Arc<dyn AudioBackend>,
+    config: Arc<AppConfig>,
+    clock: Arc<dyn Clock>,
+    actions: Mutex<Vec<ScriptAction>>,
+}
+
+impl ScriptApi {
+    fn devices(&self) -> (Vec<DeviceInfo>, Vec<String>) {
+        let devices = self.backend.devices().unwrap_or_else(|e| {
+            log::warn!("Failed to query devices for the script: {:?}", e);
+            vec![]
+        });
+        let connected = self.backend.connections().unwrap_or_default();
+        (devices, connected)
+    }
+
+    fn device_map(&self, device: &DeviceInfo, connected: &[String]) -> Dynamic {
+        let names = self.config.device_names();
+        let mut map = Map::new();
+        map.insert("id".into(), device.id.clone().into());
+        map.insert("name".into(), device.name.clone().into());
+        map.insert(
+            "alias".into(),
+            names
+                .alias(&device.id, &device.name)
+                .map_or(Dynamic::UNIT, |alias| alias.to_string().into()),
+        );
+        map.insert("display_name".into(), names.device_name(device).into());
+        map.insert("connected".into(), connected.contains(&device.id).into());
+        map.into()
+    }
+
+    fn device_by_id(&self, device_id: &str) -> Dynamic {
+        let (devices, connected) = self.devices();
+        let device = devices
+            .into_iter()
+            .find(|device| device.id == device_id)
+            .unwrap_or(DeviceInfo {
+                id: device_id.to_string(),
+                name: String::new(),
+            });
+        self.device_map(&device, &connected)
+    }
+
+    fn find(&self, query: &str) -> Dynamic {
+        let (devices, connected) = self.devices();
+        let names = self.config.device_names();
+        devices
+            .iter()
+            .find(|device| names.matches(query, &device.id, &device.name))
+            .map_or(Dynamic::UNIT, |device| self.device_map(device, &connected))
+    }
+
+    fn now(&self) -> Dynamic {
+        let now = self.clock.now();
+        let mut map = Map::new();
+        map.insert("year".into(), (now.year() as i64).into());
+        map.insert("month".into(), (now.month() as i64).into());
+        map.insert("day".into(), (now.day() as i64).into());
+        map.insert("hour".into(), (now.hour() as i64).into());
+        map.insert("minute".into(), (now.minute() as i64).into());
+        map.insert(
+            "weekday".into(),
+            (now.weekday().number_from_monday() as i64).into(),
+        );
+        map.insert("time".into(), now.format("%H:%M").to_string().into());
+        map.into()
+    }
+
+    fn push(&self, action: ScriptAction) {
+        self.actions.lock().unwrap().push(action);
+    }
+}
+
+/// 加载用户的Rhai脚本，在连接事件与定时器中调用其中的函数
+///
+/// 脚本可以定义这些函数，都是可选的：
+/// `allow_connect(device)` 返回 `false` 拒绝连接，`on_connected(device)`、`on_disconnected(device)`
+/// 与 `on_timer()`。脚本只能通过注册的函数访问设备，不能导入模块，每次调用都有操作数与时间限制。
+pub struct ScriptHost {
+    engine: Engine,
+    ast: AST,
+    scope: Mutex<Scope<'static>>,
+    api: Arc<ScriptApi>,
+    /// 本次调用的截止时间，由 `on_progress` 检查
+    deadline: Arc<Mutex<Option<Instant>>>,
+    timeout: Duration,
+    notifier: RwLock<Option<Notifier>>,
+    /// 执行脚本的连接与断开，托盘中替换为 `ConnectionManager`
+    control: RwLock<Arc<dyn DeviceControl>>,
+}
+
+impl ScriptHost {
+    /// Compile the script and run its top level statements
+    pub fn load(
+        path: &Path,
+        settings: &ScriptConfig,
+        config: Arc<AppConfig>,
+        backend: Arc<dyn AudioBackend>,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Self> {
+        let source =
+            fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        Self::compile(&source, settings, config, backend, clock)
+            .with_context(|| format!("Failed to load script {:?}", path))
+    }
+
+    pub fn compile(
+        source: &str,
+        settings: &ScriptConfig,
+        config: Arc<AppConfig>,
+        backend: Arc<dyn AudioBackend>,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Self> {
+        let control = Arc::new(BackendControl::new(config.clone(), backend.clone()));
+        let api = Arc::new(ScriptApi {
+            backend,
+            config,
+            clock,
+            actions: Default::default(),
+        });
+        let deadline = Arc::new(Mutex::new(None));
+        let engine = Self::engine(settings, &api, &deadline);
+
+        let ast = engine
+            .compile(source)
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        let host = Self {
+            engine,
+            ast,
+            scope: Mutex::new(Scope::new()),
+            api,
+            deadline,
+            timeout: settings.timeout(),
+            notifier: Default::default(),
+            control: RwLock::new(control),
+        };
+
+        let mut scope = host.scope.lock().unwrap();
+        host.start_call();
+        let result = host.engine.run_ast_with_scope(&mut scope, &host.ast);
+        *host.deadline.lock().unwrap() = None;
+        drop(scope);
+        result.map_err(|e| anyhow::anyhow!("{}", e))?;
+        host.apply_actions();
+        Ok(host)
+    }
+
+    fn engine(
+        settings: &ScriptConfig,
+        api: &Arc<ScriptApi>,
+        deadline: &Arc<Mutex<Option<Instant>>>,
+    ) -> Engine {
+        let mut engine = Engine::new();
+        engine
+            .set_module_resolver(DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .set_max_operations(settings.max_operations)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(64 * 1024)
+            .set_max_array_size(10_000)
+            .set_max_map_size(10_000)
+            .on_print(|text| log::info!("Script: {}", text))
+            .on_debug(|text, _, position| log::debug!("Script {}: {}", position, text));
+
+        engine.on_progress({
+            let deadline = deadline.clone();
+            move |_| match *deadline.lock().unwrap() {
+                Some(deadline) if Instant::now() > deadline => Some("timeout".into()),
+                _ => None,
+            }
+        });
+
+        let query = api.clone();
+        engine.register_fn("devices", move || -> Array {
+            let (devices, connected) = query.devices();
+            devices
+                .iter()
+                .map(|device| query.device_map(device, &connected))
+                .collect()
+        });
+        let query = api.clone();
+        engine.register_fn("connections", move || -> Array {
+            let (devices, connected) = query.devices();
+            devices
+                .iter()
+                .filter(|device| connected.contains(&device.id))
+                .map(|device| query.device_map(device, &connected))
+                .collect()
+        });
+        let query = api.clone();
+        engine.register_fn("device", move |query_text: &str| query.find(query_text));
+        let query = api.clone();
+        engine.register_fn("is_connected", move |query_text: &str| {
+            let device = query.find(query_text);
+            device
+                .try_cast::<Map>()
+                .and_then(|device| device.get("connected")?.as_bool().ok())
+                .unwrap_or(false)
+        });
+        let query = api.clone();
+        engine.register_fn("now", move || query.now());
+
+        let actions = api.clone();
+        engine.register_fn("connect", move |device: &str| {
+            actions.push(ScriptAction::Connect(device.to_string()))
+        });
+        let actions = api.clone();
+        engine.register_fn("disconnect", move |device: &str| {
+            actions.push(ScriptAction::Disconnect(device.to_string()))
+        });
+        let actions = api.clone();
+        engine.register_fn("notify", move |text: &str| {
+            actions.push(ScriptAction::Notify(text.to_string()))
+        });
+        engine
+    }
+
+    /// Show the messages of `notify`, they are only logged until it is set
+    pub fn set_notifier(&self, notifier: Notifier) {
+        *self.notifier.write().unwrap() = Some(notifier);
+    }
+
+    /// Run the connections and disconnections asked for by the script through `control`
+    pub fn set_control(&self, control: Arc<dyn DeviceControl>) {
+        *self.control.write().unwrap() = control;
+    }
+
+    /// Whether the script lets the device connect, errors in the script allow it
+    pub fn allow_connect(&self, device_id: &str) -> bool {
+        if !self.defines("allow_connect", 1) {
+            return true;
+        }
+        let device = self.api.device_by_id(device_id);
+        match self.call("allow_connect", vec![device]) {
+            Some(allowed) => allowed.as_bool().unwrap_or_else(|_| {
+                log::warn!("allow_connect returned {}, allowing", allowed.type_name());
+                true
+            }),
+            None => true,
+        }
+    }
+
+    pub fn handle_event(&self, event: &BackendEvent) {
+        let (name, device_id) = match event {
+            BackendEvent::Connected { device_id } => ("on_connected", device_id),
+            BackendEvent::Disconnected { device_id } => ("on_disconnected", device_id),
+        };
+        if self.defines(name, 1) {
+            let device = self.api.device_by_id(device_id);
+            self.call(name, vec![device]);
+        }
+    }
+
+    pub fn on_timer(&self) {
+        self.call("on_timer", vec![]);
+    }
+
+    /// Pass the connection events to the script and call `on_timer` every `timer`
+    pub async fn run(
+        self: Arc<Self>,
+        mut events: mpsc::UnboundedReceiver<BackendEvent>,
+        timer: Option<Duration>,
+    ) {
+        // 没有定时器时不会等待这个间隔
+        let mut interval = tokio::time::interval(timer.unwrap_or(Duration::from_secs(3600)));
+        // 第一次立即触发，跳过
+        interval.tick().await;
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Some(event) => self.blocking(move |host| host.handle_event(&event)).await,
+                    None => break,
+                },
+                _ = interval.tick(), if timer.is_some() => self.blocking(Self::on_timer).await,
+            }
+        }
+    }
+
+    /// Call the script on a blocking thread, it queries the backend and may run up to its timeout
+    async fn blocking(self: &Arc<Self>, call: impl FnOnce(&Self) + Send + 'static) {
+        let host = self.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || call(&host)).await {
+            log::error!("Script call panicked: {:?}", e);
+        }
+    }
+
+    fn defines(&self, name: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.len() == params)
+    }
+
+    /// Call the function if the script defines it, then run the actions it asked for
+    fn call(&self, name: &str, args: Vec<Dynamic>) -> Option<Dynamic> {
+        if !self.defines(name, args.len()) {
+            return None;
+        }
+
+        let mut scope = self.scope.lock().unwrap();
+        self.start_call();
+        let options = CallFnOptions::new().eval_ast(false);
+        let result = self
+            .engine
+            .call_fn_with_options::<Dynamic>(options, &mut scope, &self.ast, name, args);
+        *self.deadline.lock().unwrap() = None;
+        drop(scope);
+
+        let result = match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("Script function {} failed: {}", name, e);
+                // 出错前调用的动作同样丢弃
+                self.api.actions.lock().unwrap().clear();
+                return None;
+            }
+        };
+        self.apply_actions();
+        result
+    }
+
+    fn start_call(&self) {
+        *self.deadline.lock().unwrap() = Some(Instant::now() + self.timeout);
+    }
+
+    fn apply_actions(&self) {
+        let actions = std::mem::take(&mut *self.api.actions.lock().unwrap());
+        for action in actions {
+            let (action, query) = match action {
+                ScriptAction::Notify(text) => {
+                    log::info!("Script notification: {}", text);
+                    if let Some(notifier) = self.notifier.read().unwrap().as_ref() {
+                        notifier(text);
+                    }
+                    continue;
+                }
+                ScriptAction::Connect(query) => (ScheduleAction::Connect, query),
+                ScriptAction::Disconnect(query) => (ScheduleAction::Disconnect, query),
+            };
+
+            let (devices, connected) = self.api.devices();
+            let action = ScheduledAction {
+                action,
+                device: Some(query),
+            };
+            let names = self.api.config.device_names();
+            for device_id in schedule_targets(&action, &devices, &connected, &names) {
+                log::info!("Script asked to {} {}", action.action, device_id);
+                let control = self.control.read().unwrap().clone();
+                let action = action.action;
+                // 连接可能需要数秒，不阻塞脚本与事件
+                thread::spawn(move || {
+                    let result = match action {
+                        ScheduleAction::Connect => control.connect(&device_id),
+                        ScheduleAction::Disconnect => control.disconnect(&device_id),
+                    };
+                    if let Err(e) = result {
+                        log::warn!("Script failed to {} {}: {:?}", action, device_id, e);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// 连接前询问脚本的 [`AudioBackend`]，并将连接事件交给脚本
+pub struct ScriptedBackend {
+    inner: Arc<dyn AudioBackend>,
+    host: Arc<ScriptHost>,
+}
+
+impl ScriptedBackend {
+    /// The events are sent to `events` for [`ScriptHost::run`]
+    pub fn new(
+        inner: Arc<dyn AudioBackend>,
+        host: Arc<ScriptHost>,
+        events: mpsc::UnboundedSender<BackendEvent>,
+    ) -> Self {
+        // 事件可能在后端加锁时发出，脚本在另一个任务中处理以免重入
+        inner.subscribe(Arc::new(move |event| {
+            let _ = events.send(event);
+        }));
+        Self { inner, host }
+    }
+}
+
+impl AudioBackend for ScriptedBackend {
+    fn devices(&self) -> anyhow::Result<Vec<DeviceInfo>> {
+        self.inner.devices()
+    }
+
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        if !self.host.allow_connect(device_id) {
+            log::info!("Script refused the connection to {}", device_id);
+            return Err(ConnectRefused {
+                device_id: device_id.to_string(),
+            }
+            .into());
+        }
+        self.inner.connect(device_id)
+    }
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.inner.disconnect(device_id)
+    }
+
+    fn mark_stale(&self, device_id: &str) -> anyhow::Result<()> {
+        self.inner.mark_stale(device_id)
+    }
+
+    fn connections(&self) -> anyhow::Result<Vec<String>> {
+        self.inner.connections()
+    }
+
+    fn subscribe(&self, listener: EventListener) {
+        self.inner.subscribe(listener)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{
+        SimulatedBackend, config::test_config, control::DisconnectListener, schedule::SystemClock,
+    };
+
+    /// Records the calls instead of connecting
+    #[derive(Default)]
+    struct RecordingControl(Mutex<Vec<String>>);
+
+    impl RecordingControl {
+        async fn wait_for(&self, count: usize) -> Vec<String> {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while self.0.lock().unwrap().len() < count {
+                assert!(Instant::now() < deadline);
+                tokio::task::yield_now().await;
+            }
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl DeviceControl for RecordingControl {
+        fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("connect {}", device_id));
+            Ok(())
+        }
+
+        fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("disconnect {}", device_id));
+            Ok(())
+        }
+
+        fn on_disconnect(&self, _listener: DisconnectListener) {}
+    }
+
+    fn backend() -> Arc<SimulatedBackend> {
+        Arc::new(SimulatedBackend::new(["a", "b"].map(|id| DeviceInfo {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+        })))
+    }
+
+    fn compile(
+        name: &str,
+        source: &str,
+        settings: &ScriptConfig,
+        backend: Arc<SimulatedBackend>,
+    ) -> anyhow::Result<ScriptHost> {
+        let config = Arc::new(test_config(name, "exclusive = false\n"));
+        ScriptHost::compile(source, settings, config, backend, Arc::new(SystemClock))
+    }
+
+    #[test]
+    fn cannot_reach_outside_the_sandbox() {
+        let settings = ScriptConfig::default();
+        assert!(
+            compile(
+                "script-import",
+                "import \"fs\" as fs;",
+                &settings,
+                backend()
+            )
+            .is_err()
+        );
+        assert!(compile("script-eval", "eval(\"1\");", &settings, backend()).is_err());
+    }
+
+    #[test]
+    fn stops_calls_running_too_many_operations() {
+        let backend = backend();
+        let settings = ScriptConfig {
+            max_operations: 1000,
+            timeout_ms: 60_000,
+            ..Default::default()
+        };
+        let host = compile(
+            "script-operations",
+            "fn on_timer() { connect(\"a\"); loop {} }",
+            &settings,
+            backend.clone(),
+        )
+        .unwrap();
+
+        host.on_timer();
+        // 出错前调用的动作被丢弃
+        thread::sleep(Duration::from_millis(100));
+        assert!(backend.connections().unwrap().is_empty());
+    }
+
+    #[test]
+    fn stops_calls_past_the_deadline() {
+        let settings = ScriptConfig {
+            max_operations: 0,
+            timeout_ms: 50,
+            ..Default::default()
+        };
+        let host = compile(
+            "script-deadline",
+            "fn on_timer() { loop {} }",
+            &settings,
+            backend(),
+        )
+        .unwrap();
+
+        let start = Instant::now();
+        host.on_timer();
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn top_level_connects_go_through_the_control() {
+        let backend = backend();
+        backend.connect("a").unwrap();
+        let config = Arc::new(test_config(
+            "script-exclusive",
+            "exclusive = true\nhandover_delay_secs = 0\n",
+        ));
+        ScriptHost::compile(
+            "connect(\"B\");",
+            &ScriptConfig::default(),
+            config,
+            backend.clone(),
+            Arc::new(SystemClock),
+        )
+        .unwrap();
+
+        // 独占模式下先断开 a
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.connections().unwrap() != ["b"] {
+            assert!(Instant::now() < deadline);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn runs_the_rules_of_events_and_the_timer() {
+        let backend = backend();
+        let host = Arc::new(
+            compile(
+                "script-run",
+                "fn on_connected(device) { if device.id == \"a\" { connect(\"b\"); } }\n\
+                 fn on_timer() { disconnect(\"a\"); }",
+                &ScriptConfig::default(),
+                backend.clone(),
+            )
+            .unwrap(),
+        );
+        let control = Arc::new(RecordingControl::default());
+        host.set_control(control.clone());
+
+        let (events, receiver) = mpsc::unbounded_channel();
+        let scripted = ScriptedBackend::new(backend, host.clone(), events);
+        tokio::spawn(host.run(receiver, Some(Duration::from_secs(60))));
+
+        scripted.connect("a").unwrap();
+        assert_eq!(control.wait_for(1).await, ["connect b"]);
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        assert_eq!(control.wait_for(2).await, ["connect b", "disconnect a"]);
+    }
+}
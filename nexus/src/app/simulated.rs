@@ -0,0 +1,143 @@
+use crate::app::{
+    backend::{AudioBackend, BackendEvent, DeviceInfo, EventListener},
+    connect_error::{ConnectError, OpenStatus},
+};
+use std::{collections::HashMap, sync::Mutex};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendCall {
+    Devices,
+    Connect(String),
+    Disconnect(String),
+}
+
+#[derive(Debug, Default)]
+struct SimulatedState {
+    devices: Vec<DeviceInfo>,
+    connected: Vec<String>,
+    failures: HashMap<String, ConnectError>,
+    calls: Vec<BackendCall>,
+}
+
+/// 内存中的 [`AudioBackend`]，用于在没有蓝牙硬件的环境下驱动连接逻辑
+#[derive(Default)]
+pub struct SimulatedBackend {
+    state: Mutex<SimulatedState>,
+    listeners: Mutex<Vec<EventListener>>,
+}
+
+impl SimulatedBackend {
+    pub fn new(devices: impl IntoIterator<Item = DeviceInfo>) -> Self {
+        Self {
+            state: Mutex::new(SimulatedState {
+                devices: devices.into_iter().collect(),
+                ..Default::default()
+            }),
+            listeners: Default::default(),
+        }
+    }
+
+    pub fn add_device(&self, device: DeviceInfo) {
+        self.state.lock().unwrap().devices.push(device);
+    }
+
+    pub fn remove_device(&self, device_id: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .devices
+            .retain(|device| device.id != device_id);
+        self.drop_connection(device_id);
+    }
+
+    /// Make every following connect to the device fail, `None` lets it succeed again
+    pub fn set_failure(&self, device_id: &str, error: Option<ConnectError>) {
+        let mut state = self.state.lock().unwrap();
+        match error {
+            Some(error) => state.failures.insert(device_id.to_string(), error),
+            None => state.failures.remove(device_id),
+        };
+    }
+
+    /// Simulate the source closing the connection
+    pub fn drop_connection(&self, device_id: &str) {
+        let removed = {
+            let mut state = self.state.lock().unwrap();
+            let before = state.connected.len();
+            state.connected.retain(|id| id != device_id);
+            before != state.connected.len()
+        };
+
+        if removed {
+            self.emit(BackendEvent::Disconnected {
+                device_id: device_id.to_string(),
+            });
+        }
+    }
+
+    pub fn calls(&self) -> Vec<BackendCall> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    pub fn clear_calls(&self) {
+        self.state.lock().unwrap().calls.clear();
+    }
+
+    fn emit(&self, event: BackendEvent) {
+        let listeners = self.listeners.lock().unwrap().clone();
+        for listener in listeners {
+            listener(event.clone());
+        }
+    }
+}
+
+impl AudioBackend for SimulatedBackend {
+    fn devices(&self) -> anyhow::Result<Vec<DeviceInfo>> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(BackendCall::Devices);
+        Ok(state.devices.clone())
+    }
+
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            state
+                .calls
+                .push(BackendCall::Connect(device_id.to_string()));
+
+            if let Some(error) = state.failures.get(device_id) {
+                return Err((*error).into());
+            }
+            if !state.devices.iter().any(|device| device.id == device_id) {
+                return Err(ConnectError::new(OpenStatus::UnknownFailure, 0).into());
+            }
+            if state.connected.iter().any(|id| id == device_id) {
+                return Ok(());
+            }
+            state.connected.push(device_id.to_string());
+        }
+
+        self.emit(BackendEvent::Connected {
+            device_id: device_id.to_string(),
+        });
+        Ok(())
+    }
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.state
+            .lock()
+            .unwrap()
+            .calls
+            .push(BackendCall::Disconnect(device_id.to_string()));
+        self.drop_connection(device_id);
+        Ok(())
+    }
+
+    fn connections(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.state.lock().unwrap().connected.clone())
+    }
+
+    fn subscribe(&self, listener: EventListener) {
+        self.listeners.lock().unwrap().push(listener);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::app::{
+    backend::AudioBackend,
+    connect_error::{ConnectError, ExtendedErrorStrings, OpenStatus},
+};
+use rust_i18n::t;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuStrings {
+    pub connection_list: String,
+    pub bluetooth_list: String,
+    pub auto_connect: String,
+    pub exclusive: String,
+    pub autostart: String,
+    pub language: String,
+    pub follow_system: String,
+    pub exit: String,
+}
+
+impl Default for MenuStrings {
+    fn default() -> Self {
+        Self {
+            connection_list: "Open Connection List(&C)".to_string(),
+            bluetooth_list: "Add Bluetooth Device(&B)".to_string(),
+            auto_connect: "Automatically connect at startup(&A)".to_string(),
+            exclusive: "Only one source at a time(&O)".to_string(),
+            autostart: "Start with Windows(&W)".to_string(),
+            language: "Language(&L)".to_string(),
+            follow_system: "Follow System(&S)".to_string(),
+            exit: "Exit(&X)".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceStatusStrings {
+    pub picker_title: String,
+    pub connecting: String,
+    pub connected: String,
+    pub timeout: String,
+    pub denied_by_system: String,
+    pub not_found: String,
+    pub unknown_reason: String,
+    pub disconnected: String,
+    /// The connection was released by the idle timeout
+    pub idle_disconnected: String,
+    /// The automation script refused the connection
+    pub refused: String,
+    /// `%{alias}` and `%{status}` of a device with an alias
+    pub aliased_status: String,
+    pub extended_errors: ExtendedErrorStrings,
+}
+
+impl Default for DeviceStatusStrings {
+    fn default() -> Self {
+        Self {
+            picker_title: "A2DP Sink: Click to select a source device ".to_string(),
+            connecting: "Connecting".to_string(),
+            connected: "Connected".to_string(),
+            timeout: "Connection Timeout".to_string(),
+            denied_by_system: "Connection Denied by System".to_string(),
+            not_found: "Device not found".to_string(),
+            unknown_reason: "Unknown Reason".to_string(),
+            disconnected: "Disconnected".to_string(),
+            idle_disconnected: "Disconnected (idle)".to_string(),
+            refused: "Refused by script".to_string(),
+            aliased_status: "%{alias} · %{status}".to_string(),
+            extended_errors: Default::default(),
+        }
+    }
+}
+
+impl DeviceStatusStrings {
+    /// The picker shows the Bluetooth name, the alias goes in front of the status
+    pub fn with_alias(&self, alias: Option<&str>, status: &str) -> String {
+        match (alias, status) {
+            (None, status) => status.to_string(),
+            (Some(alias), "") => alias.to_string(),
+            (Some(alias), status) => self
+                .aliased_status
+                .replace("%{alias}", alias)
+                .replace("%{status}", status),
+        }
+    }
+
+    pub fn failure_text(&self, error: &ConnectError) -> String {
+        let status = match error.status {
+            OpenStatus::RequestTimedOut => self.timeout.clone(),
+            // Error reported here when device cannot be scanned
+            OpenStatus::UnknownFailure => self.not_found.clone(),
+            OpenStatus::DeniedBySystem => self.denied_by_system.clone(),
+            OpenStatus::Other(code) => format!("{}{}", self.unknown_reason, code),
+        };
+
+        match error.extended {
+            Some(extended) => match extended.describe(&self.extended_errors) {
+                Some(message) => format!("{status}: {message}"),
+                None => format!("{status} ({:#010X})", extended.hresult() as u32),
+            },
+            None => status,
+        }
+    }
+
+    /// Status of each device which is connected or failed to connect, used to redraw the
+    /// picker after the strings changed
+    pub fn device_statuses(
+        &self,
+        backend: &dyn AudioBackend,
+        failures: &HashMap<String, ConnectError>,
+    ) -> anyhow::Result<BTreeMap<String, DeviceStatusText>> {
+        let mut statuses = failures
+            .iter()
+            .map(|(device_id, error)| {
+                let status = DeviceStatusText {
+                    text: self.failure_text(error),
+                    connected: false,
+                };
+                (device_id.clone(), status)
+            })
+            .collect::<BTreeMap<_, _>>();
+        for device_id in backend.connections()? {
+            let status = DeviceStatusText {
+                text: self.connected.clone(),
+                connected: true,
+            };
+            statuses.insert(device_id, status);
+        }
+        Ok(statuses)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceStatusText {
+    pub text: String,
+    /// 已连接的设备显示断开按钮，否则显示重试按钮
+    pub connected: bool,
+}
+
+/// Tooltip of the tray icon, with the names of the connected devices below the title
+pub fn tooltip_text(title: &str, connected: &[String]) -> String {
+    match connected {
+        [] => title.to_string(),
+        names => format!("{title}\n{}", names.join(", ")),
+    }
+}
+
+/// 界面上所有随语言变化的文本
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UiStrings {
+    pub tooltip: String,
+    pub menu: MenuStrings,
+    pub device_status: DeviceStatusStrings,
+}
+
+impl UiStrings {
+    pub fn localized(locale: &str) -> Self {
+        Self {
+            tooltip: t!("notify_icon.tooltip", locale = locale).to_string(),
+            menu: MenuStrings {
+                connection_list: t!("notify_icon.connection_list", locale = locale).to_string(),
+                bluetooth_list: t!("notify_icon.bluetooth_list", locale = locale).to_string(),
+                auto_connect: t!("notify_icon.auto_connect", locale = locale).to_string(),
+                exclusive: t!("notify_icon.exclusive", locale = locale).to_string(),
+                autostart: t!("notify_icon.autostart", locale = locale).to_string(),
+                language: t!("notify_icon.language", locale = locale).to_string(),
+                follow_system: t!("notify_icon.follow_system", locale = locale).to_string(),
+                exit: t!("notify_icon.exit", locale = locale).to_string(),
+            },
+            device_status: DeviceStatusStrings {
+                picker_title: t!("connection_manager.picker_title", locale = locale).to_string(),
+                timeout: t!("connection_manager.timeout", locale = locale).to_string(),
+                connecting: t!("connection_manager.connecting", locale = locale).to_string(),
+                connected: t!("connection_manager.connected", locale = locale).to_string(),
+                denied_by_system: t!("connection_manager.denied_by_system", locale = locale)
+                    .to_string(),
+                not_found: t!("connection_manager.not_found", locale = locale).to_string(),
+                unknown_reason: t!("connection_manager.unknown_reason", locale = locale)
+                    .to_string(),
+                disconnected: t!("connection_manager.disconnected", locale = locale).to_string(),
+                idle_disconnected: t!("connection_manager.idle_disconnected", locale = locale)
+                    .to_string(),
+                refused: t!("connection_manager.refused", locale = locale).to_string(),
+                aliased_status: t!("connection_manager.aliased_status", locale = locale)
+                    .to_string(),
+                extended_errors: ExtendedErrorStrings {
+                    radio_off: t!(
+                        "connection_manager.extended_error.radio_off",
+                        locale = locale
+                    )
+                    .to_string(),
+                    device_busy: t!(
+                        "connection_manager.extended_error.device_busy",
+                        locale = locale
+                    )
+                    .to_string(),
+                    driver_missing: t!(
+                        "connection_manager.extended_error.driver_missing",
+                        locale = locale
+                    )
+                    .to_string(),
+                    device_unreachable: t!(
+                        "connection_manager.extended_error.device_unreachable",
+                        locale = locale
+                    )
+                    .to_string(),
+                    access_denied: t!(
+                        "connection_manager.extended_error.access_denied",
+                        locale = locale
+                    )
+                    .to_string(),
+                    service_disabled: t!(
+                        "connection_manager.extended_error.service_disabled",
+                        locale = locale
+                    )
+                    .to_string(),
+                    cancelled: t!(
+                        "connection_manager.extended_error.cancelled",
+                        locale = locale
+                    )
+                    .to_string(),
+                },
+            },
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use crate::app::{
+    backend::{AudioBackend, BackendEvent, DeviceInfo, EventListener},
+    connect_error::{ConnectError, OpenStatus},
+};
+use anyhow::Context;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tracing::log;
+use windows::{
+    Devices::Enumeration::DeviceInformation, Foundation::TypedEventHandler, Media::Audio::*,
+    core::*,
+};
+
+#[derive(Default)]
+struct Inner {
+    connections: Mutex<HashMap<String, AudioPlaybackConnection>>,
+    listeners: Mutex<Vec<EventListener>>,
+}
+
+impl Inner {
+    fn emit(&self, event: BackendEvent) {
+        let listeners = self.listeners.lock().unwrap().clone();
+        for listener in listeners {
+            listener(event.clone());
+        }
+    }
+}
+
+/// 在本进程中通过 `AudioPlaybackConnection` 建立连接
+#[derive(Default)]
+pub struct WinRtBackend {
+    inner: Arc<Inner>,
+}
+
+impl WinRtBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn handle_state(inner: &Inner, device_id: &str, state: AudioPlaybackConnectionState) {
+        match state {
+            AudioPlaybackConnectionState::Closed => {
+                // let device_id = connection.DeviceId().unwrap(); // Bug: Windows 问题会导致 double free
+
+                log::debug!("AudioPlaybackConnection closed: {}", device_id);
+                let connection = inner.connections.lock().unwrap().remove(device_id);
+
+                if connection.is_some() {
+                    inner.emit(BackendEvent::Disconnected {
+                        device_id: device_id.to_string(),
+                    });
+                }
+            }
+            _ => {
+                log::info!("AudioPlaybackConnection state changed: {:?}", state);
+            }
+        }
+    }
+}
+
+impl AudioBackend for WinRtBackend {
+    fn devices(&self) -> anyhow::Result<Vec<DeviceInfo>> {
+        let selector = AudioPlaybackConnection::GetDeviceSelector()?;
+
+        DeviceInformation::FindAllAsyncAqsFilter(&selector)?
+            .join()?
+            .into_iter()
+            .map(|device| {
+                Ok(DeviceInfo {
+                    id: device.Id()?.to_string(),
+                    name: device.Name()?.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        if self
+            .inner
+            .connections
+            .lock()
+            .unwrap()
+            .contains_key(device_id)
+        {
+            return Ok(());
+        }
+
+        let connection = AudioPlaybackConnection::TryCreateFromId(&HSTRING::from(device_id))
+            .context(format!(
+                "Failed to create AudioPlaybackConnection for device ID: {device_id}",
+            ))?;
+
+        connection.StateChanged(&{
+            let inner = self.inner.clone();
+            let device_id = device_id.to_string();
+            TypedEventHandler::<AudioPlaybackConnection, _>::new(move |sender, _| {
+                let state = sender.as_ref().unwrap().State().unwrap();
+                Self::handle_state(&inner, &device_id, state);
+                Ok(())
+            })
+        })?;
+
+        connection.Start().context(format!(
+            "Failed to start AudioPlaybackConnection for device ID: {device_id}",
+        ))?;
+
+        let result = connection.Open().context("Fail to open connection")?;
+        let status = match result.Status()? {
+            AudioPlaybackConnectionOpenResultStatus::Success => {
+                self.inner
+                    .connections
+                    .lock()
+                    .unwrap()
+                    .insert(device_id.to_string(), connection);
+                self.inner.emit(BackendEvent::Connected {
+                    device_id: device_id.to_string(),
+                });
+                return Ok(());
+            }
+            AudioPlaybackConnectionOpenResultStatus::RequestTimedOut => OpenStatus::RequestTimedOut,
+            AudioPlaybackConnectionOpenResultStatus::UnknownFailure => OpenStatus::UnknownFailure,
+            AudioPlaybackConnectionOpenResultStatus::DeniedBySystem => OpenStatus::DeniedBySystem,
+            res => OpenStatus::Other(res.0),
+        };
+
+        let extended = result.ExtendedError().map(|hr| hr.0).unwrap_or_default();
+        Err(ConnectError::new(status, extended).into())
+    }
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        let connection = self.inner.connections.lock().unwrap().remove(device_id);
+
+        if let Some(connection) = connection {
+            let closed = connection.Close();
+            // 连接已经移除，关闭失败时同样通知监听者
+            self.inner.emit(BackendEvent::Disconnected {
+                device_id: device_id.to_string(),
+            });
+            closed.with_context(|| format!("Failed to close connection to {device_id}"))?;
+        }
+        Ok(())
+    }
+
+    /// 失效的连接关闭时可能出错，不影响将其移除
+    fn mark_stale(&self, device_id: &str) -> anyhow::Result<()> {
+        let connection = self.inner.connections.lock().unwrap().remove(device_id);
+
+        if let Some(connection) = connection {
+            if let Err(e) = connection.Close() {
+                log::debug!("Fail to close stale connection {}: {:?}", device_id, e);
+            }
+            self.inner.emit(BackendEvent::Disconnected {
+                device_id: device_id.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn connections(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self
+            .inner
+            .connections
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect())
+    }
+
+    fn subscribe(&self, listener: EventListener) {
+        self.inner.listeners.lock().unwrap().push(listener);
+    }
+}
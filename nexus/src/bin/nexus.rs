@@ -1,7 +1,7 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use lit_sink_nexus::{
-    app::{AppConfig, Application},
-    init_i18n,
+    app::{AppConfig, AppPaths, OutputFormat},
+    flush_warnings, set_warn_dedup_window,
 };
 use std::{path::PathBuf, sync::OnceLock};
 use tracing_appender::{
@@ -14,30 +14,439 @@ use tracing_subscriber::{EnvFilter, Registry, fmt, layer::SubscriberExt, prelude
 #[derive(Debug, Parser)]
 #[command(version, about)]
 struct Cli {
-    #[arg(short, long, value_name = "DIR", default_value = "logs")]
-    log: PathBuf,
+    /// Log directory, defaults to `logs`
+    #[arg(short, long, value_name = "DIR")]
+    log: Option<PathBuf>,
 
-    #[arg(short, long, value_name = "FILE", default_value = "config.toml")]
-    config: PathBuf,
+    /// Config file, defaults to `config.toml`
+    ///
+    /// With a `portable.marker` next to the executable the defaults are in its directory
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Wait before starting, used by the autostart entry
+    #[arg(long, value_name = "SECS")]
+    start_delay: Option<u64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage the background service which keeps the connections
+    #[cfg(windows)]
+    Service {
+        #[command(subcommand)]
+        action: ServiceAction,
+    },
+    /// Manage starting with Windows
+    #[cfg(windows)]
+    Autostart {
+        #[command(subcommand)]
+        action: AutostartAction,
+    },
+    /// Show the recorded connections, failures and per-device statistics
+    History {
+        /// Only this device, by id or name
+        #[arg(long, value_name = "DEVICE")]
+        device: Option<String>,
+        /// Only records after this time: `30m`, `12h`, `7d`, `2w`, a date or an RFC 3339 time
+        #[arg(long, value_name = "TIME")]
+        since: Option<String>,
+        /// Success rate and average session length per device instead of the records
+        #[arg(long)]
+        summary: bool,
+        #[arg(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Name devices, the names are shown instead of their Bluetooth names
+    Alias {
+        #[command(subcommand)]
+        action: AliasAction,
+    },
+    /// Check the `[[schedule]]` rules in the config and show when each applies next
+    Schedule,
+    /// Work with the translations of the user interface
+    I18n {
+        #[command(subcommand)]
+        action: I18nAction,
+    },
+}
+
+impl Command {
+    /// Whether the command keeps running, logging to the files in the log directory
+    fn runs_in_background(&self) -> bool {
+        match self {
+            #[cfg(windows)]
+            Command::Service { action } => matches!(action, ServiceAction::Run),
+            _ => false,
+        }
+    }
+
+    /// Whether the command runs the connections or changes the config file
+    fn writes_config(&self) -> bool {
+        match self {
+            #[cfg(windows)]
+            Command::Service { action } => matches!(action, ServiceAction::Run),
+            Command::Alias { action } => !matches!(action, AliasAction::List),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(windows)]
+#[derive(Debug, Subcommand)]
+enum ServiceAction {
+    /// Register the service to start at boot
+    Install,
+    /// Stop and remove the service
+    Uninstall,
+    /// Run as the service, called by the service control manager
+    Run,
+}
+
+#[cfg(windows)]
+#[derive(Debug, Subcommand)]
+enum AutostartAction {
+    /// Start the tray application when the user logs in
+    Enable {
+        /// Wait before starting, defaults to `autostart_delay_secs` in the config
+        #[arg(long, value_name = "SECS")]
+        delay: Option<u64>,
+    },
+    /// Remove the autostart entry
+    Disable,
+    /// Show whether the autostart entry exists and is up to date
+    Status,
+}
+
+#[derive(Debug, Subcommand)]
+enum AliasAction {
+    /// Show `name` for the device
+    Set {
+        /// Device id, Bluetooth name or current alias
+        device: String,
+        name: String,
+    },
+    /// Show the Bluetooth name of the device again
+    Remove {
+        /// Device id, Bluetooth name or current alias
+        device: String,
+    },
+    /// List the aliases
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+enum I18nAction {
+    /// Write a translation file with every key, to put into the `locales` directory
+    ExportTemplate {
+        /// Locale to translate to, e.g. `ja` or `zh-HK`
+        #[arg(long)]
+        locale: String,
+        /// Output file, defaults to `<LOCALE>.toml` in the working directory
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
-    init_logger(&cli);
-    init_i18n();
+    let paths = AppPaths::for_current_exe(cli.config.clone(), cli.log.clone());
+    // 命令行命令不创建日志目录，警告输出到标准错误
+    match cli.command.as_ref().is_none_or(Command::runs_in_background) {
+        true => init_logger(&paths),
+        false => init_stderr_logger(),
+    }
+
+    // 只有托盘与服务创建或升级配置文件，其它命令只读取
+    let config = match cli.command.as_ref().is_none_or(Command::writes_config) {
+        true => AppConfig::parse_or_default(paths.config.clone()),
+        false => AppConfig::load(paths.config.clone()),
+    };
+    set_warn_dedup_window(config.warn_dedup_window());
+    lit_sink_nexus::locales::load_overlays(&paths.locales());
+
+    let result = match &cli.command {
+        None => run_tray(&cli, &paths, config),
+        #[cfg(windows)]
+        Some(Command::Service { action }) => run_service(&paths, action, config),
+        #[cfg(windows)]
+        Some(Command::Autostart { action }) => run_autostart(&paths, action, config),
+        Some(Command::History {
+            device,
+            since,
+            summary,
+            format,
+        }) => run_history(&paths, &config, device, since, *summary, *format),
+        Some(Command::Alias { action }) => run_alias(&paths, &config, action),
+        Some(Command::Schedule) => run_schedule(&config),
+        Some(Command::I18n { action }) => run_i18n(action),
+    };
+    flush_warnings();
+    if let Err(e) = result {
+        eprintln!("Error: {e:#}");
+        std::process::exit(1);
+    }
+}
+
+#[cfg(windows)]
+fn run_tray(cli: &Cli, paths: &AppPaths, config: AppConfig) -> anyhow::Result<()> {
+    if let Some(delay) = cli.start_delay {
+        std::thread::sleep(std::time::Duration::from_secs(delay));
+    }
+
+    lit_sink_nexus::init_i18n(config.language().as_deref());
+    let history = lit_sink_nexus::app::HistoryStore::open_for_recording(
+        paths.history(),
+        config.history_retention(),
+    )
+    .inspect_err(|e| tracing::warn!("Connection history is not recorded: {:?}", e))
+    .ok();
+    lit_sink_nexus::app::Application::run(config, autostart_command(paths)?, history)
+}
+
+#[cfg(not(windows))]
+fn run_tray(_cli: &Cli, _paths: &AppPaths, _config: AppConfig) -> anyhow::Result<()> {
+    anyhow::bail!("The tray application is only available on Windows")
+}
+
+#[cfg(windows)]
+fn run_service(paths: &AppPaths, action: &ServiceAction, config: AppConfig) -> anyhow::Result<()> {
+    use lit_sink_nexus::{
+        app::WinRtBackend,
+        service::{ScmHost, ServiceHost, ServiceSpec, default_endpoint},
+    };
+    use std::sync::Arc;
 
-    Application::run(AppConfig::parse_or_default(cli.config)).unwrap();
+    match action {
+        ServiceAction::Install => ScmHost.install(&ServiceSpec::new(
+            &std::env::current_exe()?,
+            &std::path::absolute(&paths.config)?,
+            &std::path::absolute(&paths.log)?,
+        )),
+        ServiceAction::Uninstall => ScmHost.uninstall(ServiceSpec::NAME),
+        ServiceAction::Run => lit_sink_nexus::service::run_service(
+            &ScmHost,
+            Arc::new(WinRtBackend::new()),
+            &config,
+            default_endpoint(),
+        ),
+    }
 }
 
-fn init_logger(cli: &Cli) {
+#[cfg(windows)]
+fn autostart_command(paths: &AppPaths) -> anyhow::Result<lit_sink_nexus::app::AutostartCommand> {
+    Ok(lit_sink_nexus::app::AutostartCommand {
+        executable: std::env::current_exe()?,
+        config: std::path::absolute(&paths.config)?,
+        log: std::path::absolute(&paths.log)?,
+        delay: None,
+    })
+}
+
+#[cfg(windows)]
+fn run_autostart(
+    paths: &AppPaths,
+    action: &AutostartAction,
+    config: AppConfig,
+) -> anyhow::Result<()> {
+    use lit_sink_nexus::app::{Autostart, AutostartStatus, RunKey};
+    use std::time::Duration;
+
+    let autostart = Autostart::new(RunKey);
+    let command = autostart_command(paths)?;
+
+    match action {
+        AutostartAction::Enable { delay } => {
+            let delay = delay.map(Duration::from_secs).or(config.autostart_delay());
+            autostart.enable(&command.with_delay(delay))?;
+            println!("Autostart enabled");
+        }
+        AutostartAction::Disable => {
+            autostart.disable()?;
+            println!("Autostart disabled");
+        }
+        AutostartAction::Status => {
+            match autostart.status(&command.with_delay(config.autostart_delay()))? {
+                AutostartStatus::Disabled => println!("disabled"),
+                AutostartStatus::Enabled {
+                    command_line,
+                    up_to_date,
+                } => {
+                    println!("enabled: {command_line}");
+                    if !up_to_date {
+                        println!("(the entry differs from the current executable or arguments)");
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_history(
+    paths: &AppPaths,
+    config: &AppConfig,
+    device: &Option<String>,
+    since: &Option<String>,
+    summary: bool,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    use lit_sink_nexus::app::{
+        HistoryFilter, HistoryStore, device_stats, parse_since, render_records, render_stats,
+    };
+
+    let filter = HistoryFilter {
+        device: device.clone(),
+        since: since
+            .as_deref()
+            .map(|since| parse_since(since, chrono::Utc::now()))
+            .transpose()?,
+    };
+    let names = config.device_names();
+    let store = HistoryStore::open(paths.history(), config.history_retention());
+    let records = store
+        .read()?
+        .into_iter()
+        .filter(|record| filter.matches(record, &names))
+        .collect::<Vec<_>>();
+
+    let output = match summary {
+        true => render_stats(&device_stats(&records, &names), &names, format)?,
+        false => render_records(&records, &names, format)?,
+    };
+    print!("{output}");
+    Ok(())
+}
+
+fn run_schedule(config: &AppConfig) -> anyhow::Result<()> {
+    use lit_sink_nexus::app::{Clock, SystemClock, parse_schedule};
+
+    let (rules, errors) = parse_schedule(&config.schedule());
+    let now = SystemClock.now();
+    for rule in &rules {
+        match rule.next_after(now) {
+            Some((time, action)) => {
+                println!(
+                    "{rule}: next {action} at {}",
+                    time.format("%a %Y-%m-%d %H:%M")
+                )
+            }
+            None => println!("{rule}: not within a year"),
+        }
+    }
+    anyhow::ensure!(errors.is_empty(), "{}", errors.join("\n"));
+    if rules.is_empty() {
+        println!("No schedule rules in {:?}", config.file_path);
+    }
+    Ok(())
+}
+
+/// Devices paired with this PC and the ones in the connection history
+fn known_devices(paths: &AppPaths, config: &AppConfig) -> Vec<lit_sink_nexus::app::DeviceInfo> {
+    use lit_sink_nexus::app::{DeviceInfo, HistoryStore};
+
+    let mut devices: Vec<DeviceInfo> = vec![];
+    #[cfg(windows)]
+    {
+        use lit_sink_nexus::app::{AudioBackend, WinRtBackend};
+        match WinRtBackend::new().devices() {
+            Ok(paired) => devices.extend(paired),
+            Err(e) => eprintln!("Failed to list paired devices: {e:?}"),
+        }
+    }
+    if let Ok(records) = HistoryStore::open(paths.history(), config.history_retention()).read() {
+        for record in records.into_iter().rev() {
+            if !devices.iter().any(|device| device.id == record.device_id) {
+                devices.push(DeviceInfo {
+                    id: record.device_id,
+                    name: record.device_name,
+                });
+            }
+        }
+    }
+    devices
+}
+
+fn run_alias(paths: &AppPaths, config: &AppConfig, action: &AliasAction) -> anyhow::Result<()> {
+    use lit_sink_nexus::app::resolve_alias_key;
+
+    let names = config.device_names();
+    match action {
+        AliasAction::Set { device, name } => {
+            anyhow::ensure!(
+                !name.trim().is_empty(),
+                "The name is empty, use `alias remove` to show the Bluetooth name again"
+            );
+            let key = match names.aliases().contains_key(device) {
+                true => device.clone(),
+                false => resolve_alias_key(device, &known_devices(paths, config), &names)?,
+            };
+            config.set_alias(&key, Some(name))?;
+            println!("{key} is shown as {name:?}");
+        }
+        AliasAction::Remove { device } => {
+            let devices = known_devices(paths, config);
+            let key = match names.aliases().contains_key(device) {
+                true => Some(device.clone()),
+                false => {
+                    let device_id = resolve_alias_key(device, &devices, &names)?;
+                    let name = devices
+                        .iter()
+                        .find(|known| known.id == device_id)
+                        .map(|known| known.name.as_str())
+                        .unwrap_or_default();
+                    names.alias_key(&device_id, name).map(str::to_string)
+                }
+            };
+            let key = key.ok_or_else(|| anyhow::anyhow!("{device:?} has no alias"))?;
+            config.set_alias(&key, None)?;
+            println!("Removed the alias of {key}");
+        }
+        AliasAction::List => {
+            let devices = known_devices(paths, config);
+            let width = names.aliases().keys().map(String::len).max().unwrap_or(0);
+            for (key, alias) in names.aliases() {
+                let name = devices
+                    .iter()
+                    .find(|device| device.id == *key)
+                    .map(|device| format!("  ({})", device.name))
+                    .unwrap_or_default();
+                println!("{key:width$}  {alias}{name}");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_i18n(action: &I18nAction) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use lit_sink_nexus::locales::{BUILTIN, export_template, overlay};
+
+    match action {
+        I18nAction::ExportTemplate { locale, output } => {
+            let output = output
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(format!("{locale}.toml")));
+            std::fs::write(&output, export_template(&BUILTIN, overlay(), locale))
+                .with_context(|| format!("Failed to write {}", output.display()))?;
+            println!("Template written to {}", output.display());
+        }
+    }
+    Ok(())
+}
+
+fn init_logger(paths: &AppPaths) {
     let subscriber = Registry::default().with(fmt::layer());
     static LOGGER_WORKER: OnceLock<WorkerGuard> = OnceLock::new();
 
     let appender = RollingFileAppender::builder()
         .rotation(Rotation::WEEKLY)
         .filename_suffix("app.log")
-        .build(&cli.log)
+        .build(&paths.log)
         .unwrap();
 
     let (non_blocking, guard) = tracing_appender::non_blocking(appender);
@@ -50,3 +459,14 @@ fn init_logger(cli: &Cli) {
 
     subscriber.with(layer).init();
 }
+
+fn init_stderr_logger() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"));
+    Registry::default()
+        .with(
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(filter),
+        )
+        .init();
+}
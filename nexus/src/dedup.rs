@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    panic::Location,
+    sync::{LazyLock, Mutex, Once},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::log;
+
+static WARN_DEDUP: LazyLock<Mutex<WarnDedup>> = LazyLock::new(|| Mutex::new(WarnDedup::new(None)));
+
+/// How often the summaries of expired warnings are written when no new warning arrives
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Set the window in which identical warnings are logged only once, `None` logs every warning
+pub fn set_warn_dedup_window(window: Option<Duration>) {
+    let summaries = WARN_DEDUP.lock().unwrap().set_window(window);
+    log_summaries(summaries);
+}
+
+/// Write the repeat counts of every suppressed warning, called before the process exits
+pub fn flush_warnings() {
+    let summaries = WARN_DEDUP.lock().unwrap().flush_all();
+    log_summaries(summaries);
+}
+
+/// Log a warning through the global deduplicator, `caller` decides the log target
+pub(crate) fn dedup_warn(caller: &Location<'_>, message: String) {
+    let target = module_target(caller.file());
+    let now = Instant::now();
+    let (summaries, log_now) = {
+        let mut dedup = WARN_DEDUP.lock().unwrap();
+        let summaries = dedup.flush(now);
+        (summaries, dedup.check(&target, &message, now))
+    };
+
+    log_summaries(summaries);
+    if log_now {
+        log::warn!(target: &target, "{}", message);
+    } else {
+        start_flusher();
+    }
+}
+
+fn log_summaries(summaries: Vec<Summary>) {
+    for summary in summaries {
+        log::warn!(
+            target: &summary.target,
+            "{} (repeated {} times)",
+            summary.message,
+            summary.repeated
+        );
+    }
+}
+
+/// 后台定期写出过期的汇总，之后不再出现的警告也能记录重复次数
+fn start_flusher() {
+    static FLUSHER: Once = Once::new();
+    FLUSHER.call_once(|| {
+        thread::spawn(|| {
+            loop {
+                thread::sleep(FLUSH_INTERVAL);
+                let summaries = WARN_DEDUP.lock().unwrap().flush(Instant::now());
+                log_summaries(summaries);
+            }
+        });
+    });
+}
+
+/// Module path of a source file of this crate, e.g. `src/app/config.rs` is `lit_sink_nexus::app::config`
+pub fn module_target(file: &str) -> String {
+    let file = file.replace('\\', "/");
+    let path = file
+        .rsplit_once("src/")
+        .map_or(file.as_str(), |(_, path)| path)
+        .trim_end_matches(".rs");
+    let path = path.strip_suffix("/mod").unwrap_or(path);
+
+    let mut target = env!("CARGO_CRATE_NAME").to_string();
+    if path != "lib" {
+        for segment in path.split('/') {
+            target.push_str("::");
+            target.push_str(segment);
+        }
+    }
+    target
+}
+
+/// A warning suppressed within its window, written once the window ends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    pub target: String,
+    pub message: String,
+    pub repeated: u32,
+}
+
+#[derive(Debug)]
+struct Entry {
+    first_seen: Instant,
+    suppressed: u32,
+}
+
+/// Suppresses identical messages within a time window and counts the repeats
+#[derive(Debug)]
+pub struct WarnDedup {
+    window: Option<Duration>,
+    /// 以日志目标与内容为键
+    entries: HashMap<(String, String), Entry>,
+}
+
+impl WarnDedup {
+    pub fn new(window: Option<Duration>) -> Self {
+        Self {
+            window,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Change the window, returns the repeats counted so far
+    pub fn set_window(&mut self, window: Option<Duration>) -> Vec<Summary> {
+        let summaries = match window == self.window {
+            true => vec![],
+            false => self.flush_all(),
+        };
+        self.window = window;
+        summaries
+    }
+
+    /// Returns `true` if the message should be logged now
+    pub fn check(&mut self, target: &str, message: &str, now: Instant) -> bool {
+        let Some(window) = self.window else {
+            return true;
+        };
+
+        let key = (target.to_string(), message.to_string());
+        match self.entries.get_mut(&key) {
+            Some(entry) if now.duration_since(entry.first_seen) < window => {
+                entry.suppressed += 1;
+                false
+            }
+            _ => {
+                self.entries.insert(
+                    key,
+                    Entry {
+                        first_seen: now,
+                        suppressed: 0,
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Drop expired messages, returns those which were suppressed with their repeat count
+    pub fn flush(&mut self, now: Instant) -> Vec<Summary> {
+        let Some(window) = self.window else {
+            return vec![];
+        };
+        self.drain(|entry| now.duration_since(entry.first_seen) >= window)
+    }
+
+    /// Drop every message, returns those which were suppressed with their repeat count
+    pub fn flush_all(&mut self) -> Vec<Summary> {
+        self.drain(|_| true)
+    }
+
+    fn drain(&mut self, expired: impl Fn(&Entry) -> bool) -> Vec<Summary> {
+        let mut summaries = vec![];
+        self.entries.retain(|(target, message), entry| {
+            if !expired(entry) {
+                return true;
+            }
+            if entry.suppressed > 0 {
+                summaries.push(Summary {
+                    target: target.clone(),
+                    message: message.clone(),
+                    repeated: entry.suppressed,
+                });
+            }
+            false
+        });
+        summaries.sort_by(|a, b| (&a.target, &a.message).cmp(&(&b.target, &b.message)));
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn summary(target: &str, message: &str, repeated: u32) -> Summary {
+        Summary {
+            target: target.to_string(),
+            message: message.to_string(),
+            repeated,
+        }
+    }
+
+    #[test]
+    fn suppresses_repeats_within_the_window() {
+        let start = Instant::now();
+        let mut dedup = WarnDedup::new(Some(WINDOW));
+        assert!(dedup.check("a", "flap", start));
+        assert!(!dedup.check("a", "flap", start + Duration::from_secs(1)));
+        assert!(!dedup.check("a", "flap", start + Duration::from_secs(2)));
+        // 不同目标或内容分别计数
+        assert!(dedup.check("b", "flap", start));
+        assert!(dedup.check("a", "other", start));
+
+        assert_eq!(dedup.flush(start + Duration::from_secs(30)), vec![]);
+        assert_eq!(dedup.flush(start + WINDOW), vec![summary("a", "flap", 2)]);
+        assert!(dedup.check("a", "flap", start + WINDOW));
+    }
+
+    #[test]
+    fn logs_everything_without_a_window() {
+        let now = Instant::now();
+        let mut dedup = WarnDedup::new(None);
+        assert!(dedup.check("a", "flap", now));
+        assert!(dedup.check("a", "flap", now));
+        assert_eq!(dedup.flush(now + WINDOW), vec![]);
+    }
+
+    #[test]
+    fn keeps_counts_when_the_window_changes() {
+        let now = Instant::now();
+        let mut dedup = WarnDedup::new(Some(WINDOW));
+        dedup.check("a", "flap", now);
+        dedup.check("a", "flap", now);
+        assert_eq!(dedup.set_window(Some(WINDOW)), vec![]);
+        assert_eq!(dedup.set_window(None), vec![summary("a", "flap", 1)]);
+        assert!(dedup.check("a", "flap", now));
+    }
+
+    #[test]
+    fn flushes_pending_counts_at_shutdown() {
+        let now = Instant::now();
+        let mut dedup = WarnDedup::new(Some(WINDOW));
+        dedup.check("a", "once", now);
+        dedup.check("a", "flap", now);
+        dedup.check("a", "flap", now);
+        dedup.check("a", "flap", now);
+        assert_eq!(dedup.flush_all(), vec![summary("a", "flap", 2)]);
+        assert_eq!(dedup.flush_all(), vec![]);
+    }
+
+    #[test]
+    fn targets_follow_the_module_path() {
+        assert_eq!(
+            module_target("nexus/src/app/config.rs"),
+            "lit_sink_nexus::app::config"
+        );
+        assert_eq!(
+            module_target(r"nexus\src\internal\win.rs"),
+            "lit_sink_nexus::internal::win"
+        );
+        assert_eq!(
+            module_target("nexus/src/service/mod.rs"),
+            "lit_sink_nexus::service"
+        );
+        assert_eq!(module_target("src/lib.rs"), "lit_sink_nexus");
+    }
+}
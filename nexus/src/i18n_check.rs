@@ -0,0 +1,288 @@
+//! 检查 `i18n/i18n.toml` 与源码中 `t!()` 的使用是否一致，由测试与翻译覆盖文件的加载使用
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    fs,
+    path::Path,
+};
+
+/// Translations of `i18n.toml`, `key -> locale -> text`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Catalog {
+    pub translations: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Catalog {
+    /// Parse the `_version = 2` layout, where `[a.b]` holds one text per locale
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let table = content
+            .parse::<toml::Table>()
+            .map_err(|e| anyhow::anyhow!("Invalid translation file: {e}"))?;
+
+        let mut catalog = Self::default();
+        catalog.collect("", &table)?;
+        Ok(catalog)
+    }
+
+    fn collect(&mut self, prefix: &str, table: &toml::Table) -> anyhow::Result<()> {
+        for (name, value) in table {
+            if prefix.is_empty() && name.starts_with('_') {
+                continue;
+            }
+            let key = match prefix {
+                "" => name.clone(),
+                prefix => format!("{prefix}.{name}"),
+            };
+            let toml::Value::Table(inner) = value else {
+                anyhow::bail!("{key} is not a table");
+            };
+
+            if inner.values().all(toml::Value::is_str) {
+                let texts = inner
+                    .iter()
+                    .map(|(locale, text)| (locale.clone(), text.as_str().unwrap().to_string()))
+                    .collect();
+                self.translations.insert(key, texts);
+            } else {
+                self.collect(&key, inner)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `available-locales` of `[package.metadata.i18n]`
+pub fn available_locales(cargo_toml: &str) -> anyhow::Result<Vec<String>> {
+    let manifest = cargo_toml.parse::<toml::Table>()?;
+    let locales = manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("i18n"))
+        .and_then(|i18n| i18n.get("available-locales"))
+        .and_then(toml::Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("No package.metadata.i18n.available-locales"))?;
+
+    Ok(locales
+        .iter()
+        .filter_map(|locale| locale.as_str().map(str::to_string))
+        .collect())
+}
+
+/// Literal keys passed to `t!()` in Rust source
+pub fn used_keys(source: &str) -> BTreeSet<String> {
+    let mut keys = BTreeSet::new();
+    let mut rest = source;
+    while let Some(index) = rest.find("t!(") {
+        let before = rest[..index].chars().next_back();
+        let after = &rest[index + 3..];
+        rest = after;
+
+        // `format!(` 之类的宏也以 `t!(` 结尾
+        if before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+        if let Some(literal) = after.trim_start().strip_prefix('"')
+            && let Some(end) = literal.find('"')
+            && literal[..end]
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        {
+            keys.insert(literal[..end].to_string());
+        }
+    }
+    keys
+}
+
+/// Keys used by every `.rs` file under `dir`
+pub fn used_keys_in_dir(dir: &Path) -> anyhow::Result<BTreeSet<String>> {
+    let mut keys = BTreeSet::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            keys.extend(used_keys_in_dir(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            keys.extend(used_keys(&fs::read_to_string(&path)?));
+        }
+    }
+    Ok(keys)
+}
+
+/// Number of `&` accelerators, `&&` is a literal ampersand
+pub fn accelerators(text: &str) -> usize {
+    text.replace("&&", "").matches('&').count()
+}
+
+/// `%{name}` placeholders
+pub fn placeholders(text: &str) -> BTreeSet<&str> {
+    text.split("%{")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}'))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// Problems which break a locale at runtime
+    pub errors: Vec<String>,
+    /// Keys defined but never used
+    pub unused: Vec<String>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for error in &self.errors {
+            writeln!(f, "error: {error}")?;
+        }
+        for key in &self.unused {
+            writeln!(f, "unused: {key}")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn check(catalog: &Catalog, locales: &[String], used: &BTreeSet<String>) -> Report {
+    let mut report = Report::default();
+
+    for key in used {
+        if !catalog.translations.contains_key(key) {
+            report.errors.push(format!("{key} is used but not defined"));
+        }
+    }
+
+    for (key, texts) in &catalog.translations {
+        if !used.contains(key) {
+            report.unused.push(key.clone());
+        }
+
+        let mut reference: Option<(&str, &str)> = None;
+        for locale in locales {
+            let Some(text) = texts.get(locale).filter(|text| !text.trim().is_empty()) else {
+                report
+                    .errors
+                    .push(format!("{key} is not translated to {locale}"));
+                continue;
+            };
+            let Some((reference_locale, reference_text)) = reference else {
+                reference = Some((locale, text));
+                continue;
+            };
+
+            if accelerators(text) != accelerators(reference_text) {
+                report.errors.push(format!(
+                    "{key}: accelerator of {locale} {text:?} differs from {reference_locale} {reference_text:?}"
+                ));
+            }
+            if placeholders(text) != placeholders(reference_text) {
+                report.errors.push(format!(
+                    "{key}: placeholders of {locale} {text:?} differ from {reference_locale} {reference_text:?}"
+                ));
+            }
+        }
+    }
+
+    report
+}
+
+/// Check the crate in `dir` with `Cargo.toml`, `i18n/i18n.toml` and `src/`
+pub fn check_crate(dir: &Path) -> anyhow::Result<Report> {
+    let locales = available_locales(&fs::read_to_string(dir.join("Cargo.toml"))?)?;
+    let catalog = Catalog::parse(&fs::read_to_string(dir.join("i18n").join("i18n.toml"))?)?;
+    let used = used_keys_in_dir(&dir.join("src"))?;
+    Ok(check(&catalog, &locales, &used))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> BTreeSet<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_literal_keys_of_t() {
+        // 写成 `tr!(`，免得这个文件本身被当作使用了这些键
+        let source = r#"
+            let title = tr!("menu.title");
+            let text = format!("{}", name);
+            let quit = rust_i18n::tr!( "menu.quit", name = name);
+            let dynamic = tr!(key);
+            let tooltip = tr!("tooltip.connected-%{count}");
+        "#
+        .replace("tr!(", "t!(");
+        assert_eq!(used_keys(&source), keys(&["menu.quit", "menu.title"]));
+    }
+
+    #[test]
+    fn counts_accelerators_and_placeholders() {
+        assert_eq!(accelerators("&Quit"), 1);
+        assert_eq!(accelerators("Save && &Quit"), 1);
+        assert_eq!(accelerators("Tom && Jerry"), 0);
+        assert_eq!(accelerators("No accelerator"), 0);
+
+        assert_eq!(
+            placeholders("%{name} connected to %{device}, %{name} again"),
+            ["device", "name"].into()
+        );
+        assert_eq!(placeholders("100% done, %{unclosed"), BTreeSet::new());
+    }
+
+    #[test]
+    fn reports_broken_translations() {
+        let catalog = Catalog::parse(
+            r#"
+            _version = 2
+
+            [menu.quit]
+            en = "&Quit"
+            zh-CN = "退出"
+
+            [menu.about]
+            en = "About %{name}"
+            zh-CN = "关于 %{app}"
+
+            [menu.help]
+            en = "Help"
+
+            [menu.old]
+            en = "Old"
+            zh-CN = "旧"
+            "#,
+        )
+        .unwrap();
+        let locales = ["en", "zh-CN"].map(str::to_string);
+        let report = check(
+            &catalog,
+            &locales,
+            &keys(&["menu.about", "menu.help", "menu.missing", "menu.quit"]),
+        );
+
+        assert_eq!(
+            report.errors,
+            [
+                "menu.missing is used but not defined",
+                "menu.about: placeholders of zh-CN \"关于 %{app}\" differ from en \"About %{name}\"",
+                "menu.help is not translated to zh-CN",
+                "menu.quit: accelerator of zh-CN \"退出\" differs from en \"&Quit\"",
+            ]
+        );
+        assert_eq!(report.unused, ["menu.old"]);
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn crate_translations_are_complete() {
+        let report = check_crate(Path::new(env!("CARGO_MANIFEST_DIR"))).unwrap();
+        assert!(
+            report.is_ok() && report.unused.is_empty(),
+            "Please fix the translations in i18n/i18n.toml:\n{report}"
+        );
+    }
+}
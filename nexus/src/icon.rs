@@ -0,0 +1,159 @@
+//! 由SVG生成多尺寸的ICO图标，`build.rs` 也使用这里的函数
+use image::{
+    ExtendedColorType, RgbaImage,
+    codecs::ico::{IcoEncoder, IcoFrame},
+};
+use resvg::{tiny_skia, usvg};
+
+/// Sizes Windows picks from for the tray, title bars, taskbar and Explorer
+pub const ICON_SIZES: [u32; 8] = [16, 20, 24, 32, 40, 48, 64, 256];
+
+/// 任务栏主题，决定图标使用的配色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskbarTheme {
+    /// The artwork as drawn, its light background stands out on a dark taskbar
+    Dark,
+    /// Light surfaces of the artwork are darkened to stand out on a light taskbar
+    Light,
+}
+
+impl TaskbarTheme {
+    pub const ALL: [TaskbarTheme; 2] = [TaskbarTheme::Dark, TaskbarTheme::Light];
+
+    /// Light colors of the artwork and their replacement on a light taskbar
+    const LIGHT_TASKBAR_PALETTE: [(&str, &str); 5] = [
+        ("#eaf3ff", "#22314d"),
+        ("#cfe3ff", "#16213a"),
+        ("#f5f9ff", "#1b2740"),
+        ("#ffffff", "#22314d"),
+        ("\"white\"", "\"#22314d\""),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+        }
+    }
+
+    /// Recolor the SVG source for this taskbar
+    pub fn apply(&self, svg: &str) -> String {
+        match self {
+            Self::Dark => svg.to_string(),
+            Self::Light => Self::LIGHT_TASKBAR_PALETTE
+                .iter()
+                .fold(svg.to_string(), |svg, (from, to)| {
+                    svg.replace(from, to).replace(&from.to_uppercase(), to)
+                }),
+        }
+    }
+}
+
+/// Render the SVG into a `size`×`size` image, scaled to fit and centered, with straight alpha
+pub fn render_svg(svg: &str, size: u32) -> anyhow::Result<RgbaImage> {
+    let tree = usvg::Tree::from_str(svg, &Default::default())?;
+    let mut pixmap = tiny_skia::Pixmap::new(size, size)
+        .ok_or_else(|| anyhow::anyhow!("Invalid icon size {size}"))?;
+
+    let tree_size = tree.size();
+    let scale = (size as f32 / tree_size.width()).min(size as f32 / tree_size.height());
+    let transform = tiny_skia::Transform::from_scale(scale, scale).post_translate(
+        (size as f32 - tree_size.width() * scale) / 2.0,
+        (size as f32 - tree_size.height() * scale) / 2.0,
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // tiny-skia 使用预乘alpha，图标需要直通alpha
+    let data = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(size, size, data)
+        .ok_or_else(|| anyhow::anyhow!("Rendered icon has an unexpected size"))
+}
+
+/// Encode one PNG frame per size into an ICO file
+pub fn render_ico(svg: &str, sizes: &[u32]) -> anyhow::Result<Vec<u8>> {
+    let mut frames = vec![];
+    for &size in sizes {
+        let image = render_svg(svg, size)?;
+        frames.push(IcoFrame::as_png(
+            image.as_raw(),
+            size,
+            size,
+            ExtendedColorType::Rgba8,
+        )?);
+    }
+
+    let mut ico = vec![];
+    IcoEncoder::new(&mut ico).encode_images(&frames)?;
+    Ok(ico)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 左半白色、右半透明的2:1图形
+    const WIDE: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+<rect x="0" y="0" width="10" height="10" fill="#FFFFFF"/>
+</svg>"##;
+
+    #[test]
+    fn renders_centered_with_straight_alpha() {
+        let image = render_svg(WIDE, 16).unwrap();
+        assert_eq!(image.dimensions(), (16, 16));
+        // 宽图缩放到16x8并垂直居中
+        assert_eq!(image.get_pixel(0, 0).0[3], 0);
+        assert_eq!(image.get_pixel(4, 8).0, [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(12, 8).0[3], 0);
+        assert_eq!(image.get_pixel(4, 15).0[3], 0);
+        assert!(render_svg("<svg", 16).is_err());
+        assert!(render_svg(WIDE, 0).is_err());
+    }
+
+    #[test]
+    fn light_theme_darkens_light_colors() {
+        assert_eq!(TaskbarTheme::Dark.apply(WIDE), WIDE);
+        let light = TaskbarTheme::Light.apply(WIDE);
+        assert!(light.contains(r##"fill="#22314d""##));
+        assert!(!light.to_lowercase().contains("#ffffff"));
+
+        let image = render_svg(&light, 16).unwrap();
+        assert_eq!(image.get_pixel(4, 8).0, [0x22, 0x31, 0x4d, 255]);
+    }
+
+    #[test]
+    fn ico_has_one_frame_per_size() {
+        let ico = render_ico(WIDE, &ICON_SIZES).unwrap();
+        // ICONDIR: reserved, type 1, count
+        assert_eq!(&ico[..4], &[0, 0, 1, 0]);
+        assert_eq!(
+            u16::from_le_bytes([ico[4], ico[5]]) as usize,
+            ICON_SIZES.len()
+        );
+        // 每个ICONDIRENTRY以宽、高开始，256写作0
+        let sizes = (0..ICON_SIZES.len())
+            .map(|i| ico[6 + i * 16] as u32)
+            .map(|size| if size == 0 { 256 } else { size })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, ICON_SIZES);
+    }
+
+    #[test]
+    fn renders_the_application_logo() {
+        let svg = include_str!("../assets/nexus.logo.svg");
+        for theme in TaskbarTheme::ALL {
+            let image = render_svg(&theme.apply(svg), 32).unwrap();
+            assert!(
+                image.pixels().any(|pixel| pixel.0[3] > 0),
+                "{} icon is empty",
+                theme.name()
+            );
+        }
+    }
+}
@@ -1,6 +1,14 @@
 pub mod app;
+mod dedup;
+pub mod i18n_check;
+pub mod icon;
 mod internal;
+pub mod locales;
+#[cfg(windows)]
 mod resource;
+pub mod service;
 
-rust_i18n::i18n!();
+rust_i18n::i18n!(backend = locales::OverlayBackend);
+pub use dedup::{flush_warnings, set_warn_dedup_window};
+#[cfg(windows)]
 pub use internal::init_i18n;
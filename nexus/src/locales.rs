@@ -0,0 +1,332 @@
+//! 从配置文件旁的 `locales/` 目录加载翻译，覆盖内置的 `i18n/i18n.toml`
+use crate::i18n_check::{Catalog, accelerators, placeholders};
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    fs,
+    path::Path,
+    sync::{LazyLock, OnceLock},
+};
+use tracing::log;
+
+/// Built-in translations, overlays may only use their keys
+pub static BUILTIN: LazyLock<Catalog> = LazyLock::new(|| {
+    Catalog::parse(include_str!("../i18n/i18n.toml")).expect("Invalid built-in translations")
+});
+
+/// Locale whose text is the reference for accelerators and placeholders
+const REFERENCE_LOCALE: &str = "en";
+
+static OVERLAY: OnceLock<Catalog> = OnceLock::new();
+
+/// rust-i18n backend with the loaded overlays, it is asked before the built-in translations
+pub struct OverlayBackend;
+
+impl rust_i18n::Backend for OverlayBackend {
+    fn available_locales(&self) -> Vec<&str> {
+        OVERLAY
+            .get()
+            .map(|overlay| overlay_locales(overlay).into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    fn translate(&self, locale: &str, key: &str) -> Option<&str> {
+        OVERLAY
+            .get()?
+            .translations
+            .get(key)?
+            .get(locale)
+            .map(String::as_str)
+    }
+}
+
+/// Locales with at least one translation in the overlays
+pub fn overlay_locales(overlay: &Catalog) -> BTreeSet<&str> {
+    overlay
+        .translations
+        .values()
+        .flat_map(|texts| texts.keys().map(String::as_str))
+        .collect()
+}
+
+/// The overlays loaded by [`load_overlays`]
+pub fn overlay() -> Option<&'static Catalog> {
+    OVERLAY.get()
+}
+
+/// Keep the translations of `overlay` which can replace the built-in ones
+///
+/// Empty texts are left out, so are unknown keys and texts whose accelerators or placeholders
+/// differ from the built-in English text, each with a warning.
+pub fn validate_overlay(builtin: &Catalog, overlay: Catalog) -> (Catalog, Vec<String>) {
+    let mut valid = Catalog::default();
+    let mut warnings = vec![];
+
+    for (key, texts) in overlay.translations {
+        let Some(builtin_texts) = builtin.translations.get(&key) else {
+            warnings.push(format!("{key} is not a known key"));
+            continue;
+        };
+        let reference = builtin_texts.get(REFERENCE_LOCALE);
+
+        for (locale, text) in texts {
+            // 模板中未翻译的键
+            if text.trim().is_empty() {
+                continue;
+            }
+            if let Some(reference) = reference {
+                if accelerators(&text) != accelerators(reference) {
+                    warnings.push(format!(
+                        "{key}: accelerator of {locale} {text:?} differs from {reference:?}"
+                    ));
+                    continue;
+                }
+                if placeholders(&text) != placeholders(reference) {
+                    warnings.push(format!(
+                        "{key}: placeholders of {locale} {text:?} differ from {reference:?}"
+                    ));
+                    continue;
+                }
+            }
+            valid
+                .translations
+                .entry(key.clone())
+                .or_default()
+                .insert(locale, text);
+        }
+    }
+
+    (valid, warnings)
+}
+
+/// Read every `*.toml` of `dir` in file name order, later files win over earlier ones
+///
+/// Files which cannot be parsed are skipped, all problems are returned as warnings.
+pub fn read_overlays(builtin: &Catalog, dir: &Path) -> anyhow::Result<(Catalog, Vec<String>)> {
+    let mut files = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"));
+    files.sort();
+
+    let mut merged = Catalog::default();
+    let mut warnings = vec![];
+    for file in files {
+        let overlay = match fs::read_to_string(&file)
+            .map_err(anyhow::Error::from)
+            .and_then(|content| Catalog::parse(&content))
+        {
+            Ok(overlay) => overlay,
+            Err(e) => {
+                warnings.push(format!("{}: {e}", file.display()));
+                continue;
+            }
+        };
+
+        let (valid, file_warnings) = validate_overlay(builtin, overlay);
+        warnings.extend(
+            file_warnings
+                .into_iter()
+                .map(|warning| format!("{}: {warning}", file.display())),
+        );
+        for (key, texts) in valid.translations {
+            merged.translations.entry(key).or_default().extend(texts);
+        }
+    }
+    Ok((merged, warnings))
+}
+
+/// Load the overlays of `dir` for the rest of the process, a missing directory is not an error
+pub fn load_overlays(dir: &Path) {
+    if !dir.is_dir() {
+        return;
+    }
+
+    match read_overlays(&BUILTIN, dir) {
+        Ok((overlay, warnings)) => {
+            for warning in warnings {
+                log::warn!("Ignoring translation {warning}");
+            }
+            log::info!(
+                "Loaded translations for {:?} from {:?}",
+                overlay_locales(&overlay),
+                dir
+            );
+            if OVERLAY.set(overlay).is_err() {
+                log::warn!("Translations are already loaded, ignoring {:?}", dir);
+            }
+        }
+        Err(e) => log::warn!("Failed to read translations from {:?}: {:?}", dir, e),
+    }
+}
+
+/// Translation file with every built-in key for `locale`, existing translations filled in
+///
+/// The English text of each key is written above it as a reference for translators.
+pub fn export_template(builtin: &Catalog, overlay: Option<&Catalog>, locale: &str) -> String {
+    let quote = |text: &str| toml::Value::String(text.to_string()).to_string();
+
+    let mut template = String::new();
+    writeln!(template, "# Translations for {locale}").unwrap();
+    writeln!(
+        template,
+        "# Put this file into the `locales` directory next to the config,"
+    )
+    .unwrap();
+    writeln!(
+        template,
+        "# keep the (&X) accelerators and %{{name}} placeholders of the English"
+    )
+    .unwrap();
+    writeln!(template, "# text. Keys left empty are shown in English.").unwrap();
+    writeln!(template, "_version = 2").unwrap();
+
+    for (key, texts) in &builtin.translations {
+        let text = overlay
+            .and_then(|overlay| overlay.translations.get(key)?.get(locale))
+            .or_else(|| texts.get(locale))
+            .map(String::as_str)
+            .unwrap_or_default();
+
+        writeln!(template).unwrap();
+        writeln!(template, "[{key}]").unwrap();
+        if let Some(reference) = texts.get(REFERENCE_LOCALE) {
+            writeln!(template, "# {REFERENCE_LOCALE} = {}", quote(reference)).unwrap();
+        }
+        writeln!(template, "{} = {}", quote_key(locale), quote(text)).unwrap();
+    }
+    template
+}
+
+/// `zh-CN` is a valid bare key, anything else is quoted
+fn quote_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+    if bare {
+        key.to_string()
+    } else {
+        toml::Value::String(key.to_string()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin() -> Catalog {
+        Catalog::parse(
+            r#"
+            _version = 2
+
+            [menu.about]
+            en = "About %{name}"
+
+            [menu.help]
+            en = "Say \"help\""
+            ja = "ヘルプ"
+
+            [menu.quit]
+            en = "&Quit"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn text<'a>(catalog: &'a Catalog, key: &str, locale: &str) -> Option<&'a str> {
+        catalog
+            .translations
+            .get(key)?
+            .get(locale)
+            .map(String::as_str)
+    }
+
+    #[test]
+    fn keeps_only_translations_matching_the_builtin_ones() {
+        let overlay = Catalog::parse(
+            r#"
+            [menu.quit]
+            ja = "終了(&Q)"
+            fr = "Quitter"
+            de = ""
+
+            [menu.about]
+            ja = "%{name} について"
+            fr = "À propos de %{app}"
+
+            [menu.unknown]
+            ja = "不明"
+            "#,
+        )
+        .unwrap();
+
+        let (valid, warnings) = validate_overlay(&builtin(), overlay);
+        assert_eq!(text(&valid, "menu.quit", "ja"), Some("終了(&Q)"));
+        assert_eq!(text(&valid, "menu.about", "ja"), Some("%{name} について"));
+        assert_eq!(valid.translations.len(), 2);
+        assert_eq!(valid.translations["menu.quit"].len(), 1);
+        assert_eq!(valid.translations["menu.about"].len(), 1);
+
+        // 空文本是模板中未翻译的键，不警告
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].starts_with("menu.about: placeholders of fr"));
+        assert!(warnings[1].starts_with("menu.quit: accelerator of fr"));
+        assert_eq!(warnings[2], "menu.unknown is not a known key");
+    }
+
+    #[test]
+    fn later_overlay_files_win() {
+        let dir = std::env::temp_dir().join(format!("nexus-test-locales-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("b.toml"),
+            "[menu.help]\nja = \"ヘルプB\"\n[menu.quit]\nja = \"終了(&Q)\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("c.toml"), "[menu.help]\nja = \"ヘルプC\"\n").unwrap();
+        fs::write(dir.join("a.toml"), "[menu.help]\nja = \"ヘルプA\"\n").unwrap();
+        fs::write(dir.join("d.toml"), "[menu.help\nja = ").unwrap();
+        fs::write(dir.join("e.txt"), "[menu.help]\nja = \"ヘルプE\"\n").unwrap();
+
+        let (overlay, warnings) = read_overlays(&builtin(), &dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(text(&overlay, "menu.help", "ja"), Some("ヘルプC"));
+        assert_eq!(text(&overlay, "menu.quit", "ja"), Some("終了(&Q)"));
+        assert_eq!(warnings.len(), 1, "{warnings:?}");
+        assert!(warnings[0].contains("d.toml"));
+    }
+
+    #[test]
+    fn exported_templates_parse_back() {
+        let overlay = Catalog::parse("[menu.about]\nja = \"%{name} について\"\n").unwrap();
+        let template = export_template(&builtin(), Some(&overlay), "ja");
+        assert!(template.contains("# en = \"About %{name}\"\nja = \"%{name} について\""));
+
+        let parsed = Catalog::parse(&template).unwrap();
+        assert_eq!(text(&parsed, "menu.about", "ja"), Some("%{name} について"));
+        assert_eq!(text(&parsed, "menu.help", "ja"), Some("ヘルプ"));
+        assert_eq!(text(&parsed, "menu.quit", "ja"), Some(""));
+        // 英文参考只是注释
+        assert_eq!(text(&parsed, "menu.help", "en"), None);
+
+        let (valid, warnings) = validate_overlay(&builtin(), parsed);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(text(&valid, "menu.about", "ja"), Some("%{name} について"));
+        assert_eq!(text(&valid, "menu.quit", "ja"), None);
+    }
+
+    #[test]
+    fn quotes_keys_which_are_not_bare() {
+        assert_eq!(quote_key("zh-CN"), "zh-CN");
+        assert_eq!(quote_key("sr_Latn"), "sr_Latn");
+        assert_eq!(quote_key("sr-Latn@x"), "\"sr-Latn@x\"");
+        assert_eq!(quote_key(""), "\"\"");
+
+        let template = export_template(&builtin(), None, "sr-Latn@x");
+        let parsed = Catalog::parse(&template).unwrap();
+        assert_eq!(text(&parsed, "menu.quit", "sr-Latn@x"), Some(""));
+    }
+}
@@ -1 +1,3 @@
 pub const APP_ICON: u16 = 101;
+/// 浅色任务栏使用的托盘图标
+pub const APP_ICON_LIGHT_TASKBAR: u16 = 102;
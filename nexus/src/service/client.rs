@@ -0,0 +1,251 @@
+use crate::{
+    app::{AudioBackend, DeviceInfo, EventListener},
+    service::{
+        protocol::{Request, Response, decode, encode},
+        transport::{self, ClientStream},
+    },
+};
+use anyhow::Context;
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    thread,
+    time::Duration,
+};
+use tracing::log;
+
+/// 同步的IPC客户端，一问一答
+pub struct IpcClient<S: Read + Write> {
+    stream: BufReader<S>,
+}
+
+impl<S: Read + Write> IpcClient<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    pub fn request(&mut self, request: &Request) -> anyhow::Result<Response> {
+        self.stream
+            .get_mut()
+            .write_all(encode(request)?.as_bytes())?;
+        self.read_response()
+    }
+
+    /// Subscribe to backend events, blocks until the server closes the connection
+    pub fn subscribe(mut self, listener: EventListener) -> anyhow::Result<()> {
+        match self.request(&Request::Subscribe)? {
+            Response::Ok => {}
+            response => anyhow::bail!("Unexpected response to subscribe: {:?}", response),
+        }
+
+        loop {
+            match self.read_response()? {
+                Response::Event { event } => listener(event),
+                response => log::warn!("Unexpected message from service: {:?}", response),
+            }
+        }
+    }
+
+    fn read_response(&mut self) -> anyhow::Result<Response> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line)? == 0 {
+            anyhow::bail!("Service closed the connection");
+        }
+        decode(&line)
+    }
+}
+
+/// How long the event subscription waits before connecting to a restarted service
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(2);
+
+/// 托盘进程通过IPC使用服务进程中的连接
+///
+/// Every request uses its own pipe connection, a slow connect doesn't hold up other requests and
+/// a restarted service is picked up by the next request.
+pub struct ServiceBackend {
+    endpoint: String,
+}
+
+impl ServiceBackend {
+    pub fn connect(endpoint: &str) -> anyhow::Result<Self> {
+        let backend = Self {
+            endpoint: endpoint.to_string(),
+        };
+        backend.open()?;
+        Ok(backend)
+    }
+
+    fn open(&self) -> anyhow::Result<IpcClient<ClientStream>> {
+        let stream = transport::connect(&self.endpoint)
+            .context(format!("Failed to connect to service at {}", self.endpoint))?;
+        Ok(IpcClient::new(stream))
+    }
+
+    fn request(&self, request: Request) -> anyhow::Result<Response> {
+        match self.open()?.request(&request)? {
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            Response::ConnectFailed { error } => Err(error.into()),
+            response => Ok(response),
+        }
+    }
+}
+
+impl AudioBackend for ServiceBackend {
+    fn devices(&self) -> anyhow::Result<Vec<DeviceInfo>> {
+        match self.request(Request::Devices)? {
+            Response::Devices { devices } => Ok(devices),
+            response => anyhow::bail!("Unexpected response: {:?}", response),
+        }
+    }
+
+    fn connect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.request(Request::Connect {
+            device_id: device_id.to_string(),
+        })?;
+        Ok(())
+    }
+
+    fn disconnect(&self, device_id: &str) -> anyhow::Result<()> {
+        self.request(Request::Disconnect {
+            device_id: device_id.to_string(),
+        })?;
+        Ok(())
+    }
+
+    fn connections(&self) -> anyhow::Result<Vec<String>> {
+        match self.request(Request::Connections)? {
+            Response::Connections { device_ids } => Ok(device_ids),
+            response => anyhow::bail!("Unexpected response: {:?}", response),
+        }
+    }
+
+    fn subscribe(&self, listener: EventListener) {
+        let backend = Self {
+            endpoint: self.endpoint.clone(),
+        };
+        thread::spawn(move || {
+            let mut available = true;
+            loop {
+                match backend.open() {
+                    Ok(client) => {
+                        if !available {
+                            log::info!("Service is available again, resubscribing");
+                        }
+                        if let Err(e) = client.subscribe(listener.clone()) {
+                            log::warn!("Service event subscription lost: {:?}", e);
+                        }
+                        available = false;
+                    }
+                    // 服务重启期间只记录一次
+                    Err(e) if available => {
+                        log::warn!("Service event subscription failed: {:?}", e);
+                        available = false;
+                    }
+                    Err(_) => {}
+                }
+                thread::sleep(RESUBSCRIBE_DELAY);
+            }
+        });
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{
+        app::{BackendEvent, ConnectError, OpenStatus, SimulatedBackend},
+        service::transport::serve,
+    };
+    use std::{
+        sync::{Arc, mpsc},
+        time::Instant,
+    };
+    use tokio::runtime::Runtime;
+
+    fn endpoint(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("nexus-{}-{}.sock", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn start(runtime: &Runtime, endpoint: &str, backend: Arc<SimulatedBackend>) {
+        runtime.spawn(serve(endpoint.to_string(), backend));
+    }
+
+    fn connect(endpoint: &str) -> ServiceBackend {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match ServiceBackend::connect(endpoint) {
+                Ok(backend) => return backend,
+                Err(e) if Instant::now() > deadline => panic!("{e:?}"),
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    fn simulated() -> Arc<SimulatedBackend> {
+        Arc::new(SimulatedBackend::new([DeviceInfo {
+            id: "a".to_string(),
+            name: "Phone".to_string(),
+        }]))
+    }
+
+    #[test]
+    fn forwards_requests_to_the_service() {
+        let runtime = Runtime::new().unwrap();
+        let endpoint = endpoint("requests");
+        let simulated = simulated();
+        start(&runtime, &endpoint, simulated.clone());
+        let backend = connect(&endpoint);
+
+        assert_eq!(backend.devices().unwrap().len(), 1);
+        backend.connect("a").unwrap();
+        assert_eq!(backend.connections().unwrap(), vec!["a".to_string()]);
+        backend.disconnect("a").unwrap();
+        assert_eq!(backend.connections().unwrap(), Vec::<String>::new());
+
+        let error = ConnectError::new(OpenStatus::DeniedBySystem, 0);
+        simulated.set_failure("a", Some(error));
+        let e = backend.connect("a").unwrap_err();
+        assert_eq!(e.downcast_ref::<ConnectError>(), Some(&error));
+        assert!(backend.connect("unknown").is_err());
+    }
+
+    #[test]
+    fn resubscribes_after_the_service_restarts() {
+        let endpoint = endpoint("events");
+        let first = Runtime::new().unwrap();
+        let simulated = simulated();
+        start(&first, &endpoint, simulated.clone());
+        let backend = connect(&endpoint);
+
+        let (sender, receiver) = mpsc::channel();
+        backend.subscribe(Arc::new(move |event| sender.send(event).unwrap()));
+        let connected = BackendEvent::Connected {
+            device_id: "a".to_string(),
+        };
+        // 订阅在线程中建立，重复连接直到收到事件
+        let wait_for_event = || {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            loop {
+                assert!(Instant::now() < deadline, "no event received");
+                simulated.drop_connection("a");
+                simulated.connect("a").unwrap();
+                if let Ok(event) = receiver.recv_timeout(Duration::from_millis(100))
+                    && event == connected
+                {
+                    return;
+                }
+            }
+        };
+        wait_for_event();
+
+        drop(first);
+        let second = Runtime::new().unwrap();
+        start(&second, &endpoint, simulated.clone());
+        while receiver.try_recv().is_ok() {}
+        wait_for_event();
+    }
+}
@@ -0,0 +1,411 @@
+use crate::app::{AudioBackend, AutoConnectPolicy};
+use std::{
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+use tracing::log;
+
+/// 服务控制管理器发送给服务的命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlCommand {
+    Start,
+    Stop,
+    Pause,
+    Resume,
+    Other(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceStatus {
+    Stopped,
+    Running,
+    Paused,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceSpec {
+    pub name: String,
+    pub display_name: String,
+    pub description: String,
+    pub command_line: String,
+}
+
+impl ServiceSpec {
+    pub const NAME: &str = "LitSinkNexus";
+
+    pub fn new(executable: &Path, config: &Path, log: &Path) -> Self {
+        Self {
+            name: Self::NAME.to_string(),
+            display_name: "Lit Audio Sink Nexus".to_string(),
+            description: env!("CARGO_PKG_DESCRIPTION").to_string(),
+            command_line: format!(
+                "\"{}\" --config \"{}\" --log \"{}\" service run",
+                executable.display(),
+                config.display(),
+                log.display()
+            ),
+        }
+    }
+}
+
+pub type ControlHandler = Box<dyn FnMut(ControlCommand) + Send + Sync>;
+
+/// 对Windows服务控制管理器的抽象
+pub trait ServiceHost {
+    fn install(&self, spec: &ServiceSpec) -> anyhow::Result<()>;
+
+    fn uninstall(&self, name: &str) -> anyhow::Result<()>;
+
+    /// Dispatch control commands to the handler, blocks until the service stops
+    fn run(&self, handler: ControlHandler) -> anyhow::Result<()>;
+}
+
+/// 根据控制命令管理服务中的连接
+pub struct ServiceController {
+    backend: Arc<dyn AudioBackend>,
+    policy: AutoConnectPolicy,
+    status: ServiceStatus,
+    paused_connections: Vec<String>,
+    /// 启动时的自动连接，控制回调必须尽快返回，连接在线程中进行
+    auto_connect: Option<AutoConnectTask>,
+}
+
+struct AutoConnectTask {
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl ServiceController {
+    pub fn new(backend: Arc<dyn AudioBackend>, policy: AutoConnectPolicy) -> Self {
+        Self {
+            backend,
+            policy,
+            status: ServiceStatus::Stopped,
+            paused_connections: vec![],
+            auto_connect: None,
+        }
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        self.status
+    }
+
+    pub fn handle(&mut self, command: ControlCommand) -> ServiceStatus {
+        log::info!("Service command {:?} in status {:?}", command, self.status);
+
+        match (self.status, command) {
+            (ServiceStatus::Stopped, ControlCommand::Start) => {
+                self.status = ServiceStatus::Running;
+                if self.policy.at_startup {
+                    self.start_auto_connect();
+                }
+            }
+            (ServiceStatus::Running, ControlCommand::Pause) => {
+                self.stop_auto_connect();
+                self.paused_connections = self.disconnect_all();
+                self.status = ServiceStatus::Paused;
+            }
+            (ServiceStatus::Paused, ControlCommand::Resume) => {
+                for device_id in std::mem::take(&mut self.paused_connections) {
+                    if let Err(e) = self.backend.connect(&device_id) {
+                        log::warn!("Failed to restore {} after resume: {:#}", device_id, e);
+                    }
+                }
+                self.status = ServiceStatus::Running;
+            }
+            (ServiceStatus::Running | ServiceStatus::Paused, ControlCommand::Stop) => {
+                self.stop_auto_connect();
+                self.disconnect_all();
+                self.paused_connections.clear();
+                self.status = ServiceStatus::Stopped;
+            }
+            (status, command) => {
+                log::debug!("Ignoring service command {:?} in {:?}", command, status);
+            }
+        }
+
+        self.status
+    }
+
+    fn start_auto_connect(&mut self) {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let backend = self.backend.clone();
+            let policy = self.policy.clone();
+            let cancel = cancel.clone();
+            move || connect_allowed(&*backend, &policy, &cancel)
+        });
+        self.auto_connect = Some(AutoConnectTask { cancel, thread });
+    }
+
+    /// Skip the devices not connected yet and wait for the current one
+    fn stop_auto_connect(&mut self) {
+        if let Some(task) = self.auto_connect.take() {
+            task.cancel.store(true, Ordering::Relaxed);
+            if task.thread.join().is_err() {
+                log::error!("Auto connect thread panicked");
+            }
+        }
+    }
+
+    fn disconnect_all(&self) -> Vec<String> {
+        let connections = self.backend.connections().unwrap_or_default();
+        for device_id in &connections {
+            if let Err(e) = self.backend.disconnect(device_id) {
+                log::warn!("Failed to disconnect {}: {:#}", device_id, e);
+            }
+        }
+        connections
+    }
+}
+
+impl Drop for ServiceController {
+    fn drop(&mut self) {
+        self.stop_auto_connect();
+    }
+}
+
+fn connect_allowed(backend: &dyn AudioBackend, policy: &AutoConnectPolicy, cancel: &AtomicBool) {
+    let devices = match backend.devices() {
+        Ok(devices) => devices,
+        Err(e) => {
+            log::error!("Failed to enumerate devices: {:?}", e);
+            return;
+        }
+    };
+    // 独占模式下已有连接的源设备时保持不变
+    if policy.exclusive && !backend.connections().unwrap_or_default().is_empty() {
+        return;
+    }
+
+    for device in policy.select(&devices) {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        log::info!("Auto connecting to: {}({})", device.name, device.id);
+        match backend.connect(&device.id) {
+            // 独占模式下只连接第一个成功的设备
+            Ok(()) if policy.exclusive => return,
+            Ok(()) => {}
+            Err(e) => log::error!("Auto connect failed: {:#}", e),
+        }
+    }
+}
+
+/// 按预设顺序发送控制命令的 [`ServiceHost`]，用于在没有服务控制管理器的环境下运行服务逻辑
+#[derive(Default)]
+pub struct ScriptedHost {
+    commands: Vec<ControlCommand>,
+    installed: Mutex<Vec<ServiceSpec>>,
+}
+
+impl ScriptedHost {
+    pub fn new(commands: impl IntoIterator<Item = ControlCommand>) -> Self {
+        Self {
+            commands: commands.into_iter().collect(),
+            installed: Default::default(),
+        }
+    }
+
+    pub fn installed(&self) -> Vec<ServiceSpec> {
+        self.installed.lock().unwrap().clone()
+    }
+}
+
+impl ServiceHost for ScriptedHost {
+    fn install(&self, spec: &ServiceSpec) -> anyhow::Result<()> {
+        let mut installed = self.installed.lock().unwrap();
+        if installed.iter().any(|s| s.name == spec.name) {
+            anyhow::bail!("Service {} already installed", spec.name);
+        }
+        installed.push(spec.clone());
+        Ok(())
+    }
+
+    fn uninstall(&self, name: &str) -> anyhow::Result<()> {
+        let mut installed = self.installed.lock().unwrap();
+        let before = installed.len();
+        installed.retain(|s| s.name != name);
+        if before == installed.len() {
+            anyhow::bail!("Service {} is not installed", name);
+        }
+        Ok(())
+    }
+
+    fn run(&self, mut handler: ControlHandler) -> anyhow::Result<()> {
+        for command in &self.commands {
+            handler(*command);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{BackendCall, ConnectError, DeviceInfo, OpenStatus, SimulatedBackend};
+    use std::time::{Duration, Instant};
+
+    fn device(id: &str, name: &str) -> DeviceInfo {
+        DeviceInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn backend() -> Arc<SimulatedBackend> {
+        Arc::new(SimulatedBackend::new([
+            device("a", "Phone"),
+            device("b", "Tablet"),
+            device("c", "Laptop"),
+        ]))
+    }
+
+    fn policy(allowed: &[&str]) -> AutoConnectPolicy {
+        AutoConnectPolicy {
+            at_startup: true,
+            allowed: allowed.iter().map(|entry| entry.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// 通过 [`ScriptedHost`] 运行控制器，返回最终状态
+    fn run(controller: ServiceController, commands: &[ControlCommand]) -> ServiceStatus {
+        let controller = Arc::new(Mutex::new(controller));
+        let host = ScriptedHost::new(commands.iter().copied());
+        host.run(Box::new({
+            let controller = controller.clone();
+            move |command| {
+                controller.lock().unwrap().handle(command);
+            }
+        }))
+        .unwrap();
+        controller.lock().unwrap().status()
+    }
+
+    #[test]
+    fn start_connects_only_allowed_devices() {
+        let backend = backend();
+        let mut controller = ServiceController::new(backend.clone(), policy(&["a", "laptop"]));
+        assert_eq!(
+            controller.handle(ControlCommand::Start),
+            ServiceStatus::Running
+        );
+
+        // 控制命令立即返回，连接在线程中完成
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.connections().unwrap().len() < 2 {
+            assert!(
+                std::time::Instant::now() < deadline,
+                "{:?}",
+                backend.calls()
+            );
+            thread::yield_now();
+        }
+        assert_eq!(
+            backend.calls(),
+            vec![
+                BackendCall::Devices,
+                BackendCall::Connect("a".to_string()),
+                BackendCall::Connect("c".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn exclusive_start_connects_the_first_allowed_device() {
+        let backend = backend();
+        backend.set_failure("c", Some(ConnectError::new(OpenStatus::DeniedBySystem, 0)));
+        let policy = AutoConnectPolicy {
+            exclusive: true,
+            ..policy(&["laptop", "b", "a"])
+        };
+        let mut controller = ServiceController::new(backend.clone(), policy);
+        controller.handle(ControlCommand::Start);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.connections().unwrap().is_empty() {
+            assert!(Instant::now() < deadline, "{:?}", backend.calls());
+            thread::yield_now();
+        }
+        drop(controller);
+        assert_eq!(
+            backend.calls(),
+            vec![
+                BackendCall::Devices,
+                BackendCall::Connect("c".to_string()),
+                BackendCall::Connect("b".to_string())
+            ]
+        );
+        assert_eq!(backend.connections().unwrap(), ["b"]);
+    }
+
+    #[test]
+    fn start_without_auto_connect_connects_nothing() {
+        let backend = backend();
+        let controller = ServiceController::new(backend.clone(), AutoConnectPolicy::default());
+        let status = run(controller, &[ControlCommand::Start]);
+
+        assert_eq!(status, ServiceStatus::Running);
+        assert_eq!(backend.calls(), vec![]);
+    }
+
+    #[test]
+    fn pause_and_resume_restore_connections() {
+        let backend = backend();
+        let mut controller = ServiceController::new(backend.clone(), AutoConnectPolicy::default());
+        controller.handle(ControlCommand::Start);
+        backend.connect("b").unwrap();
+
+        assert_eq!(
+            controller.handle(ControlCommand::Pause),
+            ServiceStatus::Paused
+        );
+        assert_eq!(backend.connections().unwrap(), Vec::<String>::new());
+        assert_eq!(
+            controller.handle(ControlCommand::Resume),
+            ServiceStatus::Running
+        );
+        assert_eq!(backend.connections().unwrap(), vec!["b".to_string()]);
+
+        assert_eq!(
+            controller.handle(ControlCommand::Stop),
+            ServiceStatus::Stopped
+        );
+        assert_eq!(backend.connections().unwrap(), Vec::<String>::new());
+        // 停止后不会再恢复暂停前的连接
+        assert_eq!(
+            controller.handle(ControlCommand::Resume),
+            ServiceStatus::Stopped
+        );
+    }
+
+    #[test]
+    fn stop_waits_for_auto_connect() {
+        let backend = backend();
+        let controller = ServiceController::new(backend.clone(), policy(&[]));
+        let status = run(controller, &[ControlCommand::Start, ControlCommand::Stop]);
+
+        assert_eq!(status, ServiceStatus::Stopped);
+        assert_eq!(backend.connections().unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn scripted_host_tracks_installations() {
+        let spec = ServiceSpec::new(
+            Path::new("nexus.exe"),
+            Path::new("config.toml"),
+            Path::new("logs"),
+        );
+        let host = ScriptedHost::default();
+        host.install(&spec).unwrap();
+        assert!(host.install(&spec).is_err());
+        assert_eq!(host.installed(), vec![spec.clone()]);
+        host.uninstall(&spec.name).unwrap();
+        assert!(host.uninstall(&spec.name).is_err());
+    }
+}
@@ -0,0 +1,459 @@
+use crate::app::{
+    AppConfig, AudioBackend, BackendEvent, ConnectError, ConnectRefused, DeviceControl, DeviceInfo,
+};
+use axum::{
+    Json, Router,
+    extract::{
+        Path, Query, Request, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+use tokio::{net::TcpListener, sync::broadcast};
+use tracing::log;
+
+/// 配置文件中的 `[api]`，供仪表盘等本机程序使用的HTTP接口
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub enabled: bool,
+    /// Port on 127.0.0.1
+    pub port: u16,
+    /// Required as `Authorization: Bearer <token>` or `?token=<token>`, generated when empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 7690,
+            token: None,
+        }
+    }
+}
+
+/// 事件流中的状态变化，每条消息一个JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApiEvent {
+    Connected { device_id: String },
+    Disconnected { device_id: String },
+    AutoConnect { enabled: bool },
+}
+
+impl From<BackendEvent> for ApiEvent {
+    fn from(event: BackendEvent) -> Self {
+        match event {
+            BackendEvent::Connected { device_id } => Self::Connected { device_id },
+            BackendEvent::Disconnected { device_id } => Self::Disconnected { device_id },
+        }
+    }
+}
+
+/// A device as listed by `GET /devices`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiDevice {
+    pub id: String,
+    pub name: String,
+    pub alias: Option<String>,
+    pub display_name: String,
+    pub connected: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoConnectBody {
+    pub enabled: bool,
+}
+
+#[derive(Clone)]
+struct ApiState {
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    /// 连接与断开经由它执行，独占模式同样适用
+    control: Arc<dyn DeviceControl>,
+    token: Arc<str>,
+    events: broadcast::Sender<ApiEvent>,
+}
+
+/// 请求失败时返回的状态码与 `{"error": ...}`
+struct ApiError {
+    status: StatusCode,
+    message: String,
+    connect_error: Option<ConnectError>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            connect_error: None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(error) = e.downcast_ref::<ConnectError>() {
+            return Self {
+                status: StatusCode::BAD_GATEWAY,
+                message: error.to_string(),
+                connect_error: Some(*error),
+            };
+        }
+        let status = match e.is::<ConnectRefused>() {
+            true => StatusCode::FORBIDDEN,
+            false => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, format!("{e:#}"))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "error": self.message,
+            "connect_error": self.connect_error,
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Routes of the API, every one of them needs `token`
+pub fn api_router(
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    control: Arc<dyn DeviceControl>,
+    token: &str,
+) -> Router {
+    let (events, _) = broadcast::channel(64);
+    backend.subscribe(Arc::new({
+        let events = events.clone();
+        move |event| {
+            let _ = events.send(ApiEvent::from(event));
+        }
+    }));
+    let state = ApiState {
+        config,
+        backend,
+        control,
+        token: token.into(),
+        events,
+    };
+
+    Router::new()
+        .route("/devices", get(devices))
+        .route("/connections", get(connections))
+        .route("/devices/{id}/connect", post(connect))
+        .route("/devices/{id}/disconnect", post(disconnect))
+        .route(
+            "/config/auto_connect",
+            get(auto_connect).put(set_auto_connect),
+        )
+        .route("/events", get(event_stream))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state)
+}
+
+/// Serve the API on 127.0.0.1 until the task is dropped
+pub async fn serve_http(
+    config: Arc<AppConfig>,
+    backend: Arc<dyn AudioBackend>,
+    control: Arc<dyn DeviceControl>,
+) -> anyhow::Result<()> {
+    let settings = config.api();
+    let token = match settings.token.filter(|token| !token.is_empty()) {
+        Some(token) => token,
+        None => {
+            let token = generate_token();
+            config.set_api_token(&token)?;
+            log::info!(
+                "Generated a token for the HTTP API in {:?}",
+                config.file_path
+            );
+            token
+        }
+    };
+
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, settings.port));
+    let listener = TcpListener::bind(address).await?;
+    log::info!("HTTP API listening on http://{}", address);
+    axum::serve(listener, api_router(config, backend, control, &token)).await?;
+    Ok(())
+}
+
+fn generate_token() -> String {
+    use rand::{Rng, distr::Alphanumeric};
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+async fn authorize(
+    State(state): State<ApiState>,
+    Query(query): Query<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // 浏览器的WebSocket不能设置请求头，事件流也接受查询参数中的token
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let token = bearer.or(query.get("token").map(String::as_str));
+    match token {
+        Some(token) if constant_time_eq(token.as_bytes(), state.token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => ApiError::new(StatusCode::UNAUTHORIZED, "Missing or invalid token").into_response(),
+    }
+}
+
+/// Compare without returning at the first difference, the time doesn't tell how much matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Run a blocking backend call outside of the async workers
+async fn blocking<T: Send + 'static>(
+    state: &ApiState,
+    call: impl FnOnce(&dyn AudioBackend) -> anyhow::Result<T> + Send + 'static,
+) -> ApiResult<T> {
+    let backend = state.backend.clone();
+    let result = tokio::task::spawn_blocking(move || call(&*backend))
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(result?)
+}
+
+/// Connect or disconnect through the control outside of the async workers
+async fn control(
+    state: &ApiState,
+    call: impl FnOnce(&dyn DeviceControl) -> anyhow::Result<()> + Send + 'static,
+) -> ApiResult<()> {
+    let control = state.control.clone();
+    let result = tokio::task::spawn_blocking(move || call(&*control))
+        .await
+        .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(result?)
+}
+
+async fn devices(State(state): State<ApiState>) -> ApiResult<Json<Vec<ApiDevice>>> {
+    let (devices, connected) = blocking(&state, |backend| {
+        Ok((backend.devices()?, backend.connections()?))
+    })
+    .await?;
+    let names = state.config.device_names();
+    let devices = devices
+        .into_iter()
+        .map(|device| ApiDevice {
+            alias: names.alias(&device.id, &device.name).map(str::to_string),
+            display_name: names.device_name(&device),
+            connected: connected.contains(&device.id),
+            id: device.id,
+            name: device.name,
+        })
+        .collect();
+    Ok(Json(devices))
+}
+
+async fn connections(State(state): State<ApiState>) -> ApiResult<Json<Vec<String>>> {
+    Ok(Json(
+        blocking(&state, |backend| backend.connections()).await?,
+    ))
+}
+
+async fn connect(State(state): State<ApiState>, Path(id): Path<String>) -> ApiResult<StatusCode> {
+    find_device(&state, &id).await?;
+    log::info!("HTTP API connecting {}", id);
+    control(&state, move |control| control.connect(&id)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn disconnect(
+    State(state): State<ApiState>,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    find_device(&state, &id).await?;
+    log::info!("HTTP API disconnecting {}", id);
+    control(&state, move |control| control.disconnect(&id)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn find_device(state: &ApiState, id: &str) -> ApiResult<DeviceInfo> {
+    let devices = blocking(state, |backend| backend.devices()).await?;
+    devices
+        .into_iter()
+        .find(|device| device.id == id)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Unknown device {id}")))
+}
+
+async fn auto_connect(State(state): State<ApiState>) -> Json<AutoConnectBody> {
+    Json(AutoConnectBody {
+        enabled: state.config.auto_connect(),
+    })
+}
+
+async fn set_auto_connect(
+    State(state): State<ApiState>,
+    Json(body): Json<AutoConnectBody>,
+) -> Json<AutoConnectBody> {
+    log::info!("HTTP API set auto connect to {}", body.enabled);
+    state.config.set_auto_connect(body.enabled);
+    let _ = state.events.send(ApiEvent::AutoConnect {
+        enabled: body.enabled,
+    });
+    Json(body)
+}
+
+async fn event_stream(State(state): State<ApiState>, upgrade: WebSocketUpgrade) -> Response {
+    let receiver = state.events.subscribe();
+    upgrade.on_upgrade(move |socket| stream_events(socket, receiver))
+}
+
+/// Send every event as a text message until the client goes away
+async fn stream_events(mut socket: WebSocket, mut receiver: broadcast::Receiver<ApiEvent>) {
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    let Ok(text) = serde_json::to_string(&event) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    log::warn!("HTTP API subscriber lagged, {} events dropped", count);
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // 客户端只会发送关闭与心跳消息
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{BackendControl, SimulatedBackend, test_config};
+    use axum::body::{Body, to_bytes};
+    use futures_util::StreamExt;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "secret";
+
+    fn backend() -> Arc<SimulatedBackend> {
+        Arc::new(SimulatedBackend::new(["a", "b"].map(|id| DeviceInfo {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+        })))
+    }
+
+    fn router(name: &str, backend: Arc<SimulatedBackend>) -> Router {
+        let config = Arc::new(test_config(
+            name,
+            "exclusive = true\nhandover_delay_secs = 0\n",
+        ));
+        let control = Arc::new(BackendControl::new(config.clone(), backend.clone()));
+        api_router(config, backend, control, TOKEN)
+    }
+
+    async fn send(router: &Router, method: &str, uri: &str, token: Option<&str>) -> Response {
+        let mut request = axum::http::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        router
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_without_the_token() {
+        let router = router("http-token", backend());
+
+        let response = send(&router, "GET", "/devices", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&router, "GET", "/devices", Some("wrong")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send(&router, "GET", "/devices?token=wrong", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = send(&router, "GET", "/devices?token=secret", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let devices: Vec<ApiDevice> = serde_json::from_slice(&body).unwrap();
+        let ids = devices.iter().map(|device| device.id.as_str());
+        assert_eq!(ids.collect::<Vec<_>>(), ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn connects_and_disconnects_through_the_control() {
+        let backend = backend();
+        backend.connect("a").unwrap();
+        let router = router("http-control", backend.clone());
+
+        // 独占模式下先断开 a
+        let response = send(&router, "POST", "/devices/b/connect", Some(TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(backend.connections().unwrap(), ["b"]);
+
+        let response = send(&router, "POST", "/devices/b/disconnect", Some(TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(backend.connections().unwrap().is_empty());
+
+        let response = send(&router, "POST", "/devices/c/connect", Some(TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn streams_events_over_websocket() {
+        let backend = backend();
+        let router = router("http-events", backend.clone());
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let url = format!("ws://{address}/events?token={TOKEN}");
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        backend.connect("a").unwrap();
+
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let event: ApiEvent = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        assert_eq!(
+            event,
+            ApiEvent::Connected {
+                device_id: "a".to_string()
+            }
+        );
+    }
+}
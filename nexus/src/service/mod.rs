@@ -0,0 +1,56 @@
+mod client;
+mod host;
+mod http;
+mod protocol;
+#[cfg(windows)]
+mod scm;
+mod server;
+mod transport;
+
+pub use client::*;
+pub use host::*;
+pub use http::*;
+pub use protocol::*;
+#[cfg(windows)]
+pub use scm::*;
+pub use server::*;
+pub use transport::*;
+
+use crate::app::{AppConfig, AudioBackend};
+use std::sync::{Arc, Mutex};
+use tracing::log;
+
+/// Run the connection logic as a service and serve tray clients on `endpoint`
+pub fn run_service(
+    host: &dyn ServiceHost,
+    backend: Arc<dyn AudioBackend>,
+    config: &AppConfig,
+    endpoint: String,
+) -> anyhow::Result<()> {
+    let server = tokio::runtime::Handle::current().spawn({
+        let backend = backend.clone();
+        async move {
+            if let Err(e) = serve(endpoint, backend).await {
+                log::error!("IPC server stopped: {:?}", e);
+            }
+        }
+    });
+
+    let controller = Mutex::new(ServiceController::new(
+        backend,
+        config.auto_connect_policy(),
+    ));
+    let stop_server = server.abort_handle();
+    let result = host.run(Box::new(move |command| {
+        let status = controller.lock().unwrap().handle(command);
+        log::debug!("Service status: {:?}", status);
+        // SCM中的 `run` 不会返回，服务停止时在这里结束IPC服务
+        if command == ControlCommand::Stop {
+            log::info!("Stopping the IPC server");
+            stop_server.abort();
+        }
+    }));
+
+    server.abort();
+    result
+}
@@ -0,0 +1,39 @@
+use crate::app::{BackendEvent, ConnectError, DeviceInfo};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+/// 托盘进程发送给服务进程的请求，每行一个JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Devices,
+    Connections,
+    Connect {
+        device_id: String,
+    },
+    Disconnect {
+        device_id: String,
+    },
+    /// Turn the connection into a stream of [`Response::Event`]
+    Subscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Devices { devices: Vec<DeviceInfo> },
+    Connections { device_ids: Vec<String> },
+    ConnectFailed { error: ConnectError },
+    Error { message: String },
+    Event { event: BackendEvent },
+}
+
+pub fn encode<T: Serialize>(message: &T) -> anyhow::Result<String> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    Ok(line)
+}
+
+pub fn decode<T: DeserializeOwned>(line: &str) -> anyhow::Result<T> {
+    Ok(serde_json::from_str(line.trim_end())?)
+}
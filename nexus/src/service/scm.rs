@@ -0,0 +1,68 @@
+use crate::service::host::{ControlCommand, ControlHandler, ServiceHost, ServiceSpec};
+use std::process::Command;
+use tracing::log;
+
+/// 通过 `sc.exe` 和服务控制管理器运行服务
+pub struct ScmHost;
+
+impl ScmHost {
+    fn sc<I, S>(args: I) -> anyhow::Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        let output = Command::new("sc.exe").args(args).output()?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "sc.exe failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stdout).trim()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl ServiceHost for ScmHost {
+    fn install(&self, spec: &ServiceSpec) -> anyhow::Result<()> {
+        log::info!("Installing service {}: {}", spec.name, spec.command_line);
+        Self::sc([
+            "create",
+            &spec.name,
+            "binPath=",
+            &spec.command_line,
+            "start=",
+            "auto",
+            "DisplayName=",
+            &spec.display_name,
+        ])?;
+        Self::sc(["description", &spec.name, &spec.description])
+    }
+
+    fn uninstall(&self, name: &str) -> anyhow::Result<()> {
+        log::info!("Uninstalling service {}", name);
+        if let Err(e) = Self::sc(["stop", name]) {
+            log::debug!("Service {} not stopped: {:?}", name, e);
+        }
+        Self::sc(["delete", name])
+    }
+
+    fn run(&self, mut handler: ControlHandler) -> anyhow::Result<()> {
+        windows_services::Service::new()
+            .can_stop()
+            .can_pause()
+            .run(move |_, command| {
+                let command = match command {
+                    windows_services::Command::Start => ControlCommand::Start,
+                    windows_services::Command::Stop => ControlCommand::Stop,
+                    windows_services::Command::Pause => ControlCommand::Pause,
+                    windows_services::Command::Resume => ControlCommand::Resume,
+                    windows_services::Command::Extended(extended) => {
+                        ControlCommand::Other(extended.control)
+                    }
+                };
+                handler(command);
+            })
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    app::{AudioBackend, BackendEvent, ConnectError},
+    service::protocol::{Request, Response, decode, encode},
+};
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    sync::broadcast,
+};
+use tracing::log;
+
+/// Execute a request against the backend
+pub fn handle_request(backend: &dyn AudioBackend, request: Request) -> Response {
+    let result = match request {
+        Request::Devices => backend
+            .devices()
+            .map(|devices| Response::Devices { devices }),
+        Request::Connections => backend
+            .connections()
+            .map(|device_ids| Response::Connections { device_ids }),
+        Request::Connect { device_id } => backend.connect(&device_id).map(|_| Response::Ok),
+        Request::Disconnect { device_id } => backend.disconnect(&device_id).map(|_| Response::Ok),
+        Request::Subscribe => Ok(Response::Ok),
+    };
+
+    result.unwrap_or_else(|e| match e.downcast_ref::<ConnectError>() {
+        Some(error) => Response::ConnectFailed { error: *error },
+        None => Response::Error {
+            message: format!("{e:#}"),
+        },
+    })
+}
+
+/// Forward backend events to a broadcast channel shared by all subscribed clients
+pub fn event_channel(backend: &dyn AudioBackend) -> broadcast::Sender<BackendEvent> {
+    let (sender, _) = broadcast::channel(64);
+    backend.subscribe(Arc::new({
+        let sender = sender.clone();
+        move |event| {
+            let _ = sender.send(event);
+        }
+    }));
+    sender
+}
+
+/// Serve one client until it disconnects
+pub async fn serve_connection<S>(
+    stream: S,
+    backend: Arc<dyn AudioBackend>,
+    events: broadcast::Sender<BackendEvent>,
+) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        let request = match decode::<Request>(&line) {
+            Ok(request) => request,
+            Err(e) => {
+                log::warn!("Invalid request {:?}: {:?}", line, e);
+                let response = Response::Error {
+                    message: format!("Invalid request: {e}"),
+                };
+                writer.write_all(encode(&response)?.as_bytes()).await?;
+                continue;
+            }
+        };
+        log::debug!("IPC request: {:?}", request);
+
+        if request == Request::Subscribe {
+            let mut receiver = events.subscribe();
+            writer.write_all(encode(&Response::Ok)?.as_bytes()).await?;
+
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        let response = Response::Event { event };
+                        writer.write_all(encode(&response)?.as_bytes()).await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        log::warn!("IPC subscriber lagged, {} events dropped", count);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                }
+            }
+        }
+
+        let response = tokio::task::spawn_blocking({
+            let backend = backend.clone();
+            move || handle_request(&*backend, request)
+        })
+        .await?;
+        writer.write_all(encode(&response)?.as_bytes()).await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::{
+    app::AudioBackend,
+    service::server::{event_channel, serve_connection},
+};
+use std::sync::Arc;
+use tracing::log;
+
+#[cfg(windows)]
+pub type ClientStream = std::fs::File;
+#[cfg(unix)]
+pub type ClientStream = std::os::unix::net::UnixStream;
+
+/// 服务进程监听的本地端点：Windows上为命名管道，其它平台为Unix socket
+pub fn default_endpoint() -> String {
+    #[cfg(windows)]
+    {
+        r"\\.\pipe\LitSinkNexus".to_string()
+    }
+    #[cfg(unix)]
+    {
+        std::env::temp_dir()
+            .join("lit-sink-nexus.sock")
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+pub fn connect(endpoint: &str) -> std::io::Result<ClientStream> {
+    #[cfg(windows)]
+    {
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(endpoint)
+    }
+    #[cfg(unix)]
+    {
+        std::os::unix::net::UnixStream::connect(endpoint)
+    }
+}
+
+/// Accept clients on the endpoint until the task is aborted
+#[cfg(unix)]
+pub async fn serve(endpoint: String, backend: Arc<dyn AudioBackend>) -> anyhow::Result<()> {
+    std::fs::remove_file(&endpoint).ok();
+    let listener = tokio::net::UnixListener::bind(&endpoint)?;
+    let events = event_channel(&*backend);
+    log::info!("IPC server listening on {}", endpoint);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn({
+            let backend = backend.clone();
+            let events = events.clone();
+            async move {
+                if let Err(e) = serve_connection(stream, backend, events).await {
+                    log::warn!("IPC connection failed: {:?}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Accept clients on the endpoint until the task is aborted
+#[cfg(windows)]
+pub async fn serve(endpoint: String, backend: Arc<dyn AudioBackend>) -> anyhow::Result<()> {
+    let events = event_channel(&*backend);
+    let mut server = pipe_server(&endpoint, true)?;
+    log::info!("IPC server listening on {}", endpoint);
+
+    loop {
+        server.connect().await?;
+        let stream = std::mem::replace(&mut server, pipe_server(&endpoint, false)?);
+        tokio::spawn({
+            let backend = backend.clone();
+            let events = events.clone();
+            async move {
+                if let Err(e) = serve_connection(stream, backend, events).await {
+                    log::warn!("IPC connection failed: {:?}", e);
+                }
+            }
+        });
+    }
+}
+
+/// 服务以SYSTEM运行，需要允许交互式用户读写管道
+#[cfg(windows)]
+fn pipe_server(
+    endpoint: &str,
+    first: bool,
+) -> std::io::Result<tokio::net::windows::named_pipe::NamedPipeServer> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+    use windows::{
+        Win32::{
+            Foundation::{HLOCAL, LocalFree},
+            Security::{
+                Authorization::{
+                    ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1,
+                },
+                PSECURITY_DESCRIPTOR, SECURITY_ATTRIBUTES,
+            },
+        },
+        core::w,
+    };
+
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            w!("D:(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;IU)"),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            None,
+        )
+    }?;
+
+    let mut attributes = SECURITY_ATTRIBUTES {
+        nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+        lpSecurityDescriptor: descriptor.0,
+        bInheritHandle: false.into(),
+    };
+    let server = unsafe {
+        ServerOptions::new()
+            .first_pipe_instance(first)
+            .create_with_security_attributes_raw(endpoint, &mut attributes as *mut _ as *mut _)
+    };
+    unsafe { LocalFree(Some(HLOCAL(descriptor.0))) };
+
+    server
+}
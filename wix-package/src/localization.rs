@@ -0,0 +1,259 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+/// MSI culture of an application locale
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Culture {
+    pub locale: String,
+    pub name: &'static str,
+    pub codepage: u32,
+    pub language: u32,
+}
+
+impl Culture {
+    pub fn for_locale(locale: &str) -> anyhow::Result<Self> {
+        let (name, codepage, language) = match locale {
+            "en" | "en-US" => ("en-US", 1252, 1033),
+            "zh-CN" => ("zh-CN", 936, 2052),
+            "zh-TW" => ("zh-TW", 950, 1028),
+            _ => anyhow::bail!("No MSI culture known for locale {locale}"),
+        };
+
+        Ok(Self {
+            locale: locale.to_string(),
+            name,
+            codepage,
+            language,
+        })
+    }
+}
+
+/// Read `package.metadata.i18n.available-locales` of a Cargo manifest
+pub fn available_locales(cargo_toml: &Path) -> anyhow::Result<Vec<String>> {
+    let content = fs::read_to_string(cargo_toml)
+        .map_err(|e| anyhow::anyhow!("Unable to read {}: {e}", cargo_toml.display()))?;
+    let manifest = content
+        .parse::<toml::Table>()
+        .map_err(|e| anyhow::anyhow!("Invalid manifest {}: {e}", cargo_toml.display()))?;
+
+    manifest
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("i18n"))
+        .and_then(|i18n| i18n.get("available-locales"))
+        .and_then(|locales| locales.as_array())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} has no package.metadata.i18n.available-locales",
+                cargo_toml.display()
+            )
+        })?
+        .iter()
+        .map(|locale| {
+            locale
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("Invalid locale {locale}"))
+        })
+        .collect()
+}
+
+/// String ids referenced as `!(loc.Id)` in WiX sources
+pub fn used_string_ids(wxs: &str) -> BTreeSet<String> {
+    wxs.split("!(loc.")
+        .skip(1)
+        .filter_map(|rest| rest.split_once(')'))
+        .map(|(id, _)| id.to_string())
+        .collect()
+}
+
+pub fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Installer strings in the `i18n.toml` layout of the application: `[Id] locale = "value"`
+#[derive(Debug, Default)]
+pub struct Translations {
+    strings: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Translations {
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let table = content
+            .parse::<toml::Table>()
+            .map_err(|e| anyhow::anyhow!("Invalid translation table: {e}"))?;
+
+        let mut strings = BTreeMap::new();
+        for (id, values) in table {
+            if id.starts_with('_') {
+                continue;
+            }
+            let values = values
+                .as_table()
+                .ok_or_else(|| anyhow::anyhow!("String {id} is not a table of locales"))?
+                .iter()
+                .map(|(locale, value)| match value.as_str() {
+                    Some(value) => Ok((locale.clone(), value.to_string())),
+                    None => Err(anyhow::anyhow!(
+                        "String {id} has a non-string value for {locale}"
+                    )),
+                })
+                .collect::<Result<_, _>>()?;
+            strings.insert(id, values);
+        }
+
+        Ok(Self { strings })
+    }
+
+    /// Every used id must exist and every id must be translated to every locale
+    pub fn check(&self, locales: &[String], used_ids: &BTreeSet<String>) -> anyhow::Result<()> {
+        let mut problems = vec![];
+
+        for id in used_ids {
+            if !self.strings.contains_key(id) {
+                problems.push(format!("{id}: used by the installer but not translated"));
+            }
+        }
+        for (id, values) in &self.strings {
+            for locale in locales {
+                if values
+                    .get(locale)
+                    .is_none_or(|value| value.trim().is_empty())
+                {
+                    problems.push(format!("{id}: missing {locale}"));
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "Incomplete installer translations:\n  {}",
+                problems.join("\n  ")
+            );
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str, locale: &str) -> Option<&str> {
+        self.strings.get(id)?.get(locale).map(String::as_str)
+    }
+
+    pub fn render_wxl(&self, culture: &Culture) -> String {
+        let mut wxl = format!(
+            "<WixLocalization Culture=\"{}\" Codepage=\"{}\" Language=\"{}\" xmlns=\"http://wixtoolset.org/schemas/v4/wxl\">\n",
+            culture.name, culture.codepage, culture.language
+        );
+        for (id, values) in &self.strings {
+            if let Some(value) = values.get(&culture.locale) {
+                writeln!(
+                    wxl,
+                    "    <String Id=\"{}\" Value=\"{}\" />",
+                    xml_escape(id),
+                    xml_escape(value)
+                )
+                .unwrap();
+            }
+        }
+        wxl.push_str("</WixLocalization>\n");
+        wxl
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSLATIONS: &str = r#"
+_version = 2
+
+[ApplicationName]
+en = "Nexus & Co"
+zh-CN = "中心"
+zh-TW = "中心"
+
+[Unused]
+en = "<unused>"
+zh-CN = "未使用"
+zh-TW = "未使用"
+"#;
+
+    fn locales() -> Vec<String> {
+        ["en", "zh-CN", "zh-TW"].map(String::from).to_vec()
+    }
+
+    fn workspace_locales() -> Vec<String> {
+        let cargo_toml = Path::new(env!("CARGO_MANIFEST_DIR")).join("../nexus/Cargo.toml");
+        available_locales(&cargo_toml).unwrap()
+    }
+
+    #[test]
+    fn every_app_locale_has_a_culture() {
+        let locales = workspace_locales();
+        assert!(!locales.is_empty());
+        let names = locales
+            .iter()
+            .map(|locale| Culture::for_locale(locale).unwrap().name)
+            .collect::<BTreeSet<_>>();
+        // 每个语言生成一个不同的.wxl
+        assert_eq!(names.len(), locales.len());
+        assert!(Culture::for_locale("fr").is_err());
+    }
+
+    #[test]
+    fn renders_one_wxl_per_locale() {
+        let translations = Translations::parse(TRANSLATIONS).unwrap();
+        let used_ids = used_string_ids(r#"<Package Name="!(loc.ApplicationName)" />"#);
+        translations.check(&locales(), &used_ids).unwrap();
+
+        let wxls = locales()
+            .iter()
+            .map(|locale| translations.render_wxl(&Culture::for_locale(locale).unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            wxls[0],
+            "<WixLocalization Culture=\"en-US\" Codepage=\"1252\" Language=\"1033\" xmlns=\"http://wixtoolset.org/schemas/v4/wxl\">\n    \
+             <String Id=\"ApplicationName\" Value=\"Nexus &amp; Co\" />\n    \
+             <String Id=\"Unused\" Value=\"&lt;unused&gt;\" />\n\
+             </WixLocalization>\n"
+        );
+        assert!(wxls[1].starts_with("<WixLocalization Culture=\"zh-CN\" Codepage=\"936\""));
+        assert!(wxls[2].contains("Value=\"中心\""));
+    }
+
+    #[test]
+    fn reports_missing_translations() {
+        let translations = Translations::parse(TRANSLATIONS).unwrap();
+        let mut locales = locales();
+        locales.push("ja".to_string());
+        let used_ids = BTreeSet::from(["Missing".to_string()]);
+
+        let message = translations
+            .check(&locales, &used_ids)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains("Missing: used by the installer but not translated"));
+        assert!(message.contains("ApplicationName: missing ja"));
+        assert!(message.contains("Unused: missing ja"));
+    }
+
+    #[test]
+    fn finds_used_string_ids() {
+        let ids = used_string_ids(r#"<A B="!(loc.First)" C="!(loc.Second) !(loc.First)" />"#);
+        assert_eq!(ids, BTreeSet::from(["First".into(), "Second".into()]));
+    }
+}
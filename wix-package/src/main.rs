@@ -1,78 +1,477 @@
-use std::{env, fs, path::PathBuf, process::Command};
+mod localization;
+mod plan;
+mod portable;
+mod release;
+mod sbom;
+mod winget;
+mod wxi;
+
+use anyhow::Context;
+use clap::{Parser, ValueEnum};
+use localization::{Culture, Translations, available_locales, used_string_ids};
+use plan::{Plan, Step};
+use portable::PortableArchive;
+use release::{WorkspaceMetadata, checksums};
+use std::{
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+use wxi::render_variable_wxi;
+
+const APP_NAME: &str = "LitAudioSinkNexus";
+const CHECKSUMS: &str = "SHA256SUMS";
+
+/// Build the MSI installers of nexus
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Write var.wxi and the .wxl files and print the build plan without running cargo or wix
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Cargo target directory, defaults to `target` in the workspace
+    #[arg(long, value_name = "DIR")]
+    target_dir: Option<PathBuf>,
+
+    /// Cargo profile used to build nexus
+    #[arg(long, default_value = "release")]
+    profile: String,
+
+    /// Target architecture, defaults to the host
+    #[arg(long, value_enum)]
+    arch: Option<Arch>,
+
+    /// Directory of the generated installers, defaults to the target directory
+    #[arg(long, value_name = "DIR")]
+    out: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Arch {
+    X64,
+    X86,
+    Arm64,
+}
+
+impl Arch {
+    fn triple(&self) -> &'static str {
+        match self {
+            Self::X64 => "x86_64-pc-windows-msvc",
+            Self::X86 => "i686-pc-windows-msvc",
+            Self::Arm64 => "aarch64-pc-windows-msvc",
+        }
+    }
+
+    fn wix_arch(&self) -> &'static str {
+        match self {
+            Self::X64 => "x64",
+            Self::X86 => "x86",
+            Self::Arm64 => "arm64",
+        }
+    }
+}
+
+/// Paths of one installer build
+#[derive(Debug)]
+struct Layout {
+    workspace_dir: PathBuf,
+    wix_dir: PathBuf,
+    target_dir: PathBuf,
+    out_dir: PathBuf,
+}
+
+impl Layout {
+    fn new(cli: &Cli) -> Self {
+        let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let workspace_dir = manifest_dir.parent().unwrap().to_path_buf();
+        let target_dir = cli
+            .target_dir
+            .clone()
+            .unwrap_or_else(|| workspace_dir.join("target"));
+
+        Self {
+            wix_dir: manifest_dir.join("wix"),
+            out_dir: cli.out.clone().unwrap_or_else(|| target_dir.clone()),
+            target_dir,
+            workspace_dir,
+        }
+    }
+
+    fn exe_file(&self, cli: &Cli) -> PathBuf {
+        let mut dir = self.target_dir.clone();
+        if let Some(arch) = cli.arch {
+            dir.push(arch.triple());
+        }
+        // the dev profile is built into `debug`
+        match cli.profile.as_str() {
+            "dev" => dir.join("debug").join("nexus.exe"),
+            profile => dir.join(profile).join("nexus.exe"),
+        }
+    }
+}
 
 fn main() {
-    println!("Starting Wix installer generation...");
-    generate_variable_wxi();
+    let cli = Cli::parse();
+
+    if let Err(e) = run(&cli) {
+        eprintln!("Error: {e:?}");
+        std::process::exit(1);
+    }
 }
 
-fn generate_variable_wxi() {
-    let app_name = "LitAudioSinkNexus";
+fn run(cli: &Cli) -> anyhow::Result<()> {
+    println!("Starting Wix installer generation...");
+    let layout = Layout::new(cli);
 
-    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
-    println!("Manifest dir: {}", manifest_dir.to_string_lossy());
-    let workspace_dir = manifest_dir.parent().unwrap().to_path_buf();
-    let wix_dir = manifest_dir.join("wix");
-    let wxi_path = wix_dir.join("var.wxi");
-    let config_toml = workspace_dir.join("config.toml");
+    generate_variable_wxi(cli, &layout)?;
+    let cultures = generate_localizations(&layout.workspace_dir, &layout.wix_dir)
+        .context("Unable to generate .wxl files")?;
+    let plan = build_plan(cli, &layout, &cultures);
+    let portable = portable_archive(cli, &layout);
+    let portable_path = layout.out_dir.join(portable_file_name(cli));
 
-    let exe_file = workspace_dir
-        .join("target")
-        .join("release")
-        .join("nexus.exe");
-    let license_file = workspace_dir.join("LICENSE");
+    if cli.dry_run {
+        print!("Build plan:\n{plan}");
+        println!("Portable archive {}:", portable_path.display());
+        for path in portable.layout() {
+            println!("    {path}");
+        }
+        println!("Release files in {}:", layout.out_dir.display());
+        for name in [
+            exe_file_name(cli),
+            CHECKSUMS.to_string(),
+            sbom_file_name(),
+            "winget/".to_string(),
+        ] {
+            println!("    {name}");
+        }
+        return Ok(());
+    }
 
-    let version = env!("CARGO_PKG_VERSION");
-    let upgrade_code =
-        uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_X500, app_name.as_bytes()).to_string();
+    fs::create_dir_all(&layout.out_dir)?;
+    plan.run()?;
+    portable.write_file(&portable_path)?;
+    println!("Generated {}", portable_path.to_string_lossy());
+    write_release_files(cli, &layout, &cultures, &portable_path)
+        .context("Unable to write release metadata")?;
+    Ok(())
+}
+
+fn generate_variable_wxi(cli: &Cli, layout: &Layout) -> anyhow::Result<()> {
+    let wxi_path = layout.wix_dir.join("var.wxi");
+    let config_toml = default_config_file(layout);
+    let license_file = layout.workspace_dir.join("LICENSE");
 
     let directives = vec![
-        ("AppName", app_name),
-        ("Manufacturer", env!("CARGO_PKG_AUTHORS")),
-        ("Version", env!("CARGO_PKG_VERSION")),
-        ("UpgradeCode", &upgrade_code),
-        ("AppDes", env!("CARGO_PKG_DESCRIPTION")),
-        ("AppExe", exe_file.to_str().unwrap()),
-        ("LicenseFile", license_file.to_str().unwrap()),
-        ("ConfigFile", config_toml.to_str().unwrap()),
+        ("AppName", APP_NAME.to_string()),
+        ("Manufacturer", env!("CARGO_PKG_AUTHORS").to_string()),
+        ("Version", env!("CARGO_PKG_VERSION").to_string()),
+        ("UpgradeCode", upgrade_code()),
+        ("AppDes", env!("CARGO_PKG_DESCRIPTION").to_string()),
+        ("AppExe", path_string(&layout.exe_file(cli))?),
+        ("LicenseFile", path_string(&license_file)?),
+        ("ConfigFile", path_string(&config_toml)?),
     ];
 
-    let mut wxi_content = String::new();
-    for (key, value) in directives {
-        wxi_content.push_str(&format!("<?define {}=\"{}\" ?>\n", key, value));
-    }
-
-    let wxi_content = format!(
-        r#"<Include xmlns="http://wixtoolset.org/schemas/v4/wxs">{}{}</Include>"#,
-        "\n", wxi_content
-    );
-
-    fs::write(&wxi_path, wxi_content).expect("Unable to write var.wxi file");
-
-    let installer_name = format!("{}-Setup-{}.msi", app_name, version);
-    let installer_path = workspace_dir.join("target").join(installer_name);
-
-    Command::new("cargo")
-        .args(["build", "--release", "--bin", "nexus"])
-        .current_dir(&workspace_dir)
-        .status()
-        .unwrap();
-
-    let _command = Command::new("wix")
-        .args([
-            "build",
-            "*.wxs",
-            "*.wxl",
-            "-ext",
-            "WixToolset.UI.wixext",
-            "-culture",
-            "zh-CN",
-            "-o",
-            installer_path.to_str().unwrap(),
-        ])
-        .current_dir(&wix_dir)
-        .status()
-        .unwrap();
-
-    let dummy_config = wix_dir.join("config.toml");
-    fs::write(&dummy_config, "").expect("Unable to write dummy config.toml");
+    let wxi_content = render_variable_wxi(&directives)?;
+    fs::write(&wxi_path, wxi_content).context("Unable to write var.wxi file")?;
+    println!("Generated {}", wxi_path.to_string_lossy());
+    Ok(())
+}
+
+/// Write one `.wxl` per locale of the application, returns the cultures to build
+fn generate_localizations(workspace_dir: &Path, wix_dir: &Path) -> anyhow::Result<Vec<Culture>> {
+    let locales = available_locales(&workspace_dir.join("nexus").join("Cargo.toml"))?;
+    let translations = Translations::parse(&fs::read_to_string(wix_dir.join("i18n.toml"))?)?;
+
+    let mut used_ids = BTreeSet::new();
+    for entry in fs::read_dir(wix_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "wxs") {
+            used_ids.extend(used_string_ids(&fs::read_to_string(&path)?));
+        }
+    }
+    translations.check(&locales, &used_ids)?;
+
+    let mut cultures = vec![];
+    for locale in &locales {
+        let culture = Culture::for_locale(locale)?;
+        let wxl_path = wix_dir.join(culture.name).with_extension("wxl");
+        fs::write(&wxl_path, translations.render_wxl(&culture))?;
+        println!("Generated {}", wxl_path.to_string_lossy());
+        cultures.push(culture);
+    }
+    Ok(cultures)
+}
+
+fn build_plan(cli: &Cli, layout: &Layout, cultures: &[Culture]) -> Plan {
+    let mut plan = Plan::default();
+
+    let mut cargo_args = vec!["build", "--profile", &cli.profile, "--bin", "nexus"];
+    if let Some(arch) = cli.arch {
+        cargo_args.extend(["--target", arch.triple()]);
+    }
+    let target_dir = layout.target_dir.to_string_lossy();
+    if cli.target_dir.is_some() {
+        cargo_args.extend(["--target-dir", &target_dir]);
+    }
+    plan.steps
+        .push(Step::new("cargo", cargo_args, &layout.workspace_dir));
+
+    for culture in cultures {
+        let installer_path = layout.out_dir.join(installer_file_name(cli, culture));
+        let wxl = format!("{}.wxl", culture.name);
+
+        let mut wix_args = vec![
+            "build".to_string(),
+            "*.wxs".to_string(),
+            wxl,
+            "-ext".to_string(),
+            "WixToolset.UI.wixext".to_string(),
+            "-culture".to_string(),
+            culture.name.to_string(),
+        ];
+        if let Some(arch) = cli.arch {
+            wix_args.extend(["-arch".to_string(), arch.wix_arch().to_string()]);
+        }
+        wix_args.extend([
+            "-o".to_string(),
+            installer_path.to_string_lossy().into_owned(),
+        ]);
+        plan.steps.push(Step::new("wix", wix_args, &layout.wix_dir));
+    }
+
+    plan
+}
+
+fn installer_file_name(cli: &Cli, culture: &Culture) -> String {
+    match cli.arch {
+        Some(arch) => format!(
+            "{}-Setup-{}-{}-{}.msi",
+            APP_NAME,
+            env!("CARGO_PKG_VERSION"),
+            arch.wix_arch(),
+            culture.name
+        ),
+        None => format!(
+            "{}-Setup-{}-{}.msi",
+            APP_NAME,
+            env!("CARGO_PKG_VERSION"),
+            culture.name
+        ),
+    }
+}
+
+fn exe_file_name(cli: &Cli) -> String {
+    match cli.arch {
+        Some(arch) => format!(
+            "{}-{}-{}.exe",
+            APP_NAME,
+            env!("CARGO_PKG_VERSION"),
+            arch.wix_arch()
+        ),
+        None => format!("{}-{}.exe", APP_NAME, env!("CARGO_PKG_VERSION")),
+    }
+}
+
+fn sbom_file_name() -> String {
+    format!("{}-{}.cdx.json", APP_NAME, env!("CARGO_PKG_VERSION"))
+}
+
+/// Copy the exe next to the installers, then write the checksums, SBOM and winget manifests
+fn write_release_files(
+    cli: &Cli,
+    layout: &Layout,
+    cultures: &[Culture],
+    portable_path: &Path,
+) -> anyhow::Result<()> {
+    let metadata = WorkspaceMetadata::read(&layout.workspace_dir.join("Cargo.toml"))?;
+    // 未指定架构时按x64发布
+    let arch = cli.arch.unwrap_or(Arch::X64);
+
+    let exe_path = layout.out_dir.join(exe_file_name(cli));
+    fs::copy(layout.exe_file(cli), &exe_path)
+        .with_context(|| format!("Unable to copy nexus.exe to {}", exe_path.display()))?;
+
+    let installers = cultures
+        .iter()
+        .map(|culture| {
+            (
+                culture,
+                layout.out_dir.join(installer_file_name(cli, culture)),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut files = installers
+        .iter()
+        .map(|(_, path)| path.as_path())
+        .collect::<Vec<_>>();
+    files.extend([exe_path.as_path(), portable_path]);
+    write_release_file(&layout.out_dir.join(CHECKSUMS), &checksums(&files)?)?;
+
+    let cargo_metadata = sbom::cargo_metadata(&layout.workspace_dir, arch.triple())?;
+    let bom = sbom::cyclonedx(&cargo_metadata, "lit-sink-nexus")?;
+    write_release_file(
+        &layout.out_dir.join(sbom_file_name()),
+        &(serde_json::to_string_pretty(&bom)? + "\n"),
+    )?;
+
+    let translations = Translations::parse(&fs::read_to_string(layout.wix_dir.join("i18n.toml"))?)?;
+    let locale = |culture: &Culture| -> anyhow::Result<winget::Locale> {
+        let text = |id: &str| {
+            translations
+                .get(id, &culture.locale)
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("{id} is not translated to {}", culture.locale))
+        };
+        Ok(winget::Locale {
+            locale: culture.name.to_string(),
+            package_name: text("PackageName")?,
+            short_description: text("PackageDescription")?,
+        })
+    };
+
+    let mut installer_entries = vec![];
+    for (culture, path) in &installers {
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        installer_entries.push(winget::Installer {
+            architecture: arch.wix_arch().to_string(),
+            locale: culture.name.to_string(),
+            url: metadata
+                .download_url(&file_name)
+                .ok_or_else(|| anyhow::anyhow!("workspace.package.repository is missing"))?,
+            sha256: portable::sha256_hex(&fs::read(path)?),
+        });
+    }
+
+    let (default_culture, other_cultures) = cultures
+        .split_first()
+        .ok_or_else(|| anyhow::anyhow!("No installer culture"))?;
+    let package = winget::Package {
+        publisher: metadata
+            .publisher()
+            .ok_or_else(|| anyhow::anyhow!("workspace.package.authors is empty"))?
+            .to_string(),
+        name: APP_NAME.to_string(),
+        version: metadata.version.clone(),
+        license: metadata.license.clone(),
+        publisher_url: metadata.publisher_url(),
+        package_url: metadata.homepage().map(str::to_string),
+        tags: metadata
+            .keywords
+            .iter()
+            .map(|keyword| keyword.to_lowercase())
+            .collect(),
+        upgrade_code: upgrade_code(),
+        default_locale: locale(default_culture)?,
+        locales: other_cultures
+            .iter()
+            .map(locale)
+            .collect::<anyhow::Result<_>>()?,
+        installers: installer_entries,
+    };
+
+    let winget_dir = layout.out_dir.join("winget").join(package.directory());
+    fs::create_dir_all(&winget_dir)?;
+    for (file_name, content) in package.manifests() {
+        write_release_file(&winget_dir.join(file_name), &content)?;
+    }
+    Ok(())
+}
+
+fn write_release_file(path: &Path, content: &str) -> anyhow::Result<()> {
+    fs::write(path, content).with_context(|| format!("Unable to write {}", path.display()))?;
+    println!("Generated {}", path.to_string_lossy());
+    Ok(())
+}
+
+fn upgrade_code() -> String {
+    uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_X500, APP_NAME.as_bytes()).to_string()
+}
+
+fn portable_archive(cli: &Cli, layout: &Layout) -> PortableArchive {
+    PortableArchive::new(
+        &format!("{}-{}", APP_NAME, env!("CARGO_PKG_VERSION")),
+        &layout.exe_file(cli),
+        &layout.workspace_dir.join("LICENSE"),
+        &default_config_file(layout),
+    )
+}
+
+/// 安装包与免安装版本共用的默认配置
+fn default_config_file(layout: &Layout) -> PathBuf {
+    layout
+        .workspace_dir
+        .join("nexus")
+        .join("config.default.toml")
+}
+
+fn portable_file_name(cli: &Cli) -> String {
+    match cli.arch {
+        Some(arch) => format!(
+            "{}-{}-portable-{}.zip",
+            APP_NAME,
+            env!("CARGO_PKG_VERSION"),
+            arch.wix_arch()
+        ),
+        None => format!("{}-{}-portable.zip", APP_NAME, env!("CARGO_PKG_VERSION")),
+    }
+}
+
+fn path_string(path: &Path) -> anyhow::Result<String> {
+    path.to_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Path is not valid UTF-8: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_one_wxl_per_app_locale() {
+        let workspace_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let wix_dir = std::env::temp_dir().join(format!("wix-package-{}", std::process::id()));
+        fs::create_dir_all(&wix_dir).unwrap();
+        for entry in fs::read_dir(workspace_dir.join("wix-package").join("wix")).unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext == "wxs" || ext == "toml")
+            {
+                fs::copy(&path, wix_dir.join(path.file_name().unwrap())).unwrap();
+            }
+        }
+
+        let cultures = generate_localizations(workspace_dir, &wix_dir).unwrap();
+        let locales = available_locales(&workspace_dir.join("nexus").join("Cargo.toml")).unwrap();
+        assert_eq!(
+            cultures
+                .iter()
+                .map(|culture| culture.locale.clone())
+                .collect::<Vec<_>>(),
+            locales
+        );
+
+        let mut wxls = fs::read_dir(&wix_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wxl"))
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        wxls.sort();
+        assert_eq!(wxls, ["en-US.wxl", "zh-CN.wxl", "zh-TW.wxl"]);
+        fs::remove_dir_all(&wix_dir).unwrap();
+    }
+
+    #[test]
+    fn names_the_release_files() {
+        let version = env!("CARGO_PKG_VERSION");
+        assert_eq!(
+            sbom_file_name(),
+            format!("LitAudioSinkNexus-{version}.cdx.json")
+        );
+    }
 }
@@ -0,0 +1,79 @@
+use anyhow::Context;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// An external tool invocation of the build
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub program: String,
+    pub args: Vec<String>,
+    pub current_dir: PathBuf,
+}
+
+impl Step {
+    pub fn new<I, S>(program: &str, args: I, current_dir: &Path) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            program: program.to_string(),
+            args: args.into_iter().map(Into::into).collect(),
+            current_dir: current_dir.to_path_buf(),
+        }
+    }
+
+    pub fn run(&self) -> anyhow::Result<()> {
+        println!("Running: {self}");
+        let status = Command::new(&self.program)
+            .args(&self.args)
+            .current_dir(&self.current_dir)
+            .status()
+            .with_context(|| format!("Failed to start {}", self.program))?;
+
+        if !status.success() {
+            anyhow::bail!("`{self}` failed with {status}");
+        }
+        Ok(())
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(cd {}) {}", self.current_dir.display(), self.program)?;
+        for arg in &self.args {
+            if arg.contains(' ') {
+                write!(f, " \"{arg}\"")?;
+            } else {
+                write!(f, " {arg}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub steps: Vec<Step>,
+}
+
+impl Plan {
+    pub fn run(&self) -> anyhow::Result<()> {
+        for step in &self.steps {
+            step.run()?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{}. {}", index + 1, step)?;
+        }
+        Ok(())
+    }
+}
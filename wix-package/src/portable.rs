@@ -0,0 +1,237 @@
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{Seek, Write},
+    path::{Path, PathBuf},
+};
+use zip::{DateTime, ZipWriter, write::SimpleFileOptions};
+
+/// Must match `PORTABLE_MARKER` of nexus
+pub const PORTABLE_MARKER: &str = "portable.marker";
+pub const MANIFEST: &str = "SHA256SUMS";
+
+const MARKER_CONTENT: &str =
+    "LitAudioSinkNexus keeps config.toml and logs/ in this directory while this file exists.\r\n";
+
+/// A file of the portable archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    File(PathBuf),
+    Content(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub source: Source,
+}
+
+/// Files of the portable distribution, all inside the `root` directory of the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortableArchive {
+    pub root: String,
+    pub entries: Vec<Entry>,
+}
+
+impl PortableArchive {
+    pub fn new(root: &str, exe: &Path, license: &Path, default_config: &Path) -> Self {
+        let entries = vec![
+            Entry {
+                name: "nexus.exe".to_string(),
+                source: Source::File(exe.to_path_buf()),
+            },
+            Entry {
+                name: "LICENSE".to_string(),
+                source: Source::File(license.to_path_buf()),
+            },
+            Entry {
+                name: "config.toml".to_string(),
+                source: Source::File(default_config.to_path_buf()),
+            },
+            Entry {
+                name: PORTABLE_MARKER.to_string(),
+                source: Source::Content(MARKER_CONTENT.as_bytes().to_vec()),
+            },
+        ];
+
+        Self {
+            root: root.to_string(),
+            entries,
+        }
+    }
+
+    /// Paths inside the archive, the manifest is last
+    pub fn layout(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .chain([MANIFEST])
+            .map(|name| format!("{}/{}", self.root, name))
+            .collect()
+    }
+
+    /// Write the archive, the result only depends on the content of the files
+    pub fn write<W: Write + Seek>(&self, writer: W) -> anyhow::Result<()> {
+        let mut contents = vec![];
+        for entry in &self.entries {
+            let content = match &entry.source {
+                Source::File(path) => {
+                    fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?
+                }
+                Source::Content(content) => content.clone(),
+            };
+            contents.push((entry.name.as_str(), content));
+        }
+        let manifest = sha256_manifest(&contents);
+
+        let options = SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(DateTime::default())
+            .unix_permissions(0o644);
+
+        let mut zip = ZipWriter::new(writer);
+        zip.add_directory(format!("{}/", self.root), options)?;
+        for (name, content) in contents
+            .iter()
+            .map(|(name, content)| (*name, content.as_slice()))
+            .chain([(MANIFEST, manifest.as_bytes())])
+        {
+            zip.start_file(format!("{}/{}", self.root, name), options)?;
+            zip.write_all(content)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    pub fn write_file(&self, path: &Path) -> anyhow::Result<()> {
+        let file = fs::File::create(path)
+            .with_context(|| format!("Unable to create {}", path.display()))?;
+        self.write(file)
+    }
+}
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            write!(hex, "{byte:02x}").unwrap();
+            hex
+        })
+}
+
+/// `sha256sum` compatible listing
+pub fn sha256_manifest(files: &[(&str, Vec<u8>)]) -> String {
+    files
+        .iter()
+        .map(|(name, content)| format!("{}  {}\n", sha256_hex(content), name))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::BTreeSet,
+        io::{Cursor, Read},
+    };
+    use zip::ZipArchive;
+
+    /// 可执行文件、许可证与配置文件，内容为文件名
+    fn archive(dir: &Path) -> PortableArchive {
+        fs::create_dir_all(dir).unwrap();
+        for name in ["nexus.exe", "LICENSE", "config.default.toml"] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        PortableArchive::new(
+            "Nexus-1.0.0",
+            &dir.join("nexus.exe"),
+            &dir.join("LICENSE"),
+            &dir.join("config.default.toml"),
+        )
+    }
+
+    fn write(archive: &PortableArchive) -> Vec<u8> {
+        let mut cursor = Cursor::new(vec![]);
+        archive.write(&mut cursor).unwrap();
+        cursor.into_inner()
+    }
+
+    fn read(zip: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str) -> String {
+        let mut content = String::new();
+        zip.by_name(name)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn writes_the_layout_with_a_manifest() {
+        let dir = std::env::temp_dir().join(format!("portable-{}", std::process::id()));
+        let archive = archive(&dir);
+        assert_eq!(
+            archive.layout(),
+            [
+                "Nexus-1.0.0/nexus.exe",
+                "Nexus-1.0.0/LICENSE",
+                "Nexus-1.0.0/config.toml",
+                "Nexus-1.0.0/portable.marker",
+                "Nexus-1.0.0/SHA256SUMS",
+            ]
+        );
+
+        let bytes = write(&archive);
+        // 内容相同时输出逐字节相同
+        assert_eq!(bytes, write(&archive));
+
+        let mut zip = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let names = zip.file_names().map(str::to_string).collect::<Vec<_>>();
+        let mut expected = vec!["Nexus-1.0.0/".to_string()];
+        expected.extend(archive.layout());
+        assert_eq!(
+            names.iter().collect::<BTreeSet<_>>(),
+            expected.iter().collect()
+        );
+
+        assert_eq!(
+            read(&mut zip, "Nexus-1.0.0/config.toml"),
+            "config.default.toml"
+        );
+        assert_eq!(
+            read(&mut zip, "Nexus-1.0.0/portable.marker"),
+            MARKER_CONTENT
+        );
+        assert_eq!(
+            read(&mut zip, "Nexus-1.0.0/SHA256SUMS"),
+            format!(
+                "{}  nexus.exe\n{}  LICENSE\n{}  config.toml\n{}  portable.marker\n",
+                sha256_hex(b"nexus.exe"),
+                sha256_hex(b"LICENSE"),
+                sha256_hex(b"config.default.toml"),
+                sha256_hex(MARKER_CONTENT.as_bytes()),
+            )
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hashes_are_lowercase_sha256() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn missing_files_fail() {
+        let archive = PortableArchive::new(
+            "Nexus",
+            Path::new("/nonexistent/nexus.exe"),
+            Path::new("/nonexistent/LICENSE"),
+            Path::new("/nonexistent/config.toml"),
+        );
+        assert!(archive.write(Cursor::new(vec![])).is_err());
+    }
+}
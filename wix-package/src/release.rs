@@ -0,0 +1,106 @@
+use crate::portable::sha256_manifest;
+use anyhow::Context;
+use std::{fs, path::Path};
+
+/// `[workspace.package]` of the workspace manifest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceMetadata {
+    pub version: String,
+    pub authors: Vec<String>,
+    pub license: String,
+    pub repository: Option<String>,
+    pub description: String,
+    pub keywords: Vec<String>,
+}
+
+impl WorkspaceMetadata {
+    pub fn read(cargo_toml: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(cargo_toml)
+            .with_context(|| format!("Unable to read {}", cargo_toml.display()))?;
+        Self::parse(&content)
+    }
+
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let manifest = content.parse::<toml::Table>()?;
+        let package = manifest
+            .get("workspace")
+            .and_then(|workspace| workspace.get("package"))
+            .ok_or_else(|| anyhow::anyhow!("No [workspace.package] in the manifest"))?;
+
+        let string = |key: &str| {
+            package
+                .get(key)
+                .and_then(|value| value.as_str())
+                .map(str::to_string)
+        };
+        let strings = |key: &str| -> Vec<String> {
+            package
+                .get(key)
+                .and_then(|value| value.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|value| value.as_str().map(str::to_string))
+                .collect()
+        };
+        let required = |key: &str| {
+            string(key).ok_or_else(|| anyhow::anyhow!("workspace.package.{key} is missing"))
+        };
+
+        Ok(Self {
+            version: required("version")?,
+            authors: strings("authors"),
+            license: required("license")?,
+            repository: string("repository"),
+            description: required("description")?,
+            keywords: strings("keywords"),
+        })
+    }
+
+    /// Name of the first author without the `<...>` part
+    pub fn publisher(&self) -> Option<&str> {
+        let author = self.authors.first()?;
+        Some(author.split('<').next().unwrap_or(author).trim())
+    }
+
+    /// `https://` url of the `<...>` part of the first author
+    pub fn publisher_url(&self) -> Option<String> {
+        let author = self.authors.first()?;
+        let (_, url) = author.split_once('<')?;
+        let url = url.trim_end_matches('>').trim();
+        match url.contains("://") {
+            true => Some(url.to_string()),
+            false => Some(format!("https://{url}")),
+        }
+    }
+
+    /// Repository url without the `.git` suffix
+    pub fn homepage(&self) -> Option<&str> {
+        let repository = self.repository.as_deref()?;
+        Some(repository.strip_suffix(".git").unwrap_or(repository))
+    }
+
+    pub fn download_url(&self, file_name: &str) -> Option<String> {
+        Some(format!(
+            "{}/releases/download/{}/{}",
+            self.homepage()?,
+            self.version,
+            file_name
+        ))
+    }
+}
+
+/// `sha256sum` listing of the release files, by file name
+pub fn checksums(files: &[&Path]) -> anyhow::Result<String> {
+    let mut contents = vec![];
+    for path in files {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Invalid release file {}", path.display()))?;
+        let content =
+            fs::read(path).with_context(|| format!("Unable to read {}", path.display()))?;
+        contents.push((name, content));
+    }
+    contents.sort_by(|a, b| a.0.cmp(b.0));
+    Ok(sha256_manifest(&contents))
+}
@@ -0,0 +1,225 @@
+use anyhow::Context;
+use serde_json::{Value, json};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    process::Command,
+};
+
+/// A package of the `cargo metadata` output
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Package {
+    name: String,
+    version: String,
+    license: Option<String>,
+}
+
+impl Package {
+    fn purl(&self) -> String {
+        format!("pkg:cargo/{}@{}", self.name, self.version)
+    }
+
+    fn component(&self, kind: &str) -> Value {
+        let mut component = json!({
+            "type": kind,
+            "bom-ref": self.purl(),
+            "name": self.name,
+            "version": self.version,
+            "purl": self.purl(),
+        });
+        if let Some(license) = &self.license {
+            component["licenses"] = json!([{ "expression": license }]);
+        }
+        component
+    }
+}
+
+/// CycloneDX 1.5 BOM of `package` and the dependencies it links
+///
+/// `metadata` is the output of `cargo metadata --format-version 1`, build and dev dependencies
+/// are left out. The BOM has no timestamp and a serial number derived from the package, so the
+/// same dependency graph always gives the same file.
+pub fn cyclonedx(metadata: &Value, package: &str) -> anyhow::Result<Value> {
+    let mut packages = BTreeMap::new();
+    for value in array(metadata, "packages")? {
+        let id = string(value, "id")?;
+        packages.insert(
+            id.to_string(),
+            Package {
+                name: string(value, "name")?.to_string(),
+                version: string(value, "version")?.to_string(),
+                license: value["license"].as_str().map(str::to_string),
+            },
+        );
+    }
+
+    let root_id = packages
+        .iter()
+        .find(|(_, p)| p.name == package)
+        .map(|(id, _)| id.clone())
+        .ok_or_else(|| anyhow::anyhow!("Package {package} is not in the cargo metadata"))?;
+
+    let mut graph = BTreeMap::new();
+    for node in array(&metadata["resolve"], "nodes")? {
+        let mut deps = BTreeSet::new();
+        for dep in node["deps"].as_array().into_iter().flatten() {
+            let normal = dep["dep_kinds"]
+                .as_array()
+                .is_some_and(|kinds| kinds.iter().any(|kind| kind["kind"].is_null()));
+            if normal {
+                deps.insert(string(dep, "pkg")?.to_string());
+            }
+        }
+        graph.insert(string(node, "id")?.to_string(), deps);
+    }
+
+    // 只保留根包可达的依赖
+    let mut linked = BTreeSet::new();
+    let mut pending = vec![root_id.clone()];
+    while let Some(id) = pending.pop() {
+        if linked.insert(id.clone()) {
+            pending.extend(graph.get(&id).into_iter().flatten().cloned());
+        }
+    }
+
+    let lookup = |id: &str| {
+        packages
+            .get(id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown package id {id}"))
+    };
+    let root = lookup(&root_id)?;
+
+    let mut components = BTreeMap::new();
+    let mut dependencies = BTreeMap::new();
+    for id in &linked {
+        let package = lookup(id)?;
+        if id != &root_id {
+            components.insert(package.purl(), package.component("library"));
+        }
+        let depends_on = graph
+            .get(id)
+            .into_iter()
+            .flatten()
+            .map(|dep| lookup(dep).map(Package::purl))
+            .collect::<anyhow::Result<BTreeSet<_>>>()?;
+        dependencies.insert(
+            package.purl(),
+            json!({ "ref": package.purl(), "dependsOn": depends_on }),
+        );
+    }
+
+    let serial = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, root.purl().as_bytes());
+    Ok(json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": format!("urn:uuid:{serial}"),
+        "version": 1,
+        "metadata": {
+            "component": root.component("application"),
+        },
+        "components": components.into_values().collect::<Vec<_>>(),
+        "dependencies": dependencies.into_values().collect::<Vec<_>>(),
+    }))
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> anyhow::Result<&'a Vec<Value>> {
+    value[key]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("cargo metadata has no {key} array"))
+}
+
+fn string<'a>(value: &'a Value, key: &str) -> anyhow::Result<&'a str> {
+    value[key]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("cargo metadata entry has no {key}"))
+}
+
+/// Run `cargo metadata` for the dependency graph of `target`
+pub fn cargo_metadata(workspace_dir: &Path, target: &str) -> anyhow::Result<Value> {
+    let output = Command::new("cargo")
+        .args([
+            "metadata",
+            "--format-version",
+            "1",
+            "--filter-platform",
+            target,
+        ])
+        .current_dir(workspace_dir)
+        .output()
+        .context("Failed to start cargo metadata")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "cargo metadata failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Value {
+        let normal = json!([{ "kind": null, "target": null }]);
+        let dev = json!([{ "kind": "dev", "target": null }]);
+        json!({
+            "packages": [
+                { "id": "app", "name": "app", "version": "1.0.0", "license": "MIT" },
+                { "id": "lib", "name": "lib", "version": "0.2.0", "license": "Apache-2.0" },
+                { "id": "dev", "name": "dev", "version": "0.1.0" },
+                { "id": "other", "name": "other", "version": "3.0.0" },
+            ],
+            "resolve": {
+                "nodes": [
+                    { "id": "app", "deps": [
+                        { "pkg": "lib", "dep_kinds": normal },
+                        { "pkg": "dev", "dep_kinds": dev },
+                    ] },
+                    { "id": "lib", "deps": [] },
+                    { "id": "dev", "deps": [] },
+                    { "id": "other", "deps": [] },
+                ]
+            }
+        })
+    }
+
+    #[test]
+    fn lists_only_linked_dependencies() {
+        let bom = cyclonedx(&metadata(), "app").unwrap();
+        assert_eq!(bom["metadata"]["component"]["purl"], "pkg:cargo/app@1.0.0");
+        assert_eq!(
+            bom["components"],
+            json!([{
+                "type": "library",
+                "bom-ref": "pkg:cargo/lib@0.2.0",
+                "name": "lib",
+                "version": "0.2.0",
+                "purl": "pkg:cargo/lib@0.2.0",
+                "licenses": [{ "expression": "Apache-2.0" }],
+            }])
+        );
+        assert_eq!(
+            bom["dependencies"],
+            json!([
+                { "ref": "pkg:cargo/app@1.0.0", "dependsOn": ["pkg:cargo/lib@0.2.0"] },
+                { "ref": "pkg:cargo/lib@0.2.0", "dependsOn": [] },
+            ])
+        );
+    }
+
+    #[test]
+    fn output_is_reproducible() {
+        let bom = cyclonedx(&metadata(), "app").unwrap();
+        assert_eq!(bom, cyclonedx(&metadata(), "app").unwrap());
+        assert!(
+            bom["serialNumber"]
+                .as_str()
+                .unwrap()
+                .starts_with("urn:uuid:")
+        );
+        assert!(cyclonedx(&metadata(), "missing").is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt::Write;
+
+pub const MANIFEST_VERSION: &str = "1.6.0";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Installer {
+    pub architecture: String,
+    pub locale: String,
+    pub url: String,
+    pub sha256: String,
+}
+
+/// Localized texts of the package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+    pub locale: String,
+    pub package_name: String,
+    pub short_description: String,
+}
+
+/// Everything needed for a winget manifest set of one version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub publisher: String,
+    pub name: String,
+    pub version: String,
+    pub license: String,
+    pub publisher_url: Option<String>,
+    pub package_url: Option<String>,
+    pub tags: Vec<String>,
+    pub upgrade_code: String,
+    pub default_locale: Locale,
+    pub locales: Vec<Locale>,
+    pub installers: Vec<Installer>,
+}
+
+impl Package {
+    pub fn identifier(&self) -> String {
+        format!("{}.{}", self.publisher, self.name)
+    }
+
+    /// Directory of the manifests in the winget-pkgs repository
+    pub fn directory(&self) -> String {
+        let first = self.publisher.chars().next().unwrap_or('_');
+        format!(
+            "manifests/{}/{}/{}/{}",
+            first.to_ascii_lowercase(),
+            self.publisher,
+            self.name,
+            self.version
+        )
+    }
+
+    /// `(file name, content)` of the version, installer and locale manifests
+    pub fn manifests(&self) -> Vec<(String, String)> {
+        let identifier = self.identifier();
+        let mut manifests = vec![
+            (format!("{identifier}.yaml"), self.version_manifest()),
+            (
+                format!("{identifier}.installer.yaml"),
+                self.installer_manifest(),
+            ),
+            (
+                format!("{identifier}.locale.{}.yaml", self.default_locale.locale),
+                self.locale_manifest(&self.default_locale, true),
+            ),
+        ];
+        for locale in &self.locales {
+            manifests.push((
+                format!("{identifier}.locale.{}.yaml", locale.locale),
+                self.locale_manifest(locale, false),
+            ));
+        }
+        manifests
+    }
+
+    fn header(&self, manifest_type: &str) -> String {
+        let mut yaml = format!(
+            "# yaml-language-server: $schema=https://aka.ms/winget-manifest.{manifest_type}.{MANIFEST_VERSION}.schema.json\n\n"
+        );
+        field(&mut yaml, "PackageIdentifier", &self.identifier());
+        field(&mut yaml, "PackageVersion", &self.version);
+        yaml
+    }
+
+    fn footer(yaml: &mut String, manifest_type: &str) {
+        field(yaml, "ManifestType", manifest_type);
+        field(yaml, "ManifestVersion", MANIFEST_VERSION);
+    }
+
+    fn version_manifest(&self) -> String {
+        let mut yaml = self.header("version");
+        field(&mut yaml, "DefaultLocale", &self.default_locale.locale);
+        Self::footer(&mut yaml, "version");
+        yaml
+    }
+
+    fn installer_manifest(&self) -> String {
+        let mut yaml = self.header("installer");
+        field(&mut yaml, "InstallerType", "wix");
+        field(&mut yaml, "Scope", "machine");
+        field(&mut yaml, "UpgradeBehavior", "install");
+        yaml.push_str("AppsAndFeaturesEntries:\n");
+        writeln!(
+            yaml,
+            "- UpgradeCode: {}",
+            quote(&format!("{{{}}}", self.upgrade_code.to_uppercase()))
+        )
+        .unwrap();
+        yaml.push_str("Installers:\n");
+        for installer in &self.installers {
+            writeln!(yaml, "- Architecture: {}", quote(&installer.architecture)).unwrap();
+            writeln!(yaml, "  InstallerLocale: {}", quote(&installer.locale)).unwrap();
+            writeln!(yaml, "  InstallerUrl: {}", quote(&installer.url)).unwrap();
+            writeln!(
+                yaml,
+                "  InstallerSha256: {}",
+                quote(&installer.sha256.to_uppercase())
+            )
+            .unwrap();
+        }
+        Self::footer(&mut yaml, "installer");
+        yaml
+    }
+
+    fn locale_manifest(&self, locale: &Locale, default: bool) -> String {
+        let manifest_type = if default { "defaultLocale" } else { "locale" };
+        let mut yaml = self.header(manifest_type);
+        field(&mut yaml, "PackageLocale", &locale.locale);
+        field(&mut yaml, "Publisher", &self.publisher);
+        if let Some(url) = &self.publisher_url {
+            field(&mut yaml, "PublisherUrl", url);
+        }
+        field(&mut yaml, "PackageName", &locale.package_name);
+        if let Some(url) = &self.package_url {
+            field(&mut yaml, "PackageUrl", url);
+        }
+        field(&mut yaml, "License", &self.license);
+        field(&mut yaml, "ShortDescription", &locale.short_description);
+        if default && !self.tags.is_empty() {
+            yaml.push_str("Tags:\n");
+            for tag in &self.tags {
+                writeln!(yaml, "- {}", quote(tag)).unwrap();
+            }
+        }
+        Self::footer(&mut yaml, manifest_type);
+        yaml
+    }
+}
+
+fn field(yaml: &mut String, key: &str, value: &str) {
+    writeln!(yaml, "{key}: {}", quote(value)).unwrap();
+}
+
+/// YAML single quoted scalar, the only escape is a doubled quote
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package() -> Package {
+        Package {
+            publisher: "Littrick".to_string(),
+            name: "LitAudioSinkNexus".to_string(),
+            version: "1.2.3".to_string(),
+            license: "MIT".to_string(),
+            publisher_url: None,
+            package_url: Some("https://github.com/littrick/lit-sink-nexus".to_string()),
+            tags: vec!["bluetooth".to_string()],
+            upgrade_code: "c9e9296f-5f11-5543-98f9-0d2bfb6a2865".to_string(),
+            default_locale: Locale {
+                locale: "en-US".to_string(),
+                package_name: "Lit Audio Sink Nexus".to_string(),
+                short_description: "Play audio from Bluetooth sources".to_string(),
+            },
+            locales: vec![Locale {
+                locale: "zh-CN".to_string(),
+                package_name: "Lit 音频播放中心".to_string(),
+                short_description: "播放蓝牙音频".to_string(),
+            }],
+            installers: vec![Installer {
+                architecture: "x64".to_string(),
+                locale: "en-US".to_string(),
+                url: "https://example.com/setup.msi".to_string(),
+                sha256: "ab12cd".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn names_the_manifests() {
+        let package = package();
+        assert_eq!(
+            package.directory(),
+            "manifests/l/Littrick/LitAudioSinkNexus/1.2.3"
+        );
+        assert_eq!(
+            package
+                .manifests()
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>(),
+            [
+                "Littrick.LitAudioSinkNexus.yaml",
+                "Littrick.LitAudioSinkNexus.installer.yaml",
+                "Littrick.LitAudioSinkNexus.locale.en-US.yaml",
+                "Littrick.LitAudioSinkNexus.locale.zh-CN.yaml",
+            ]
+        );
+    }
+
+    #[test]
+    fn installer_manifest_uses_uppercase_hashes() {
+        let manifests = package().manifests();
+        let installer = &manifests[1].1;
+        assert!(installer.contains("  InstallerSha256: 'AB12CD'\n"));
+        assert!(installer.contains("- UpgradeCode: '{C9E9296F-5F11-5543-98F9-0D2BFB6A2865}'\n"));
+        assert!(installer.ends_with("ManifestType: 'installer'\nManifestVersion: '1.6.0'\n"));
+    }
+
+    #[test]
+    fn only_the_default_locale_has_tags() {
+        let manifests = package().manifests();
+        assert!(manifests[2].1.contains("ManifestType: 'defaultLocale'"));
+        assert!(manifests[2].1.contains("Tags:\n- 'bluetooth'\n"));
+        assert!(manifests[3].1.contains("ManifestType: 'locale'"));
+        assert!(!manifests[3].1.contains("Tags:"));
+        assert!(!manifests[3].1.contains("PublisherUrl"));
+    }
+
+    #[test]
+    fn quotes_yaml_scalars() {
+        assert_eq!(quote("it's"), "'it''s'");
+        assert_eq!(quote("a: b"), "'a: b'");
+    }
+}
@@ -0,0 +1,135 @@
+/// Render preprocessor definitions as a WiX include file
+///
+/// WiX takes the text of `<?define ?>` literally and only strips the surrounding quotes, so values
+/// are written as is. `&`, `<` and quotes are fine inside a processing instruction, only `?>` and
+/// line breaks cannot be represented.
+pub fn render_variable_wxi(variables: &[(&str, String)]) -> anyhow::Result<String> {
+    let mut defines = String::new();
+    for (name, value) in variables {
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            anyhow::bail!("Invalid preprocessor variable name {name:?}");
+        }
+        if value.contains("?>") || value.contains(['\r', '\n']) {
+            anyhow::bail!("Value of {name} cannot be written to var.wxi: {value:?}");
+        }
+        defines.push_str(&format!("<?define {}=\"{}\" ?>\n", name, value));
+    }
+
+    let content = format!(
+        r#"<Include xmlns="http://wixtoolset.org/schemas/v4/wxs">{}{}</Include>"#,
+        "\n", defines
+    );
+    validate_variable_wxi(&content, variables)?;
+    Ok(content)
+}
+
+/// Parse the include file back and check it is well formed and every value round-trips
+pub fn validate_variable_wxi(content: &str, variables: &[(&str, String)]) -> anyhow::Result<()> {
+    let body = content
+        .strip_prefix(r#"<Include xmlns="http://wixtoolset.org/schemas/v4/wxs">"#)
+        .and_then(|body| body.strip_suffix("</Include>"))
+        .ok_or_else(|| anyhow::anyhow!("var.wxi is not a single <Include> element"))?;
+
+    let mut parsed = vec![];
+    let mut rest = body.trim_start();
+    while !rest.is_empty() {
+        let pi = rest
+            .strip_prefix("<?define ")
+            .ok_or_else(|| anyhow::anyhow!("Unexpected content in var.wxi: {rest:?}"))?;
+        let (pi, tail) = pi
+            .split_once("?>")
+            .ok_or_else(|| anyhow::anyhow!("Unterminated processing instruction in var.wxi"))?;
+        let (name, value) = pi
+            .trim_end()
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid define {pi:?}"))?;
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .ok_or_else(|| anyhow::anyhow!("Unquoted value of {name}"))?;
+
+        parsed.push((name, value));
+        rest = tail.trim_start();
+    }
+
+    if parsed.len() != variables.len() {
+        anyhow::bail!(
+            "var.wxi defines {} variables, expected {}",
+            parsed.len(),
+            variables.len()
+        );
+    }
+    for ((name, value), (expected_name, expected_value)) in parsed.iter().zip(variables) {
+        if name != expected_name || value != expected_value {
+            anyhow::bail!(
+                "var.wxi defines {name}={value:?}, expected {expected_name}={expected_value:?}"
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Vec<(&'static str, String)> {
+        vec![
+            ("AppName", "LitAudioSinkNexus".to_string()),
+            ("Manufacturer", "littrick <github.com/littrick>".to_string()),
+            ("AppExe", r"C:\a & b\nexus.exe".to_string()),
+        ]
+    }
+
+    #[test]
+    fn renders_the_include_file() {
+        assert_eq!(
+            render_variable_wxi(&variables()).unwrap(),
+            r#"<Include xmlns="http://wixtoolset.org/schemas/v4/wxs">
+<?define AppName="LitAudioSinkNexus" ?>
+<?define Manufacturer="littrick <github.com/littrick>" ?>
+<?define AppExe="C:\a & b\nexus.exe" ?>
+</Include>"#
+        );
+        assert_eq!(
+            render_variable_wxi(&[]).unwrap(),
+            "<Include xmlns=\"http://wixtoolset.org/schemas/v4/wxs\">\n</Include>"
+        );
+    }
+
+    #[test]
+    fn rejects_values_which_cannot_be_written() {
+        assert!(render_variable_wxi(&[("App Name", String::new())]).is_err());
+        assert!(render_variable_wxi(&[("AppName", "a ?> b".to_string())]).is_err());
+        assert!(render_variable_wxi(&[("AppName", "a\nb".to_string())]).is_err());
+        assert!(render_variable_wxi(&[("AppName", "a\rb".to_string())]).is_err());
+        // 引号在处理指令中原样保留
+        render_variable_wxi(&[("AppName", r#"say "hi""#.to_string())]).unwrap();
+    }
+
+    #[test]
+    fn validation_detects_mismatches() {
+        let content = render_variable_wxi(&variables()).unwrap();
+        validate_variable_wxi(&content, &variables()).unwrap();
+
+        let mut changed = variables();
+        changed[2].1 = "other.exe".to_string();
+        assert!(validate_variable_wxi(&content, &changed).is_err());
+        assert!(validate_variable_wxi(&content, &variables()[..2]).is_err());
+        assert!(validate_variable_wxi(&content.replace("</Include>", ""), &variables()).is_err());
+        assert!(
+            validate_variable_wxi(
+                &content.replace("<?define AppName", "<?define AppName\n"),
+                &variables()
+            )
+            .is_err()
+        );
+        assert!(
+            validate_variable_wxi(
+                &content.replace(r#""LitAudioSinkNexus""#, "LitAudioSinkNexus"),
+                &variables()
+            )
+            .is_err()
+        );
+    }
+}